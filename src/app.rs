@@ -1,10 +1,15 @@
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 use zeroize::Zeroizing;
 
 use crate::config::Settings;
+use crate::models::connection::ConnectionProfile;
 use crate::models::tunnel::TunnelConfig;
 use crate::keys::storage::KeyStore;
+use crate::ssh::algorithms::NegotiatedAlgorithms;
+use crate::ssh::sftp::{SftpCommand, SftpEvent};
+use crate::ssh::tunnel::TunnelStatsSnapshot;
 use crate::storage::profiles::ProfileStore;
 
 /// Commands sent from GTK UI thread to Tokio SSH task
@@ -14,6 +19,16 @@ pub enum SshCommand {
     Resize { cols: u32, rows: u32 },
     StartTunnel(TunnelConfig),
     StopTunnel(Uuid),
+    /// Open another shell on this session's connection, for a duplicated tab
+    OpenShell {
+        event_tx: async_channel::Sender<SshEvent>,
+        cmd_rx: async_channel::Receiver<SshCommand>,
+    },
+    /// Open an SFTP channel on this session's connection
+    OpenSftp {
+        event_tx: async_channel::Sender<SftpEvent>,
+        cmd_rx: async_channel::Receiver<SftpCommand>,
+    },
     Disconnect,
 }
 
@@ -21,15 +36,93 @@ pub enum SshCommand {
 #[derive(Debug, Clone)]
 pub enum SshEvent {
     Connected,
+    AlgorithmsNegotiated(NegotiatedAlgorithms),
+    /// Which SSH agent authenticates or is forwarded, and where it lives
+    AgentInUse(String),
+    /// The session is being written to this log file
+    LogStarted(PathBuf),
     Data(Vec<u8>),
     TunnelEstablished(Uuid),
     TunnelFailed(Uuid, String),
-    Disconnected(Option<String>),
+    TunnelStopped(Uuid),
+    TunnelStats(Vec<(Uuid, TunnelStatsSnapshot)>),
+    Disconnected(DisconnectReason),
     Error(String),
-    HostKeyVerify {
-        key_type: String,
-        fingerprint: String,
-    },
+    HostKeyVerify(HostKeyPrompt),
+    AuthPrompt(AuthPrompt),
+}
+
+/// Why a terminal session ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The user disconnected or closed the tab
+    User,
+    /// The remote shell exited and the server closed the channel
+    RemoteClosed,
+    /// The connection broke or could not be established
+    Lost(String),
+    /// Keepalives or the inactivity timeout found the server unresponsive
+    TimedOut(String),
+    /// Authentication, host key or configuration errors that would fail
+    /// again on a retry
+    Failed(String),
+}
+
+impl DisconnectReason {
+    /// Whether reconnecting has a chance of working.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, DisconnectReason::Lost(_) | DisconnectReason::TimedOut(_))
+    }
+}
+
+/// How the user answered an unknown host key prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyDecision {
+    Reject,
+    AcceptOnce,
+    AcceptAndSave,
+}
+
+/// An unknown server key waiting for the user's decision.
+/// The handshake blocks until a decision is sent on `response_tx`.
+#[derive(Debug, Clone)]
+pub struct HostKeyPrompt {
+    pub host: String,
+    pub key_type: String,
+    pub fingerprint: String,
+    pub randomart: String,
+    /// known_hosts names a certificate authority for the host, which is not
+    /// used since host certificates are not supported
+    pub cert_authority: bool,
+    pub response_tx: async_channel::Sender<HostKeyDecision>,
+}
+
+/// One question of a keyboard-interactive round
+#[derive(Debug, Clone)]
+pub struct AuthPromptField {
+    pub prompt: String,
+    /// Whether the answer may be shown while typing
+    pub echo: bool,
+}
+
+/// A keyboard-interactive round sent by the server.
+/// Authentication blocks until the answers, one per field, are sent on
+/// `response_tx`; `None` cancels the attempt.
+#[derive(Debug, Clone)]
+pub struct AuthPrompt {
+    pub host: String,
+    pub name: String,
+    pub instructions: String,
+    pub fields: Vec<AuthPromptField>,
+    pub response_tx: async_channel::Sender<Option<Vec<Zeroizing<String>>>>,
+}
+
+/// The built-in agent needs the passphrase of a locked key.
+/// `None` on `response_tx` refuses the signature.
+#[derive(Debug, Clone)]
+pub struct KeyUnlockPrompt {
+    pub key_name: String,
+    pub response_tx: async_channel::Sender<Option<Zeroizing<String>>>,
 }
 
 /// Application-wide shared state
@@ -38,21 +131,61 @@ pub struct SharedState {
     pub settings: Arc<Mutex<Settings>>,
     pub profile_store: Arc<Mutex<ProfileStore>>,
     pub key_store: Arc<Mutex<KeyStore>>,
+    /// Passphrase requests from the built-in agent, answered by the main window
+    pub key_unlock_tx: async_channel::Sender<KeyUnlockPrompt>,
+    pub key_unlock_rx: async_channel::Receiver<KeyUnlockPrompt>,
 }
 
 impl SharedState {
     pub fn new() -> Self {
+        let (key_unlock_tx, key_unlock_rx) = async_channel::unbounded();
         Self {
             settings: Arc::new(Mutex::new(Settings::load())),
             profile_store: Arc::new(Mutex::new(ProfileStore::load())),
             key_store: Arc::new(Mutex::new(KeyStore::load())),
+            key_unlock_tx,
+            key_unlock_rx,
         }
     }
 }
 
 /// Holds authentication credentials for a connection attempt (not persisted)
-#[allow(dead_code)]
+#[derive(Clone, Default)]
 pub struct AuthCredentials {
     pub password: Option<Zeroizing<String>>,
-    pub private_key_path: Option<std::path::PathBuf>,
+    pub key_passphrase: Option<Zeroizing<String>>,
+    /// Keyring labels to save the password and passphrase under once the
+    /// server accepted them, set when the user ticked "Remember"
+    pub remember_password: Option<String>,
+    pub remember_passphrase: Option<String>,
+    /// The password came from the keyring and is forgotten if rejected
+    pub password_saved: bool,
+}
+
+/// One hop of a connection: the profile to authenticate with and its secrets.
+#[derive(Clone)]
+pub struct Hop {
+    pub profile: ConnectionProfile,
+    pub credentials: AuthCredentials,
+}
+
+/// Everything needed to open a connection: the jump hosts in the order they
+/// are traversed, followed by the destination.
+#[derive(Clone)]
+pub struct ConnectionChain {
+    pub jump_hosts: Vec<Hop>,
+    pub target: Hop,
+    /// Global settings at the time the connection was started
+    pub settings: Settings,
+}
+
+impl ConnectionChain {
+    pub fn profile(&self) -> &ConnectionProfile {
+        &self.target.profile
+    }
+
+    /// All hops in connection order, ending with the destination.
+    pub fn hops(&self) -> impl Iterator<Item = &Hop> {
+        self.jump_hosts.iter().chain(std::iter::once(&self.target))
+    }
 }
@@ -4,6 +4,7 @@ use std::path::PathBuf;
 use std::sync::OnceLock;
 
 use crate::error::AppError;
+use crate::models::connection::{AlgorithmPreferences, SessionLogConfig};
 
 static PROJECT_DIRS: OnceLock<ProjectDirs> = OnceLock::new();
 
@@ -42,6 +43,31 @@ pub fn keys_dir() -> PathBuf {
     data_dir().join("keys")
 }
 
+/// Saved passwords and passphrases when no Secret Service is running
+pub fn secrets_path() -> PathBuf {
+    data_dir().join("secrets.json")
+}
+
+/// Default directory for session logs
+pub fn default_log_dir() -> PathBuf {
+    data_dir().join("logs")
+}
+
+/// Where terminal recordings are saved
+pub fn recordings_dir() -> PathBuf {
+    data_dir().join("recordings")
+}
+
+/// Socket of the built-in SSH agent. Lives in the runtime directory when
+/// there is one so it is cleaned up on logout.
+pub fn agent_socket_path() -> PathBuf {
+    project_dirs()
+        .runtime_dir()
+        .map(|dir| dir.to_path_buf())
+        .unwrap_or_else(config_dir)
+        .join("agent.sock")
+}
+
 pub fn ensure_directories() -> Result<(), AppError> {
     std::fs::create_dir_all(config_dir())?;
     std::fs::create_dir_all(keys_dir())?;
@@ -49,11 +75,31 @@ pub fn ensure_directories() -> Result<(), AppError> {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub font_family: String,
     pub font_size: u32,
     pub scrollback_lines: i64,
     pub default_terminal_type: String,
+    /// Serve the key manager's keys over an ssh-agent socket
+    pub agent_enabled: bool,
+    /// How long a key stays unlocked in the agent, 0 = until the app quits
+    pub agent_key_lifetime_minutes: u32,
+    /// Seconds between keepalive requests on idle connections, 0 = off
+    pub keepalive_interval_secs: u32,
+    /// Unanswered keepalives before a connection is considered dead
+    pub keepalive_max: u32,
+    /// Close connections that receive nothing for this many seconds, 0 = never
+    pub inactivity_timeout_secs: u32,
+    /// Open terminals, SFTP browsers and tunnels of a profile on one connection
+    pub share_connections: bool,
+    /// Seconds a shared connection stays open after its last tab closed
+    pub connection_idle_secs: u32,
+    /// Algorithms offered to every host unless its profile overrides them
+    pub algorithms: AlgorithmPreferences,
+    pub session_log: SessionLogConfig,
+    /// Where session logs are written
+    pub log_directory: PathBuf,
 }
 
 impl Default for Settings {
@@ -63,11 +109,28 @@ impl Default for Settings {
             font_size: 12,
             scrollback_lines: 10000,
             default_terminal_type: "xterm-256color".into(),
+            agent_enabled: false,
+            agent_key_lifetime_minutes: 15,
+            keepalive_interval_secs: 30,
+            keepalive_max: 3,
+            inactivity_timeout_secs: 0,
+            share_connections: true,
+            connection_idle_secs: 60,
+            algorithms: AlgorithmPreferences::default(),
+            session_log: SessionLogConfig::default(),
+            log_directory: default_log_dir(),
         }
     }
 }
 
 impl Settings {
+    pub fn agent_key_lifetime(&self) -> Option<std::time::Duration> {
+        match self.agent_key_lifetime_minutes {
+            0 => None,
+            minutes => Some(std::time::Duration::from_secs(u64::from(minutes) * 60)),
+        }
+    }
+
     pub fn load() -> Self {
         let path = settings_path();
         if path.exists() {
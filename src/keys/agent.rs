@@ -0,0 +1,407 @@
+use sha2::Sha256;
+use signature::{SignatureEncoding, Signer};
+use ssh_key::{Algorithm, HashAlg, PrivateKey, PublicKey, Signature};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::app::{KeyUnlockPrompt, SharedState};
+use crate::config;
+use crate::error::AppError;
+use crate::keys::certificate;
+use crate::keys::storage::KeyStore;
+use crate::storage::paths;
+use crate::storage::secrets::{self, SecretKind};
+
+// Message numbers from draft-miller-ssh-agent
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+const SSH_AGENT_RSA_SHA2_256: u32 = 0x02;
+const SSH_AGENT_RSA_SHA2_512: u32 = 0x04;
+
+/// Upper bound for a single request, the same limit OpenSSH's agent uses
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+/// How often expired unlocked keys are dropped from memory
+const PURGE_INTERVAL: Duration = Duration::from_secs(30);
+
+static AGENT: Mutex<Option<KeyAgent>> = Mutex::new(None);
+
+/// A decrypted key kept in memory after the user entered its passphrase.
+struct UnlockedKey {
+    key: PrivateKey,
+    unlocked_at: Instant,
+}
+
+/// State shared by all client connections of the agent.
+struct AgentShared {
+    key_store: Arc<Mutex<KeyStore>>,
+    unlocked: Mutex<HashMap<Uuid, UnlockedKey>>,
+    /// `None` keeps keys unlocked until the agent stops
+    lifetime: Mutex<Option<Duration>>,
+    unlock_tx: async_channel::Sender<KeyUnlockPrompt>,
+    /// Serializes passphrase prompts so parallel requests ask only once
+    unlock_lock: tokio::sync::Mutex<()>,
+}
+
+/// In-process ssh-agent serving the keys of the key manager.
+struct KeyAgent {
+    socket_path: PathBuf,
+    shared: Arc<AgentShared>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for KeyAgent {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+/// Start, stop or reconfigure the built-in agent to match the settings.
+pub fn apply_settings(state: &SharedState) {
+    let (enabled, lifetime) = {
+        let settings = state.settings.lock().unwrap();
+        (settings.agent_enabled, settings.agent_key_lifetime())
+    };
+
+    let mut agent = AGENT.lock().unwrap();
+    if !enabled {
+        if agent.take().is_some() {
+            log::info!("Built-in SSH agent stopped");
+        }
+        return;
+    }
+
+    if let Some(running) = agent.as_ref() {
+        *running.shared.lifetime.lock().unwrap() = lifetime;
+        return;
+    }
+
+    let shared = Arc::new(AgentShared {
+        key_store: state.key_store.clone(),
+        unlocked: Mutex::new(HashMap::new()),
+        lifetime: Mutex::new(lifetime),
+        unlock_tx: state.key_unlock_tx.clone(),
+        unlock_lock: tokio::sync::Mutex::new(()),
+    });
+    let socket_path = config::agent_socket_path();
+    match spawn_agent(&socket_path, shared.clone()) {
+        Ok(task) => {
+            log::info!("Built-in SSH agent listening on {}", socket_path.display());
+            *agent = Some(KeyAgent {
+                socket_path,
+                shared,
+                task,
+            });
+        }
+        Err(e) => log::error!("Failed to start the built-in SSH agent: {e}"),
+    }
+}
+
+/// Stop the agent and remove its socket.
+pub fn stop() {
+    AGENT.lock().unwrap().take();
+}
+
+/// Socket of the built-in agent if it is running.
+pub fn socket_path() -> Option<PathBuf> {
+    AGENT
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|agent| agent.socket_path.clone())
+}
+
+fn spawn_agent(socket_path: &Path, shared: Arc<AgentShared>) -> Result<JoinHandle<()>, AppError> {
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent)?;
+        std::fs::set_permissions(parent, std::fs::Permissions::from_mode(0o700))?;
+    }
+    // A socket left behind by a previous run would make bind fail
+    if socket_path.exists() {
+        std::fs::remove_file(socket_path)?;
+    }
+
+    let rt = crate::runtime();
+    let _guard = rt.enter();
+    let listener = UnixListener::bind(socket_path)?;
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))?;
+
+    Ok(rt.spawn(async move {
+        let mut purge_interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve_client(stream, shared.clone()));
+                    }
+                    Err(e) => {
+                        log::error!("SSH agent accept error: {e}");
+                        break;
+                    }
+                },
+                _ = purge_interval.tick() => shared.purge_expired(),
+            }
+        }
+    }))
+}
+
+async fn serve_client(mut stream: UnixStream, shared: Arc<AgentShared>) {
+    loop {
+        let len = match stream.read_u32().await {
+            Ok(len) => len as usize,
+            Err(_) => return,
+        };
+        if len == 0 || len > MAX_MESSAGE_LEN {
+            log::warn!("SSH agent client sent a message of {len} bytes");
+            return;
+        }
+        let mut request = vec![0u8; len];
+        if stream.read_exact(&mut request).await.is_err() {
+            return;
+        }
+
+        let response = match shared.handle_message(&request).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::warn!("SSH agent request failed: {e}");
+                vec![SSH_AGENT_FAILURE]
+            }
+        };
+
+        let mut frame = Vec::with_capacity(response.len() + 4);
+        put_u32(&mut frame, response.len() as u32);
+        frame.extend_from_slice(&response);
+        if stream.write_all(&frame).await.is_err() {
+            return;
+        }
+    }
+}
+
+impl AgentShared {
+    async fn handle_message(&self, request: &[u8]) -> Result<Vec<u8>, AppError> {
+        let mut reader = Reader::new(request);
+        match reader.read_u8()? {
+            SSH_AGENTC_REQUEST_IDENTITIES => {
+                let identities = self.identities();
+                let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+                put_u32(&mut response, identities.len() as u32);
+                for (_, name, blob) in &identities {
+                    put_string(&mut response, blob);
+                    put_string(&mut response, name.as_bytes());
+                }
+                Ok(response)
+            }
+            SSH_AGENTC_SIGN_REQUEST => {
+                let blob = reader.read_string()?;
+                let data = reader.read_string()?;
+                let flags = reader.read_u32()?;
+
+                let (id, name, _) = self
+                    .identities()
+                    .into_iter()
+                    .find(|(_, _, identity)| identity.as_slice() == blob)
+                    .ok_or_else(|| AppError::SshKey("Unknown key in sign request".into()))?;
+
+                // Refuse what cannot be signed before asking for a passphrase
+                let rsa_hash = match Reader::new(blob).read_string()? {
+                    b"ssh-rsa" | b"ssh-rsa-cert-v01@openssh.com" => Some(rsa_hash(flags, &name)?),
+                    _ => None,
+                };
+
+                let key = self.private_key(id, &name).await?;
+                let signature = match rsa_hash {
+                    Some(HashAlg::Sha256) => sign_rsa_sha256(&key, data)?,
+                    _ => key.try_sign(data).map_err(|e| AppError::SshKey(e.to_string()))?,
+                };
+
+                let mut signature_blob = Vec::new();
+                put_string(&mut signature_blob, signature.algorithm().as_str().as_bytes());
+                put_string(&mut signature_blob, signature.as_bytes());
+
+                let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+                put_string(&mut response, &signature_blob);
+                Ok(response)
+            }
+            other => Err(AppError::SshKey(format!("Unsupported agent request {other}"))),
+        }
+    }
+
+    /// Public key blobs of every key in the key manager, with their names.
+    /// Keys with a certificate are offered a second time as the certificate.
+    fn identities(&self) -> Vec<(Uuid, String, Vec<u8>)> {
+        let keys = self.key_store.lock().unwrap().keys.clone();
+        let mut identities = Vec::new();
+        for meta in keys {
+            let public_key = match PublicKey::read_openssh_file(&paths::public_key_path(&meta.id)) {
+                Ok(key) => key,
+                Err(e) => {
+                    log::warn!("Skipping key {} in agent: {e}", meta.name);
+                    continue;
+                }
+            };
+            if let Ok(Some(cert)) = certificate::load_certificate(&meta.id) {
+                if let Ok(blob) = cert.to_bytes() {
+                    identities.push((meta.id, format!("{} (certificate)", meta.name), blob));
+                }
+            }
+            if let Ok(blob) = public_key.to_bytes() {
+                identities.push((meta.id, meta.name, blob));
+            }
+        }
+        identities
+    }
+
+    /// Load a private key, asking for its passphrase unless it is still unlocked.
+    async fn private_key(&self, id: Uuid, name: &str) -> Result<PrivateKey, AppError> {
+        let key = PrivateKey::read_openssh_file(&paths::private_key_path(&id))?;
+        if !key.is_encrypted() {
+            return Ok(key);
+        }
+
+        let _unlocking = self.unlock_lock.lock().await;
+        self.purge_expired();
+        if let Some(unlocked) = self.unlocked.lock().unwrap().get(&id) {
+            return Ok(unlocked.key.clone());
+        }
+
+        // A passphrase saved in the keyring unlocks without asking
+        if let Some(saved) = secrets::lookup(SecretKind::KeyPassphrase, id).await {
+            match key.decrypt(saved.as_bytes()) {
+                Ok(decrypted) => {
+                    self.remember_unlocked(id, &decrypted);
+                    return Ok(decrypted);
+                }
+                Err(_) => log::info!("Saved passphrase of \"{name}\" no longer works"),
+            }
+        }
+
+        let (response_tx, response_rx) = async_channel::bounded::<Option<Zeroizing<String>>>(1);
+        let prompt = KeyUnlockPrompt {
+            key_name: name.to_string(),
+            response_tx,
+        };
+        self.unlock_tx
+            .send(prompt)
+            .await
+            .map_err(|_| AppError::SshKey("Cannot ask for the key passphrase".into()))?;
+        let passphrase = response_rx
+            .recv()
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| AppError::SshKey(format!("Unlocking \"{name}\" was cancelled")))?;
+
+        let decrypted = key.decrypt(passphrase.as_bytes())?;
+        self.remember_unlocked(id, &decrypted);
+        Ok(decrypted)
+    }
+
+    fn remember_unlocked(&self, id: Uuid, key: &PrivateKey) {
+        self.unlocked.lock().unwrap().insert(
+            id,
+            UnlockedKey {
+                key: key.clone(),
+                unlocked_at: Instant::now(),
+            },
+        );
+    }
+
+    fn purge_expired(&self) {
+        let Some(lifetime) = *self.lifetime.lock().unwrap() else {
+            return;
+        };
+        self.unlocked
+            .lock()
+            .unwrap()
+            .retain(|_, unlocked| unlocked.unlocked_at.elapsed() < lifetime);
+    }
+}
+
+/// The hash an RSA signature is made with, from the request flags. SHA-1
+/// `ssh-rsa` signatures are not supported.
+fn rsa_hash(flags: u32, name: &str) -> Result<HashAlg, AppError> {
+    if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+        Ok(HashAlg::Sha512)
+    } else if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+        Ok(HashAlg::Sha256)
+    } else {
+        Err(AppError::SshKey(format!(
+            "Key \"{name}\" does not sign with SHA-1 (ssh-rsa)"
+        )))
+    }
+}
+
+/// ssh-key only makes rsa-sha2-512 signatures, so rsa-sha2-256 is done
+/// with the rsa crate directly
+fn sign_rsa_sha256(key: &PrivateKey, data: &[u8]) -> Result<Signature, AppError> {
+    let keypair = key
+        .key_data()
+        .rsa()
+        .ok_or_else(|| AppError::SshKey("Not an RSA key".into()))?;
+    let signing_key = rsa::pkcs1v15::SigningKey::<Sha256>::try_from(keypair)?;
+    let signature = signing_key
+        .try_sign(data)
+        .map_err(|e| AppError::SshKey(e.to_string()))?;
+    Ok(Signature::new(
+        Algorithm::Rsa {
+            hash: Some(HashAlg::Sha256),
+        },
+        signature.to_vec(),
+    )?)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AppError> {
+        if self.data.len() < len {
+            return Err(AppError::SshKey("Truncated agent message".into()));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, AppError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, AppError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_string(&mut self) -> Result<&'a [u8], AppError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    put_u32(buf, value.len() as u32);
+    buf.extend_from_slice(value);
+}
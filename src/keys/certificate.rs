@@ -0,0 +1,142 @@
+use ssh_key::certificate::CertType;
+use ssh_key::{Certificate, HashAlg, PublicKey};
+use uuid::Uuid;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+use crate::storage::paths;
+
+/// Certificates closer to expiry than this are flagged in the key manager
+const EXPIRY_WARNING_SECS: u64 = 24 * 60 * 60;
+
+/// Where a certificate stands relative to its validity window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertificateStatus {
+    NotYetValid,
+    Valid,
+    /// Valid, but less than a day (or a quarter of its lifetime) remains
+    ExpiringSoon,
+    Expired,
+}
+
+/// The parts of a user certificate shown in the key manager.
+#[derive(Debug, Clone)]
+pub struct CertificateInfo {
+    pub key_id: String,
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    pub ca_fingerprint: String,
+}
+
+impl CertificateInfo {
+    pub fn from_certificate(cert: &Certificate) -> Self {
+        Self {
+            key_id: cert.key_id().to_string(),
+            principals: cert.valid_principals().to_vec(),
+            valid_after: cert.valid_after(),
+            valid_before: cert.valid_before(),
+            ca_fingerprint: cert.signature_key().fingerprint(HashAlg::Sha256).to_string(),
+        }
+    }
+
+    pub fn status(&self) -> CertificateStatus {
+        self.status_at(unix_now())
+    }
+
+    pub fn status_at(&self, now: u64) -> CertificateStatus {
+        if now < self.valid_after {
+            return CertificateStatus::NotYetValid;
+        }
+        if now >= self.valid_before {
+            return CertificateStatus::Expired;
+        }
+        let lifetime = self.valid_before.saturating_sub(self.valid_after);
+        let warning = EXPIRY_WARNING_SECS.min(lifetime / 4);
+        if self.valid_before - now <= warning {
+            CertificateStatus::ExpiringSoon
+        } else {
+            CertificateStatus::Valid
+        }
+    }
+
+    /// Principals as OpenSSH lists them, "any" for an unrestricted certificate.
+    pub fn principals_summary(&self) -> String {
+        if self.principals.is_empty() {
+            "any principal".into()
+        } else {
+            self.principals.join(", ")
+        }
+    }
+
+    pub fn validity_summary(&self) -> String {
+        let from = format_timestamp(self.valid_after);
+        let to = if self.valid_before == u64::MAX {
+            "forever".to_string()
+        } else {
+            format_timestamp(self.valid_before)
+        };
+        format!("{from} → {to}")
+    }
+}
+
+/// Load the user certificate stored next to a key, if there is one.
+pub fn load_certificate(key_id: &Uuid) -> Result<Option<Certificate>, AppError> {
+    let path = paths::certificate_path(key_id);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = std::fs::read_to_string(&path)?;
+    Ok(Some(Certificate::from_openssh(data.trim())?))
+}
+
+/// Parse an OpenSSH user certificate and make sure it certifies `public_key`.
+pub fn parse_user_certificate(data: &str, public_key: &PublicKey) -> Result<Certificate, AppError> {
+    let cert = Certificate::from_openssh(data.trim())
+        .map_err(|e| AppError::SshKey(format!("Invalid certificate: {e}")))?;
+    if cert.cert_type() != CertType::User {
+        return Err(AppError::SshKey(
+            "This is a host certificate, not a user certificate".into(),
+        ));
+    }
+    if cert.public_key() != public_key.key_data() {
+        return Err(AppError::SshKey(
+            "The certificate was issued for a different key".into(),
+        ));
+    }
+    Ok(cert)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Render a Unix timestamp as `YYYY-MM-DD HH:MM UTC`.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        rem / 3600,
+        (rem % 3600) / 60
+    )
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
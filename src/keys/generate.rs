@@ -5,6 +5,7 @@ use ssh_key::{Algorithm, EcdsaCurve, HashAlg, LineEnding, PrivateKey};
 use uuid::Uuid;
 
 use crate::error::AppError;
+use crate::keys::certificate;
 use crate::keys::storage::KeyStore;
 use crate::models::connection::{KeyAlgorithm, KeyPairMeta};
 
@@ -71,6 +72,7 @@ pub fn generate_keypair(
         private_key_filename: format!("{}.key", id),
         public_key_filename: format!("{}.pub", id),
         has_passphrase,
+        certificate_filename: None,
     };
 
     Ok(meta)
@@ -100,8 +102,25 @@ pub fn import_keypair(
         .map_err(|e| AppError::KeyGen(format!("Invalid public key: {e}")))?;
     let fingerprint = pub_key.fingerprint(HashAlg::Sha256).to_string();
 
+    // Pick up a certificate saved the way ssh-keygen does, e.g. id_ed25519-cert.pub
+    let mut cert_path = private_key_path.as_os_str().to_owned();
+    cert_path.push("-cert.pub");
+    let certificate = match std::fs::read_to_string(&cert_path) {
+        Ok(data) => match certificate::parse_user_certificate(&data, &pub_key) {
+            Ok(_) => Some(data),
+            Err(e) => {
+                log::warn!("Ignoring certificate next to imported key: {e}");
+                None
+            }
+        },
+        Err(_) => None,
+    };
+
     let id = Uuid::new_v4();
     KeyStore::write_key_files(&id, &private_key_data, &public_key_data)?;
+    if let Some(ref data) = certificate {
+        KeyStore::write_certificate_file(&id, data)?;
+    }
 
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -117,6 +136,7 @@ pub fn import_keypair(
         private_key_filename: format!("{}.key", id),
         public_key_filename: format!("{}.pub", id),
         has_passphrase,
+        certificate_filename: certificate.map(|_| format!("{}-cert.pub", id)),
     };
 
     Ok(meta)
@@ -132,3 +152,35 @@ fn map_algorithm(algo: Algorithm) -> KeyAlgorithm {
         _ => KeyAlgorithm::Ed25519, // fallback
     }
 }
+
+/// Import the key pair at `private_key_path` unless the key manager already
+/// holds a key with the same fingerprint. The public key is expected next to
+/// it with a `.pub` suffix, as ssh-keygen writes it.
+pub fn find_or_import_keypair(
+    key_store: &mut KeyStore,
+    private_key_path: &Path,
+) -> Result<Uuid, AppError> {
+    let mut public_key_path = private_key_path.as_os_str().to_owned();
+    public_key_path.push(".pub");
+    let public_key_path = Path::new(&public_key_path);
+
+    let public_key = ssh_key::PublicKey::read_openssh_file(public_key_path)
+        .map_err(|e| AppError::KeyGen(format!("Cannot read {}: {e}", public_key_path.display())))?;
+    let fingerprint = public_key.fingerprint(HashAlg::Sha256).to_string();
+    if let Some(existing) = key_store
+        .keys
+        .iter()
+        .find(|k| k.public_key_fingerprint == fingerprint)
+    {
+        return Ok(existing.id);
+    }
+
+    let name = private_key_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Imported key".into());
+    let meta = import_keypair(&name, private_key_path, public_key_path)?;
+    let id = meta.id;
+    key_store.add(meta)?;
+    Ok(id)
+}
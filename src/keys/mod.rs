@@ -1,2 +1,4 @@
+pub mod agent;
+pub mod certificate;
 pub mod generate;
 pub mod storage;
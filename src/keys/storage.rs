@@ -1,10 +1,13 @@
 use std::os::unix::fs::PermissionsExt;
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
 
 use crate::config;
 use crate::error::AppError;
+use crate::keys::certificate;
 use crate::models::connection::KeyPairMeta;
+use crate::storage::backup::{self, EncryptedPayload, BACKUP_VERSION};
 use crate::storage::paths;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,14 +15,23 @@ pub struct KeyBackupEntry {
     pub meta: KeyPairMeta,
     pub private_key: String,
     pub public_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct KeyBackup {
     pub version: u32,
+    #[serde(default)]
     pub keys: Vec<KeyBackupEntry>,
+    /// The serialized `keys`, when the backup is passphrase-protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<EncryptedPayload>,
 }
 
+/// Authenticated with encrypted key backups
+const KEY_BACKUP_CONTEXT: &str = "grustyssh-key-backup";
+
 #[derive(Debug)]
 pub struct KeyStore {
     pub keys: Vec<KeyPairMeta>,
@@ -58,12 +70,16 @@ impl KeyStore {
         // Remove key files
         let priv_path = paths::private_key_path(id);
         let pub_path = paths::public_key_path(id);
+        let cert_path = paths::certificate_path(id);
         if priv_path.exists() {
             std::fs::remove_file(&priv_path)?;
         }
         if pub_path.exists() {
             std::fs::remove_file(&pub_path)?;
         }
+        if cert_path.exists() {
+            std::fs::remove_file(&cert_path)?;
+        }
         self.keys.retain(|k| &k.id != id);
         self.save()
     }
@@ -89,35 +105,114 @@ impl KeyStore {
         Ok(())
     }
 
+    pub fn write_certificate_file(id: &Uuid, certificate_openssh: &str) -> Result<(), AppError> {
+        let cert_path = paths::certificate_path(id);
+        std::fs::write(&cert_path, certificate_openssh)?;
+        std::fs::set_permissions(&cert_path, std::fs::Permissions::from_mode(0o644))?;
+        Ok(())
+    }
+
+    /// Store a user certificate for an existing key, replacing any previous one.
+    pub fn attach_certificate(&mut self, id: &Uuid, certificate_openssh: &str) -> Result<(), AppError> {
+        let public_key = ssh_key::PublicKey::from_openssh(Self::read_public_key(id)?.trim())?;
+        certificate::parse_user_certificate(certificate_openssh, &public_key)?;
+
+        let meta = self
+            .keys
+            .iter_mut()
+            .find(|k| &k.id == id)
+            .ok_or_else(|| AppError::SshKey("Key not found".into()))?;
+        Self::write_certificate_file(id, certificate_openssh)?;
+        meta.certificate_filename = Some(format!("{}-cert.pub", id));
+        self.save()
+    }
+
     pub fn read_public_key(id: &Uuid) -> Result<String, AppError> {
         let path = paths::public_key_path(id);
         Ok(std::fs::read_to_string(path)?)
     }
 
-    pub fn export_backup(&self) -> Result<String, AppError> {
+    /// Read every key from disk for a backup, private keys included.
+    pub fn backup_entries(&self) -> Result<Vec<KeyBackupEntry>, AppError> {
         let mut entries = Vec::new();
         for meta in &self.keys {
             let private_key = std::fs::read_to_string(paths::private_key_path(&meta.id))?;
             let public_key = std::fs::read_to_string(paths::public_key_path(&meta.id))?;
+            let certificate = match meta.certificate_filename {
+                Some(_) => Some(std::fs::read_to_string(paths::certificate_path(&meta.id))?),
+                None => None,
+            };
             entries.push(KeyBackupEntry {
                 meta: meta.clone(),
                 private_key,
                 public_key,
+                certificate,
             });
         }
-        Ok(serde_json::to_string_pretty(&KeyBackup { version: 1, keys: entries })?)
+        Ok(entries)
+    }
+
+    /// Serialize the entries from [`Self::backup_entries`]. With a passphrase
+    /// they are encrypted, see [`backup::seal`]. Deriving the key takes a
+    /// while, so call this off the main thread without the store locked.
+    pub fn export_backup(
+        entries: Vec<KeyBackupEntry>,
+        passphrase: Option<&str>,
+    ) -> Result<String, AppError> {
+        let backup = match passphrase {
+            Some(passphrase) => {
+                let plaintext = Zeroizing::new(serde_json::to_vec(&entries)?);
+                KeyBackup {
+                    version: BACKUP_VERSION,
+                    keys: Vec::new(),
+                    encrypted: Some(backup::seal(&plaintext, passphrase, KEY_BACKUP_CONTEXT)?),
+                }
+            }
+            None => KeyBackup {
+                version: BACKUP_VERSION,
+                keys: entries,
+                encrypted: None,
+            },
+        };
+        Ok(serde_json::to_string_pretty(&backup)?)
     }
 
-    pub fn import_backup(&mut self, json: &str) -> Result<usize, AppError> {
+    /// Read the keys of a backup. Encrypted backups need `passphrase`, use
+    /// [`backup::is_encrypted`] to find out beforehand. Like
+    /// [`Self::export_backup`] this is slow and needs no store.
+    pub fn read_backup(
+        json: &str,
+        passphrase: Option<&str>,
+    ) -> Result<Vec<KeyBackupEntry>, AppError> {
         let backup: KeyBackup = serde_json::from_str(json)
             .map_err(|e| AppError::Other(format!("Invalid backup file: {e}")))?;
+        backup::check_version(backup.version)?;
+        match backup.encrypted {
+            Some(ref payload) => {
+                let passphrase = passphrase
+                    .ok_or_else(|| AppError::Other("This backup is protected by a passphrase".into()))?;
+                let plaintext = backup::open(payload, passphrase, KEY_BACKUP_CONTEXT)?;
+                serde_json::from_slice::<Vec<KeyBackupEntry>>(&plaintext)
+                    .map_err(|e| AppError::Other(format!("Invalid backup file: {e}")))
+            }
+            None => Ok(backup.keys),
+        }
+    }
+
+    /// Write the keys read from a backup, skipping those already present.
+    pub fn import_backup(&mut self, entries: Vec<KeyBackupEntry>) -> Result<usize, AppError> {
         let mut imported = 0;
-        for entry in backup.keys {
+        for entry in entries {
             if self.keys.iter().any(|k| k.id == entry.meta.id) {
                 continue;
             }
             Self::write_key_files(&entry.meta.id, &entry.private_key, &entry.public_key)?;
-            self.keys.push(entry.meta);
+            let mut meta = entry.meta;
+            match entry.certificate {
+                Some(ref certificate) => Self::write_certificate_file(&meta.id, certificate)?,
+                None => meta.certificate_filename = None,
+            }
+            self.keys.push(meta);
             imported += 1;
         }
         self.save()?;
@@ -52,6 +52,10 @@ fn main() {
         gtk::Window::set_default_icon_name("grustyssh");
     });
 
+    app.connect_shutdown(|_| {
+        keys::agent::stop();
+    });
+
     app.connect_activate(move |app| {
         let state = SharedState::new();
         let window = ui::window::build_window(app, state);
@@ -3,11 +3,17 @@ use uuid::Uuid;
 
 use crate::models::tunnel::TunnelConfig;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum AuthMethod {
     Password,
     PublicKey,
     Both,
+    /// Server-driven prompts, e.g. a password followed by a one-time code
+    KeyboardInteractive,
+    /// Public key first, then keyboard-interactive for the second factor
+    PublicKeyAndKeyboardInteractive,
+    /// Every identity held by the profile's SSH agent
+    Agent,
 }
 
 impl std::fmt::Display for AuthMethod {
@@ -16,6 +22,45 @@ impl std::fmt::Display for AuthMethod {
             AuthMethod::Password => write!(f, "Password"),
             AuthMethod::PublicKey => write!(f, "Public Key"),
             AuthMethod::Both => write!(f, "Both"),
+            AuthMethod::KeyboardInteractive => write!(f, "Keyboard-Interactive"),
+            AuthMethod::PublicKeyAndKeyboardInteractive => {
+                write!(f, "Public Key + Keyboard-Interactive")
+            }
+            AuthMethod::Agent => write!(f, "SSH Agent"),
+        }
+    }
+}
+
+impl AuthMethod {
+    /// Whether this method authenticates with the profile's key pair.
+    pub fn uses_key(&self) -> bool {
+        matches!(
+            self,
+            AuthMethod::PublicKey | AuthMethod::Both | AuthMethod::PublicKeyAndKeyboardInteractive
+        )
+    }
+
+    /// Whether a password must be entered before connecting.
+    pub fn uses_password(&self) -> bool {
+        matches!(self, AuthMethod::Password | AuthMethod::Both)
+    }
+}
+
+/// Which SSH agent a profile authenticates with and forwards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AgentChoice {
+    /// The agent from `$SSH_AUTH_SOCK`, e.g. ssh-agent, gpg-agent or a token
+    #[default]
+    System,
+    /// The built-in agent serving the key manager's keys
+    BuiltIn,
+}
+
+impl std::fmt::Display for AgentChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgentChoice::System => write!(f, "System agent"),
+            AgentChoice::BuiltIn => write!(f, "Built-in agent"),
         }
     }
 }
@@ -62,6 +107,84 @@ pub struct KeyPairMeta {
     pub public_key_filename: String,
     #[serde(default)]
     pub has_passphrase: bool,
+    /// OpenSSH user certificate stored next to the key, if any
+    #[serde(default)]
+    pub certificate_filename: Option<String>,
+}
+
+/// An environment variable sent to the server before the shell starts.
+/// sshd only accepts names listed in its `AcceptEnv`, by default LANG and LC_*.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+}
+
+/// A PTY terminal mode (RFC 4254, 8) such as `VERASE` or `ICRNL`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalMode {
+    pub name: String,
+    pub value: u32,
+}
+
+/// One line of a startup script, typed once `wait_for` matched the output
+/// or right after the previous step when there is nothing to wait for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StartupStep {
+    /// Regular expression, e.g. a shell prompt
+    #[serde(default)]
+    pub wait_for: Option<String>,
+    pub send: String,
+}
+
+/// How a session transcript is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogFormat {
+    /// Exactly the bytes received, escape sequences included
+    Raw,
+    /// Escape sequences stripped, readable in any editor
+    #[default]
+    Text,
+}
+
+/// Session logging options, global in the settings and optionally
+/// overridden per profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionLogConfig {
+    pub enabled: bool,
+    pub format: LogFormat,
+    /// Prefix every line of a text log with the local time
+    pub timestamps: bool,
+    /// Also log what was typed, including passwords at no-echo prompts
+    pub log_input: bool,
+    /// File name with `{profile}`, `{host}`, `{user}`, `{date}` and `{time}`
+    pub file_template: String,
+}
+
+impl Default for SessionLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: LogFormat::Text,
+            timestamps: true,
+            log_input: false,
+            file_template: "{profile}-{date}.log".into(),
+        }
+    }
+}
+
+/// Ordered algorithm lists offered during key exchange, most preferred
+/// first. An empty list falls back to the next level: profile, then global
+/// settings, then the russh defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlgorithmPreferences {
+    pub kex: Vec<String>,
+    pub host_key: Vec<String>,
+    pub cipher: Vec<String>,
+    pub mac: Vec<String>,
+    pub compression: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,6 +197,47 @@ pub struct ConnectionProfile {
     pub auth_method: AuthMethod,
     pub key_pair_id: Option<Uuid>,
     pub tunnels: Vec<TunnelConfig>,
+    /// Profiles to hop through before reaching this host (ProxyJump), in order
+    #[serde(default)]
+    pub jump_hosts: Vec<Uuid>,
+    /// Agent used for agent authentication and forwarding
+    #[serde(default)]
+    pub agent: AgentChoice,
+    /// Let the server use our SSH agent (`ssh -A`)
+    #[serde(default)]
+    pub agent_forwarding: bool,
+    /// Let remote GUI programs open windows on our display (`ssh -X`)
+    #[serde(default)]
+    pub x11_forwarding: bool,
+    /// Reconnect terminal tabs with backoff when the connection drops
+    #[serde(default)]
+    pub auto_reconnect: bool,
+    /// Keepalive settings for this host, `None` uses the global setting
+    #[serde(default)]
+    pub keepalive_interval_secs: Option<u32>,
+    #[serde(default)]
+    pub keepalive_max: Option<u32>,
+    #[serde(default)]
+    pub inactivity_timeout_secs: Option<u32>,
+    /// Algorithm overrides for this host, e.g. legacy ones for old network gear
+    #[serde(default)]
+    pub algorithms: AlgorithmPreferences,
+    /// `TERM` for this host, `None` uses the global default
+    #[serde(default)]
+    pub terminal_type: Option<String>,
+    #[serde(default)]
+    pub environment: Vec<EnvVar>,
+    #[serde(default)]
+    pub terminal_modes: Vec<TerminalMode>,
+    /// Run this with `exec` instead of starting a login shell
+    #[serde(default)]
+    pub remote_command: Option<String>,
+    /// Lines typed after login
+    #[serde(default)]
+    pub startup_script: Vec<StartupStep>,
+    /// Logging options for this host, `None` uses the global ones
+    #[serde(default)]
+    pub session_log: Option<SessionLogConfig>,
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -90,6 +254,21 @@ impl ConnectionProfile {
             auth_method: AuthMethod::Password,
             key_pair_id: None,
             tunnels: Vec::new(),
+            jump_hosts: Vec::new(),
+            agent: AgentChoice::System,
+            agent_forwarding: false,
+            x11_forwarding: false,
+            auto_reconnect: false,
+            keepalive_interval_secs: None,
+            keepalive_max: None,
+            inactivity_timeout_secs: None,
+            algorithms: AlgorithmPreferences::default(),
+            terminal_type: None,
+            environment: Vec::new(),
+            terminal_modes: Vec::new(),
+            remote_command: None,
+            startup_script: Vec::new(),
+            session_log: None,
             created_at: now,
             updated_at: now,
         }
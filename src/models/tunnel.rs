@@ -3,13 +3,23 @@ use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TunnelType {
+    /// `ssh -L`: listen on `local_host:local_port`, connect to
+    /// `remote_host:remote_port` from the server.
     LocalForward,
+    /// `ssh -R`: the server listens on `remote_host:remote_port`,
+    /// connections are forwarded to `local_host:local_port`.
+    RemoteForward,
+    /// `ssh -D`: SOCKS4/5 proxy on `local_host:local_port`, destinations are
+    /// chosen per connection by the client.
+    DynamicForward,
 }
 
 impl std::fmt::Display for TunnelType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             TunnelType::LocalForward => write!(f, "Local Forward"),
+            TunnelType::RemoteForward => write!(f, "Remote Forward"),
+            TunnelType::DynamicForward => write!(f, "Dynamic Forward (SOCKS)"),
         }
     }
 }
@@ -39,4 +49,21 @@ impl TunnelConfig {
             enabled: true,
         }
     }
+
+    /// One-line description of the forward in the direction traffic flows.
+    pub fn summary(&self) -> String {
+        match self.tunnel_type {
+            TunnelType::LocalForward => format!(
+                "{}:{} → {}:{}",
+                self.local_host, self.local_port, self.remote_host, self.remote_port
+            ),
+            TunnelType::RemoteForward => format!(
+                "remote {}:{} → {}:{}",
+                self.remote_host, self.remote_port, self.local_host, self.local_port
+            ),
+            TunnelType::DynamicForward => {
+                format!("SOCKS proxy on {}:{}", self.local_host, self.local_port)
+            }
+        }
+    }
 }
@@ -0,0 +1,93 @@
+use russh::client;
+use russh::Channel;
+use russh_keys::agent::client::AgentClient;
+use tokio::net::UnixStream;
+
+use std::path::PathBuf;
+
+use crate::error::AppError;
+use crate::keys;
+use crate::models::connection::AgentChoice;
+use crate::ssh::handler::ClientHandler;
+
+/// Environment variable OpenSSH uses to locate the agent socket.
+pub const AUTH_SOCK_ENV: &str = "SSH_AUTH_SOCK";
+
+/// Socket of the agent a profile chose. Never falls back to the other
+/// agent, so keys on a hardware token don't silently go missing.
+pub fn agent_socket(choice: AgentChoice) -> Result<PathBuf, AppError> {
+    match choice {
+        AgentChoice::System => std::env::var_os(AUTH_SOCK_ENV)
+            .map(PathBuf::from)
+            .ok_or_else(|| AppError::Auth(format!("No SSH agent running ({AUTH_SOCK_ENV} is not set)"))),
+        AgentChoice::BuiltIn => keys::agent::socket_path().ok_or_else(|| {
+            AppError::Auth("The built-in SSH agent is off, enable it in Preferences".into())
+        }),
+    }
+}
+
+/// The agent and its socket, for telling the user which one a session uses
+pub fn describe(choice: AgentChoice) -> String {
+    match agent_socket(choice) {
+        Ok(socket) => format!("{choice} at {}", socket.display()),
+        Err(e) => format!("{choice} unavailable: {e}"),
+    }
+}
+
+/// Authenticate with every identity held by the agent until the server
+/// accepts one. Returns `false` if none was accepted.
+pub async fn authenticate_with_agent(
+    session: &mut client::Handle<ClientHandler>,
+    username: &str,
+    choice: AgentChoice,
+) -> Result<bool, AppError> {
+    let socket = agent_socket(choice)?;
+    let mut agent = AgentClient::connect_uds(&socket)
+        .await
+        .map_err(|e| AppError::Auth(format!("Cannot reach the SSH agent: {e}")))?;
+    let identities = agent
+        .request_identities()
+        .await
+        .map_err(|e| AppError::Auth(format!("Failed to list agent identities: {e}")))?;
+    if identities.is_empty() {
+        return Err(AppError::Auth(format!("The {choice} holds no identities")));
+    }
+
+    for key in identities {
+        let fingerprint = key.fingerprint();
+        let (returned, result) = session.authenticate_future(username, key, agent).await;
+        agent = returned;
+        match result {
+            Ok(true) => return Ok(true),
+            Ok(false) => log::debug!("Agent identity {fingerprint} was not accepted"),
+            Err(e) => log::warn!("Agent failed to sign with {fingerprint}: {e}"),
+        }
+    }
+    Ok(false)
+}
+
+/// Connect a forwarded `auth-agent@openssh.com` channel to the local agent.
+pub async fn forward_to_local_agent(channel: Channel<client::Msg>, choice: AgentChoice) {
+    let socket_path = match agent_socket(choice) {
+        Ok(socket_path) => socket_path,
+        Err(e) => {
+            log::warn!("Cannot forward the agent: {e}");
+            let _ = channel.close().await;
+            return;
+        }
+    };
+
+    let mut agent_stream = match UnixStream::connect(&socket_path).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            log::warn!("Failed to connect to the SSH agent for forwarding: {e}");
+            let _ = channel.close().await;
+            return;
+        }
+    };
+
+    let mut channel_stream = channel.into_stream();
+    if let Err(e) = tokio::io::copy_bidirectional(&mut channel_stream, &mut agent_stream).await {
+        log::debug!("Forwarded agent connection ended: {e}");
+    }
+}
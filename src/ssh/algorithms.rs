@@ -1,7 +1,244 @@
-use russh::Preferred;
+use russh::keys::key;
+use russh::{cipher, compression, kex, mac, Preferred};
 
-/// Build a `russh::Preferred` with our desired algorithm ordering.
-/// We just use the russh defaults which include all supported algorithms.
-pub fn preferred_algorithms() -> Preferred {
-    Preferred::default()
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::models::connection::AlgorithmPreferences;
+use crate::ssh::kexinit::ServerKexInit;
+
+/// Key exchange algorithms russh implements, strongest first
+const KEX: &[kex::Name] = &[
+    kex::CURVE25519,
+    kex::CURVE25519_PRE_RFC_8731,
+    kex::ECDH_SHA2_NISTP521,
+    kex::ECDH_SHA2_NISTP384,
+    kex::ECDH_SHA2_NISTP256,
+    kex::DH_G16_SHA512,
+    kex::DH_G14_SHA256,
+    kex::DH_G14_SHA1,
+    kex::DH_G1_SHA1,
+];
+
+const HOST_KEY: &[key::Name] = &[
+    key::ED25519,
+    key::ECDSA_SHA2_NISTP521,
+    key::ECDSA_SHA2_NISTP384,
+    key::ECDSA_SHA2_NISTP256,
+    key::RSA_SHA2_512,
+    key::RSA_SHA2_256,
+    key::SSH_RSA,
+];
+
+const CIPHER: &[cipher::Name] = &[
+    cipher::CHACHA20_POLY1305,
+    cipher::AES_256_GCM,
+    cipher::AES_256_CTR,
+    cipher::AES_192_CTR,
+    cipher::AES_128_CTR,
+    cipher::AES_256_CBC,
+    cipher::AES_192_CBC,
+    cipher::AES_128_CBC,
+    cipher::TRIPLE_DES_CBC,
+];
+
+const MAC: &[mac::Name] = &[
+    mac::HMAC_SHA512_ETM,
+    mac::HMAC_SHA256_ETM,
+    mac::HMAC_SHA512,
+    mac::HMAC_SHA256,
+    mac::HMAC_SHA1_ETM,
+    mac::HMAC_SHA1,
+];
+
+const COMPRESSION: &[compression::Name] = &[
+    compression::NONE,
+    compression::ZLIB,
+    compression::ZLIB_LEGACY,
+];
+
+/// The algorithm lists that can be configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgorithmKind {
+    Kex,
+    HostKey,
+    Cipher,
+    Mac,
+    Compression,
+}
+
+impl AlgorithmKind {
+    /// Names of every supported algorithm of this kind
+    pub fn supported(self) -> Vec<&'static str> {
+        match self {
+            AlgorithmKind::Kex => names(KEX),
+            AlgorithmKind::HostKey => names(HOST_KEY),
+            AlgorithmKind::Cipher => names(CIPHER),
+            AlgorithmKind::Mac => names(MAC),
+            AlgorithmKind::Compression => names(COMPRESSION),
+        }
+    }
+
+    /// The names in `list` that russh does not implement
+    pub fn unsupported(self, list: &[String]) -> Vec<String> {
+        let supported = self.supported();
+        list.iter()
+            .filter(|name| !supported.contains(&name.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+fn names<T: AsRef<str>>(list: &'static [T]) -> Vec<&'static str> {
+    list.iter().map(|name| name.as_ref()).collect()
+}
+
+/// Only modern algorithms, roughly what ssh-audit recommends
+pub fn hardened() -> AlgorithmPreferences {
+    AlgorithmPreferences {
+        kex: strings(&[kex::CURVE25519, kex::CURVE25519_PRE_RFC_8731, kex::DH_G16_SHA512]),
+        host_key: strings(&[key::ED25519, key::RSA_SHA2_512, key::RSA_SHA2_256]),
+        cipher: strings(&[cipher::CHACHA20_POLY1305, cipher::AES_256_GCM, cipher::AES_256_CTR]),
+        mac: strings(&[mac::HMAC_SHA512_ETM, mac::HMAC_SHA256_ETM]),
+        compression: Vec::new(),
+    }
+}
+
+/// Everything russh implements, modern algorithms first, for old switches
+/// and appliances that only speak SHA-1 and CBC
+pub fn legacy() -> AlgorithmPreferences {
+    AlgorithmPreferences {
+        kex: strings(KEX),
+        host_key: strings(HOST_KEY),
+        cipher: strings(CIPHER),
+        mac: strings(MAC),
+        compression: Vec::new(),
+    }
+}
+
+fn strings<T: AsRef<str>>(list: &[T]) -> Vec<String> {
+    list.iter().map(|name| name.as_ref().to_string()).collect()
+}
+
+/// Build the `russh::Preferred` for a connection. Each list comes from the
+/// profile if set there, else from the global settings, else russh's
+/// defaults. Unknown names are skipped.
+pub fn preferred_algorithms(
+    global: &AlgorithmPreferences,
+    profile: &AlgorithmPreferences,
+) -> Preferred {
+    let defaults = Preferred::default();
+    let pick = |profile: &[String], global: &[String]| -> Vec<String> {
+        if profile.is_empty() {
+            global.to_vec()
+        } else {
+            profile.to_vec()
+        }
+    };
+
+    let kex = match select(KEX, &pick(&profile.kex, &global.kex)) {
+        // The pseudo-algorithms announcing extension negotiation and strict
+        // key exchange must stay, or the Terrapin countermeasure is lost
+        Some(mut kex) => {
+            kex.push(kex::EXTENSION_SUPPORT_AS_CLIENT);
+            kex.push(kex::EXTENSION_OPENSSH_STRICT_KEX_AS_CLIENT);
+            Cow::Owned(kex)
+        }
+        None => defaults.kex,
+    };
+
+    Preferred {
+        kex,
+        key: select(HOST_KEY, &pick(&profile.host_key, &global.host_key))
+            .map_or(defaults.key, Cow::Owned),
+        cipher: select(CIPHER, &pick(&profile.cipher, &global.cipher))
+            .map_or(defaults.cipher, Cow::Owned),
+        mac: select(MAC, &pick(&profile.mac, &global.mac)).map_or(defaults.mac, Cow::Owned),
+        compression: select(COMPRESSION, &pick(&profile.compression, &global.compression))
+            .map_or(defaults.compression, Cow::Owned),
+    }
+}
+
+/// The names in a `russh::Preferred`, to compare the algorithms two
+/// connections were set up with
+pub fn preferred_names(preferred: &Preferred) -> AlgorithmPreferences {
+    AlgorithmPreferences {
+        kex: strings(&preferred.kex),
+        host_key: strings(&preferred.key),
+        cipher: strings(&preferred.cipher),
+        mac: strings(&preferred.mac),
+        compression: strings(&preferred.compression),
+    }
+}
+
+/// Map configured names onto russh's, in the configured order. `None` when
+/// nothing usable is configured, so the caller keeps the default list.
+fn select<T: AsRef<str> + Copy>(supported: &[T], configured: &[String]) -> Option<Vec<T>> {
+    let mut selected = Vec::new();
+    for name in configured {
+        match supported.iter().find(|s| s.as_ref() == name) {
+            Some(algorithm) => selected.push(*algorithm),
+            None => log::warn!("Ignoring unsupported algorithm {name}"),
+        }
+    }
+    (!selected.is_empty()).then_some(selected)
+}
+
+/// The algorithms a connection ended up with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiatedAlgorithms {
+    pub kex: Option<String>,
+    pub host_key: Option<String>,
+    pub cipher: Option<String>,
+    pub mac: Option<String>,
+    pub compression: Option<String>,
+}
+
+impl NegotiatedAlgorithms {
+    /// Apply the SSH negotiation rule (RFC 4253, 7.1): the first algorithm on
+    /// the client's list that the server also offers. Uses the
+    /// client-to-server lists, which servers keep identical in practice.
+    pub fn negotiate(client: &Preferred, server: &ServerKexInit) -> Self {
+        let cipher = first_common(&client.cipher, &server.cipher);
+        // AEAD ciphers authenticate the data themselves and ignore the MAC
+        let aead = cipher
+            .as_deref()
+            .is_some_and(|c| c == cipher::CHACHA20_POLY1305.as_ref() || c.contains("-gcm"));
+        let mac = if aead {
+            Some("implicit".to_string())
+        } else {
+            first_common(&client.mac, &server.mac)
+        };
+
+        Self {
+            kex: first_common(&client.kex, &server.kex),
+            host_key: first_common(&client.key, &server.host_key),
+            cipher,
+            mac,
+            compression: first_common(&client.compression, &server.compression),
+        }
+    }
+}
+
+fn first_common<T: AsRef<str>>(client: &[T], server: &[String]) -> Option<String> {
+    client
+        .iter()
+        .map(|name| name.as_ref())
+        .find(|name| server.iter().any(|s| s == name))
+        .map(str::to_string)
+}
+
+impl fmt::Display for NegotiatedAlgorithms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "?".into());
+        write!(
+            f,
+            "kex {}, host key {}, cipher {}, MAC {}, compression {}",
+            show(&self.kex),
+            show(&self.host_key),
+            show(&self.cipher),
+            show(&self.mac),
+            show(&self.compression)
+        )
+    }
 }
@@ -0,0 +1,113 @@
+/// Removes terminal escape sequences and control characters from session
+/// output, leaving the text a reader would see. Keeps its state between
+/// calls, so sequences split across packets are still recognised.
+#[derive(Debug, Default)]
+pub struct AnsiStripper {
+    state: State,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    /// Control sequence, up to a final byte in `@`..`~`
+    Csi,
+    /// Operating system command such as a window title, or a DCS, SOS, PM
+    /// or APC string, up to BEL or ST
+    ControlString,
+    ControlStringEscape,
+}
+
+impl AnsiStripper {
+    pub fn strip(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for &b in data {
+            self.state = match (self.state, b) {
+                (State::Ground, 0x1b) => State::Escape,
+                (State::Ground, b'\n' | b'\t') => {
+                    out.push(b);
+                    State::Ground
+                }
+                (State::Ground, c) if c < 0x20 || c == 0x7f => State::Ground,
+                (State::Ground, c) => {
+                    out.push(c);
+                    State::Ground
+                }
+                (State::Escape, b'[') => State::Csi,
+                (State::Escape, b']' | b'P' | b'X' | b'^' | b'_') => State::ControlString,
+                // Two-byte sequences such as ESC = or ESC ( B
+                (State::Escape, b'(' | b')' | b'#') => State::Escape,
+                (State::Escape, _) => State::Ground,
+                (State::Csi, c) if (0x40..=0x7e).contains(&c) => State::Ground,
+                (State::Csi, _) => State::Csi,
+                (State::ControlString, 0x07) => State::Ground,
+                (State::ControlString, 0x1b) => State::ControlStringEscape,
+                (State::ControlString, _) => State::ControlString,
+                (State::ControlStringEscape, b'\\') => State::Ground,
+                (State::ControlStringEscape, _) => State::ControlString,
+            };
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(data: &[u8]) -> String {
+        String::from_utf8(AnsiStripper::default().strip(data)).unwrap()
+    }
+
+    #[test]
+    fn colours_and_cursor_movement() {
+        assert_eq!(strip(b"\x1b[1;32muser@host\x1b[0m:\x1b[34m~\x1b[0m$ "), "user@host:~$ ");
+        assert_eq!(strip(b"a\x1b[2Kb\x1b[?2004hc"), "abc");
+    }
+
+    #[test]
+    fn control_characters() {
+        assert_eq!(strip(b"one\r\ntwo\tthree\x07\x08\x7f\n"), "one\ntwo\tthree\n");
+    }
+
+    #[test]
+    fn two_byte_sequences() {
+        assert_eq!(strip(b"\x1b=\x1b(Bline\x1b)0\x1b#8\x1b>"), "line");
+    }
+
+    #[test]
+    fn osc_ends_at_bel_or_st() {
+        assert_eq!(strip(b"\x1b]0;title\x07a\x1b]2;other\x1b\\b"), "ab");
+        // An ESC inside the string that does not start ST is part of it
+        assert_eq!(strip(b"\x1b]0;x\x1bytitle\x07c"), "c");
+    }
+
+    #[test]
+    fn dcs_sos_pm_apc_strings() {
+        assert_eq!(strip(b"a\x1bP1$r0m\x1b\\b"), "ab");
+        assert_eq!(strip(b"\x1bXsos\x1b\\c"), "c");
+        assert_eq!(strip(b"\x1b^pm\x1b\\d"), "d");
+        assert_eq!(strip(b"\x1b_Gf=100;AAAA\x1b\\e"), "e");
+    }
+
+    #[test]
+    fn sequences_split_across_calls() {
+        let mut stripper = AnsiStripper::default();
+        let mut out = Vec::new();
+        for chunk in [
+            &b"red \x1b"[..],
+            b"[3",
+            b"1mtext\x1b[0",
+            b"m \x1b]0;ti",
+            b"tle\x1b",
+            b"\\ \x1bP",
+            b"data",
+            b"\x1b",
+            b"\\done",
+        ] {
+            out.extend(stripper.strip(chunk));
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "red text  done");
+    }
+}
@@ -1,24 +1,73 @@
+use std::sync::Arc;
 use async_trait::async_trait;
 use russh::client;
 use russh::keys::key::PublicKey;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use russh::keys::PublicKeyBase64;
+use russh::Channel;
 
-use crate::app::SshEvent;
+use crate::app::{HostKeyDecision, HostKeyPrompt, SshEvent};
+use crate::config;
+use crate::error::AppError;
+use crate::models::connection::AgentChoice;
+use crate::ssh::agent;
+use crate::ssh::known_hosts::{self, HostKeyStatus, KnownHosts};
+use crate::ssh::tunnel::{self, RemoteForwardTargets};
+use crate::ssh::x11::{self, X11Forwarding};
 
 pub struct ClientHandler {
     pub event_tx: async_channel::Sender<SshEvent>,
-    pub host_key_accepted: Arc<Mutex<Option<bool>>>,
-    pub host_key_notify: Arc<tokio::sync::Notify>,
+    pub host: String,
+    pub port: u16,
+    pub remote_forwards: RemoteForwardTargets,
+    /// Sessions to the jump hosts this connection is tunnelled through.
+    /// They are owned by the handler so they stay open exactly as long as
+    /// this session does.
+    pub jump_sessions: Vec<client::Handle<ClientHandler>>,
+    /// The agent the server may open channels back to, if any
+    pub agent_forwarding: Option<AgentChoice>,
+    /// Where X11 channels the server opens are sent, if anywhere
+    pub x11: Option<Arc<X11Forwarding>>,
 }
 
 impl ClientHandler {
-    pub fn new(event_tx: async_channel::Sender<SshEvent>) -> Self {
+    pub fn new(
+        event_tx: async_channel::Sender<SshEvent>,
+        host: String,
+        port: u16,
+        remote_forwards: RemoteForwardTargets,
+    ) -> Self {
         Self {
             event_tx,
-            host_key_accepted: Arc::new(Mutex::new(None)),
-            host_key_notify: Arc::new(tokio::sync::Notify::new()),
+            host,
+            port,
+            remote_forwards,
+            jump_sessions: Vec::new(),
+            agent_forwarding: None,
+            x11: None,
+        }
+    }
+
+    /// Ask the UI whether to trust an unknown key and wait for the answer.
+    async fn ask_host_key(
+        &self,
+        host_name: &str,
+        key: &ssh_key::PublicKey,
+        cert_authority: bool,
+    ) -> HostKeyDecision {
+        let (response_tx, response_rx) = async_channel::bounded::<HostKeyDecision>(1);
+        let prompt = HostKeyPrompt {
+            host: host_name.to_string(),
+            key_type: key.algorithm().as_str().to_string(),
+            fingerprint: known_hosts::fingerprint(key),
+            randomart: known_hosts::randomart(key),
+            cert_authority,
+            response_tx,
+        };
+
+        if self.event_tx.send(SshEvent::HostKeyVerify(prompt)).await.is_err() {
+            return HostKeyDecision::Reject;
         }
+        response_rx.recv().await.unwrap_or(HostKeyDecision::Reject)
     }
 }
 
@@ -26,27 +75,116 @@ impl ClientHandler {
 impl client::Handler for ClientHandler {
     type Error = anyhow::Error;
 
+    // russh only negotiates plain host key algorithms, so servers never
+    // present a host certificate and `@cert-authority` entries cannot be
+    // checked. The prompt for an unknown key says so.
     async fn check_server_key(
         &mut self,
         server_public_key: &PublicKey,
     ) -> Result<bool, Self::Error> {
-        let fingerprint = server_public_key.fingerprint();
-        let key_type = match server_public_key {
-            PublicKey::Ed25519(_) => "ssh-ed25519",
-            PublicKey::RSA { .. } => "ssh-rsa",
-            PublicKey::EC { ref key } => key.ident(),
-        };
+        let key = ssh_key::PublicKey::from_bytes(&server_public_key.public_key_bytes())
+            .map_err(|e| AppError::HostKey(format!("Unsupported server key: {e}")))?;
+        let key_type = key.algorithm().as_str().to_string();
+        let fingerprint = known_hosts::fingerprint(&key);
+        let host_name = known_hosts::host_pattern(&self.host, self.port);
 
-        let _ = self
-            .event_tx
-            .send(SshEvent::HostKeyVerify {
-                key_type: key_type.to_string(),
-                fingerprint: fingerprint.clone(),
-            })
-            .await;
+        let mut known_hosts = KnownHosts::load(config::known_hosts_path());
+        match known_hosts.check(&self.host, self.port, &key) {
+            HostKeyStatus::Trusted => Ok(true),
+            HostKeyStatus::Changed { line } => Err(AppError::HostKey(format!(
+                "REMOTE HOST IDENTIFICATION HAS CHANGED for {host_name}! \
+                 The server offered {key_type} key {fingerprint}, which does not match \
+                 the key stored at {}:{line}. Remove that line if the change is expected.",
+                known_hosts.path().display()
+            ))
+            .into()),
+            HostKeyStatus::Revoked { line } => Err(AppError::HostKey(format!(
+                "The {key_type} key {fingerprint} for {host_name} is marked as revoked \
+                 at {}:{line}",
+                known_hosts.path().display()
+            ))
+            .into()),
+            HostKeyStatus::Unknown => match self
+                .ask_host_key(&host_name, &key, known_hosts.has_cert_authority(&self.host, self.port))
+                .await
+            {
+                HostKeyDecision::AcceptOnce => Ok(true),
+                HostKeyDecision::AcceptAndSave => {
+                    known_hosts.add(&self.host, self.port, &key)?;
+                    Ok(true)
+                }
+                HostKeyDecision::Reject => Err(AppError::HostKey(format!(
+                    "The {key_type} key {fingerprint} for {host_name} was rejected"
+                ))
+                .into()),
+            },
+        }
+    }
 
-        // Auto-accept (TOFU model) - in production, check known_hosts
-        Ok(true)
+    async fn server_channel_open_forwarded_tcpip(
+        &mut self,
+        channel: Channel<client::Msg>,
+        connected_address: &str,
+        connected_port: u32,
+        originator_address: &str,
+        originator_port: u32,
+        _session: &mut client::Session,
+    ) -> Result<(), Self::Error> {
+        match tunnel::lookup_remote_forward(&self.remote_forwards, connected_address, connected_port) {
+            Some(target) => {
+                log::info!(
+                    "Remote forward {connected_address}:{connected_port} from \
+                     {originator_address}:{originator_port} -> {}:{}",
+                    target.local_host,
+                    target.local_port
+                );
+                // The tunnel task runs the connection, unless it was just stopped
+                if let Err(e) = target.channels.try_send(channel) {
+                    let _ = e.into_inner().close().await;
+                }
+            }
+            None => {
+                log::warn!(
+                    "Server opened a forwarded channel for unknown listener \
+                     {connected_address}:{connected_port}"
+                );
+                let _ = channel.close().await;
+            }
+        }
+        Ok(())
     }
 
+    async fn server_channel_open_agent_forward(
+        &mut self,
+        channel: Channel<client::Msg>,
+        _session: &mut client::Session,
+    ) -> Result<(), Self::Error> {
+        if let Some(choice) = self.agent_forwarding {
+            tokio::spawn(agent::forward_to_local_agent(channel, choice));
+        } else {
+            log::warn!("Server opened an agent channel but agent forwarding is disabled");
+            let _ = channel.close().await;
+        }
+        Ok(())
+    }
+
+    async fn server_channel_open_x11(
+        &mut self,
+        channel: Channel<client::Msg>,
+        originator_address: &str,
+        originator_port: u32,
+        _session: &mut client::Session,
+    ) -> Result<(), Self::Error> {
+        match &self.x11 {
+            Some(forwarding) => {
+                log::debug!("X11 connection from {originator_address}:{originator_port}");
+                tokio::spawn(x11::forward_to_display(channel, forwarding.clone()));
+            }
+            None => {
+                log::warn!("Server opened an X11 channel but X11 forwarding is disabled");
+                let _ = channel.close().await;
+            }
+        }
+        Ok(())
+    }
 }
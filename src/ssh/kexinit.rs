@@ -0,0 +1,267 @@
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// `SSH_MSG_KEXINIT`
+const MSG_KEXINIT: u8 = 20;
+/// Give up on servers whose greeting does not fit, RFC 4253 allows
+/// packets of 35000 bytes
+const MAX_CAPTURE: usize = 64 * 1024;
+
+/// The algorithm lists from the server's first KEXINIT packet. Only the
+/// client-to-server direction is kept for ciphers, MACs and compression.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerKexInit {
+    pub kex: Vec<String>,
+    pub host_key: Vec<String>,
+    pub cipher: Vec<String>,
+    pub mac: Vec<String>,
+    pub compression: Vec<String>,
+}
+
+/// Where a [`KexInitSniffer`] leaves what it saw
+pub type KexInitSlot = Arc<Mutex<Option<ServerKexInit>>>;
+
+/// Transport wrapper that reads along until the server's KEXINIT went by.
+/// russh does not expose the negotiated algorithms, but the server's offer
+/// together with our own preference list determines them.
+pub struct KexInitSniffer<S> {
+    inner: S,
+    /// Bytes received so far, `None` once done
+    captured: Option<Vec<u8>>,
+    slot: KexInitSlot,
+}
+
+impl<S> KexInitSniffer<S> {
+    pub fn new(inner: S, slot: KexInitSlot) -> Self {
+        Self {
+            inner,
+            captured: Some(Vec::new()),
+            slot,
+        }
+    }
+
+    fn observe(&mut self, data: &[u8]) {
+        let Some(captured) = self.captured.as_mut() else {
+            return;
+        };
+        captured.extend_from_slice(data);
+        match parse_greeting(captured) {
+            Parsed::Incomplete if captured.len() <= MAX_CAPTURE => {}
+            Parsed::Incomplete | Parsed::Invalid => {
+                log::debug!("Could not read the server's KEXINIT");
+                self.captured = None;
+            }
+            Parsed::Done(kexinit) => {
+                *self.slot.lock().unwrap() = Some(kexinit);
+                self.captured = None;
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for KexInitSniffer<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            this.observe(&buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for KexInitSniffer<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+enum Parsed {
+    Incomplete,
+    Invalid,
+    Done(ServerKexInit),
+}
+
+/// Parse the start of the server's side of a connection: optional banner
+/// lines, the `SSH-2.0-...` identification line, then the first binary
+/// packet, which is always an unencrypted KEXINIT.
+fn parse_greeting(data: &[u8]) -> Parsed {
+    let mut pos = 0;
+    loop {
+        let Some(end) = data[pos..].iter().position(|&b| b == b'\n') else {
+            return Parsed::Incomplete;
+        };
+        let line = &data[pos..pos + end];
+        pos += end + 1;
+        if line.starts_with(b"SSH-") {
+            break;
+        }
+    }
+
+    let packet = &data[pos..];
+    if packet.len() < 5 {
+        return Parsed::Incomplete;
+    }
+    let length = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize;
+    let padding = packet[4] as usize;
+    if length > MAX_CAPTURE || padding + 1 > length {
+        return Parsed::Invalid;
+    }
+    if packet.len() < 4 + length {
+        return Parsed::Incomplete;
+    }
+
+    let payload = &packet[5..4 + length - padding];
+    match parse_kexinit(payload) {
+        Some(kexinit) => Parsed::Done(kexinit),
+        None => Parsed::Invalid,
+    }
+}
+
+fn parse_kexinit(payload: &[u8]) -> Option<ServerKexInit> {
+    let (&msg, rest) = payload.split_first()?;
+    if msg != MSG_KEXINIT {
+        return None;
+    }
+    // 16 byte cookie
+    let mut rest = rest.get(16..)?;
+
+    let mut lists = Vec::with_capacity(8);
+    for _ in 0..8 {
+        let (list, tail) = name_list(rest)?;
+        lists.push(list);
+        rest = tail;
+    }
+    let mut lists = lists.into_iter();
+    let kex = lists.next()?;
+    let host_key = lists.next()?;
+    let cipher = lists.next()?;
+    let _cipher_s2c = lists.next()?;
+    let mac = lists.next()?;
+    let _mac_s2c = lists.next()?;
+    let compression = lists.next()?;
+
+    Some(ServerKexInit {
+        kex,
+        host_key,
+        cipher,
+        mac,
+        compression,
+    })
+}
+
+/// Read one comma-separated `name-list` (RFC 4251, 5)
+fn name_list(data: &[u8]) -> Option<(Vec<String>, &[u8])> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let names = data.get(4..4 + len)?;
+    let names = std::str::from_utf8(names).ok()?;
+    let list = names
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some((list, &data[4 + len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEX: &str = "sntrup761x25519-sha512@openssh.com,curve25519-sha256,\
+        curve25519-sha256@libssh.org,ecdh-sha2-nistp256,diffie-hellman-group16-sha512,\
+        ext-info-s,kex-strict-s-v00@openssh.com";
+    const HOST_KEY: &str = "rsa-sha2-512,rsa-sha2-256,ecdsa-sha2-nistp256,ssh-ed25519";
+    const CIPHER: &str = "chacha20-poly1305@openssh.com,aes128-ctr,aes256-gcm@openssh.com";
+    const MAC: &str = "umac-64-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-256";
+    const COMPRESSION: &str = "none,zlib@openssh.com";
+
+    /// What an OpenSSH 9.6 server sends before key exchange: a banner line,
+    /// its identification and the KEXINIT packet. The server-to-client lists
+    /// differ so a mix-up of the directions shows.
+    fn greeting() -> Vec<u8> {
+        let mut payload = vec![MSG_KEXINIT];
+        payload.extend_from_slice(&[0x5a; 16]);
+        for list in [KEX, HOST_KEY, CIPHER, "aes256-ctr", MAC, "hmac-sha1", COMPRESSION, "none", "", ""] {
+            payload.extend_from_slice(&(list.len() as u32).to_be_bytes());
+            payload.extend_from_slice(list.as_bytes());
+        }
+        // first_kex_packet_follows, reserved
+        payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+
+        // At least four bytes, up to a multiple of eight
+        let padding = 4 + (8 - (payload.len() + 9) % 8) % 8;
+        let mut data = b"Welcome to example.com\r\nSSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n".to_vec();
+        data.extend_from_slice(&((payload.len() + padding + 1) as u32).to_be_bytes());
+        data.push(padding as u8);
+        data.extend_from_slice(&payload);
+        data.resize(data.len() + padding, 0);
+        data
+    }
+
+    fn names(list: &str) -> Vec<String> {
+        list.split(',').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_the_server_offer() {
+        let Parsed::Done(kexinit) = parse_greeting(&greeting()) else {
+            panic!("greeting not parsed");
+        };
+        assert_eq!(
+            kexinit,
+            ServerKexInit {
+                kex: names(KEX),
+                host_key: names(HOST_KEY),
+                cipher: names(CIPHER),
+                mac: names(MAC),
+                compression: names(COMPRESSION),
+            }
+        );
+    }
+
+    #[test]
+    fn sniffer_waits_for_the_whole_packet() {
+        let greeting = greeting();
+        let slot = KexInitSlot::default();
+        let mut sniffer = KexInitSniffer::new((), slot.clone());
+        for chunk in greeting.chunks(7) {
+            assert!(slot.lock().unwrap().is_none());
+            sniffer.observe(chunk);
+        }
+        assert_eq!(slot.lock().unwrap().as_ref().unwrap().host_key, names(HOST_KEY));
+        assert!(sniffer.captured.is_none());
+    }
+
+    #[test]
+    fn rejects_other_packets() {
+        let mut greeting = greeting();
+        let start = greeting.iter().position(|&b| b == b'\n').unwrap() + 1;
+        let start = start + greeting[start..].iter().position(|&b| b == b'\n').unwrap() + 1;
+        assert!(matches!(parse_greeting(&greeting[..start + 40]), Parsed::Incomplete));
+
+        // SSH_MSG_IGNORE instead of KEXINIT
+        greeting[start + 5] = 2;
+        assert!(matches!(parse_greeting(&greeting), Parsed::Invalid));
+    }
+}
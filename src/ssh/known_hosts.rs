@@ -0,0 +1,358 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use ssh_key::{Algorithm, EcdsaCurve, HashAlg, PublicKey};
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+
+/// Result of looking up a server key in the known hosts file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostKeyStatus {
+    /// The key matches a stored entry for this host.
+    Trusted,
+    /// No key of this type is stored for this host yet.
+    Unknown,
+    /// A different key of the same type is stored for this host.
+    Changed { line: usize },
+    /// The key is explicitly marked as `@revoked`.
+    Revoked { line: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Marker {
+    CertAuthority,
+    Revoked,
+}
+
+#[derive(Debug, Clone)]
+enum HostPatterns {
+    /// `|1|salt|hash` entry written with `HashKnownHosts yes`
+    Hashed { salt: Vec<u8>, hash: Vec<u8> },
+    /// Comma-separated list of plain patterns, possibly with wildcards and negation
+    Plain(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+struct KnownHostEntry {
+    line: usize,
+    marker: Option<Marker>,
+    patterns: HostPatterns,
+    key_type: String,
+    key_data: Vec<u8>,
+}
+
+/// An OpenSSH-format known_hosts file.
+#[derive(Debug)]
+pub struct KnownHosts {
+    path: PathBuf,
+    entries: Vec<KnownHostEntry>,
+}
+
+impl KnownHosts {
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(data) => parse_entries(&data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                log::warn!("Failed to read known hosts {}: {e}", path.display());
+                Vec::new()
+            }
+        };
+        Self { path, entries }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Check a server key against all entries matching `host` and `port`.
+    pub fn check(&self, host: &str, port: u16, key: &PublicKey) -> HostKeyStatus {
+        let key_type = key.algorithm().as_str().to_string();
+        let key_data = match key.to_bytes() {
+            Ok(data) => data,
+            Err(_) => return HostKeyStatus::Unknown,
+        };
+
+        let mut changed_line = None;
+        for entry in self.entries.iter().filter(|e| e.matches(host, port)) {
+            match entry.marker {
+                Some(Marker::Revoked) => {
+                    if entry.key_data == key_data {
+                        return HostKeyStatus::Revoked { line: entry.line };
+                    }
+                }
+                Some(Marker::CertAuthority) => {}
+                None => {
+                    if entry.key_data == key_data {
+                        return HostKeyStatus::Trusted;
+                    }
+                    if entry.key_type == key_type && changed_line.is_none() {
+                        changed_line = Some(entry.line);
+                    }
+                }
+            }
+        }
+
+        match changed_line {
+            Some(line) => HostKeyStatus::Changed { line },
+            None => HostKeyStatus::Unknown,
+        }
+    }
+
+    /// Whether a `@cert-authority` line covers `host` and `port`. Host
+    /// certificates are not supported, so such a host still has its plain
+    /// key checked, and the user should know why they are asked.
+    pub fn has_cert_authority(&self, host: &str, port: u16) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.marker == Some(Marker::CertAuthority) && e.matches(host, port))
+    }
+
+    /// Append a new entry for `host` and `port` and write it to disk.
+    pub fn add(&mut self, host: &str, port: u16, key: &PublicKey) -> Result<(), AppError> {
+        let host_pattern = host_pattern(host, port);
+        let mut key = key.clone();
+        key.set_comment("");
+        let openssh = key.to_openssh()?;
+        let line = format!("{host_pattern} {openssh}\n");
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+
+        let line_no = self.entries.iter().map(|e| e.line).max().unwrap_or(0) + 1;
+        self.entries.extend(parse_line(line_no, line.trim_end()));
+        Ok(())
+    }
+}
+
+impl KnownHostEntry {
+    fn matches(&self, host: &str, port: u16) -> bool {
+        let candidate = host_pattern(&host.to_lowercase(), port);
+        match &self.patterns {
+            HostPatterns::Hashed { salt, hash } => hash_host(salt, &candidate) == *hash,
+            HostPatterns::Plain(patterns) => {
+                let mut matched = false;
+                for pattern in patterns {
+                    if let Some(negated) = pattern.strip_prefix('!') {
+                        if wildcard_match(negated, &candidate) {
+                            return false;
+                        }
+                    } else if wildcard_match(pattern, &candidate) {
+                        matched = true;
+                    }
+                }
+                matched
+            }
+        }
+    }
+}
+
+/// The name OpenSSH uses for a host in known_hosts: bare hostname on
+/// port 22, `[host]:port` otherwise.
+pub fn host_pattern(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{host}]:{port}")
+    }
+}
+
+/// Render the SHA256 fingerprint of a key the way `ssh-keygen -l` does.
+pub fn fingerprint(key: &PublicKey) -> String {
+    key.fingerprint(HashAlg::Sha256).to_string()
+}
+
+/// Render the OpenSSH "visual host key" for a key.
+pub fn randomart(key: &PublicKey) -> String {
+    let header = match key.algorithm() {
+        Algorithm::Ed25519 => "[ED25519 256]",
+        Algorithm::Ecdsa { curve: EcdsaCurve::NistP256 } => "[ECDSA 256]",
+        Algorithm::Ecdsa { curve: EcdsaCurve::NistP384 } => "[ECDSA 384]",
+        Algorithm::Ecdsa { curve: EcdsaCurve::NistP521 } => "[ECDSA 521]",
+        Algorithm::Rsa { .. } => "[RSA]",
+        _ => "[KEY]",
+    };
+    key.fingerprint(HashAlg::Sha256).to_randomart(header)
+}
+
+fn parse_entries(data: &str) -> Vec<KnownHostEntry> {
+    data.lines()
+        .enumerate()
+        .filter_map(|(idx, line)| parse_line(idx + 1, line))
+        .collect()
+}
+
+fn parse_line(line_no: usize, line: &str) -> Option<KnownHostEntry> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.split_whitespace();
+    let mut first = fields.next()?;
+    let marker = match first {
+        "@cert-authority" => Some(Marker::CertAuthority),
+        "@revoked" => Some(Marker::Revoked),
+        other if other.starts_with('@') => return None,
+        _ => None,
+    };
+    if marker.is_some() {
+        first = fields.next()?;
+    }
+
+    let patterns = if let Some(hashed) = first.strip_prefix("|1|") {
+        let (salt, hash) = hashed.split_once('|')?;
+        HostPatterns::Hashed {
+            salt: BASE64.decode(salt).ok()?,
+            hash: BASE64.decode(hash).ok()?,
+        }
+    } else {
+        HostPatterns::Plain(first.split(',').map(|p| p.to_lowercase()).collect())
+    };
+
+    let key_type = fields.next()?.to_string();
+    let key_data = BASE64.decode(fields.next()?).ok()?;
+
+    Some(KnownHostEntry {
+        line: line_no,
+        marker,
+        patterns,
+        key_type,
+        key_data,
+    })
+}
+
+fn hash_host(salt: &[u8], host: &str) -> Vec<u8> {
+    let mut mac = match Hmac::<Sha1>::new_from_slice(salt) {
+        Ok(mac) => mac,
+        Err(_) => return Vec::new(),
+    };
+    mac.update(host.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Match an OpenSSH host pattern supporting `*` and `?` wildcards.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIPD/WiucYmO1w23d68aZ+eJOUq+a8o2aRrajHS0uQ/Dt";
+    const KEY_B: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIO7Co9PkO0jxHZ5uE12QRmSHvsp1LqfRtb3/gamETJBK";
+
+    fn known_hosts(data: &str) -> KnownHosts {
+        KnownHosts {
+            path: PathBuf::new(),
+            entries: parse_entries(data),
+        }
+    }
+
+    fn key(openssh: &str) -> PublicKey {
+        PublicKey::from_openssh(openssh).unwrap()
+    }
+
+    #[test]
+    fn hashed_entries_match_host_and_port() {
+        // KEY_A for example.com and [example.com]:2222, hashed by `ssh-keygen -H`
+        let hosts = known_hosts(&format!(
+            "|1|xUkXuv6mCWg17QNXLnt5FvWqPIA=|QmnHrFGnhs+FJDPmfb+wu3RbBjU= {KEY_A}\n\
+             |1|+hiBH0Vx8PEClQ5DY2rvSjAOG3s=|nG+o6a17Hwi3NjUpvnJoIemLX5Q= {KEY_A}\n"
+        ));
+        let a = key(KEY_A);
+        assert_eq!(hosts.check("example.com", 22, &a), HostKeyStatus::Trusted);
+        assert_eq!(hosts.check("Example.COM", 2222, &a), HostKeyStatus::Trusted);
+        assert_eq!(hosts.check("example.com", 2200, &a), HostKeyStatus::Unknown);
+        assert_eq!(hosts.check("example.org", 22, &a), HostKeyStatus::Unknown);
+        assert_eq!(
+            hosts.check("example.com", 2222, &key(KEY_B)),
+            HostKeyStatus::Changed { line: 2 }
+        );
+    }
+
+    #[test]
+    fn negated_patterns_exclude_hosts() {
+        let hosts = known_hosts(&format!(
+            "*.example.com,!secret.example.com {KEY_A}\n\
+             [*.example.com]:2222 {KEY_B}\n"
+        ));
+        let a = key(KEY_A);
+        assert_eq!(hosts.check("www.example.com", 22, &a), HostKeyStatus::Trusted);
+        assert_eq!(hosts.check("secret.example.com", 22, &a), HostKeyStatus::Unknown);
+        // The port is part of the name, the port 22 pattern does not apply
+        assert_eq!(hosts.check("www.example.com", 2222, &a), HostKeyStatus::Changed { line: 2 });
+        assert_eq!(hosts.check("www.example.com", 2222, &key(KEY_B)), HostKeyStatus::Trusted);
+    }
+
+    #[test]
+    fn markers_never_trust_a_key() {
+        let hosts = known_hosts(&format!(
+            "@revoked * {KEY_B}\n\
+             @cert-authority *.example.com {KEY_A}\n\
+             # comment\n\
+             @unknown-marker example.com {KEY_A}\n"
+        ));
+        assert_eq!(
+            hosts.check("example.com", 22, &key(KEY_B)),
+            HostKeyStatus::Revoked { line: 1 }
+        );
+        assert_eq!(hosts.check("www.example.com", 22, &key(KEY_A)), HostKeyStatus::Unknown);
+        assert_eq!(hosts.check("example.com", 22, &key(KEY_A)), HostKeyStatus::Unknown);
+        assert!(hosts.has_cert_authority("www.example.com", 22));
+        assert!(!hosts.has_cert_authority("example.com", 22));
+    }
+
+    #[test]
+    fn host_pattern_brackets_non_default_ports() {
+        assert_eq!(host_pattern("example.com", 22), "example.com");
+        assert_eq!(host_pattern("example.com", 2222), "[example.com]:2222");
+        assert_eq!(host_pattern("::1", 2222), "[::1]:2222");
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("*.example.com", "www.example.com"));
+        assert!(wildcard_match("host?", "host1"));
+        assert!(!wildcard_match("host?", "host10"));
+        assert!(wildcard_match("[*]:2222", "[10.0.0.1]:2222"));
+        assert!(!wildcard_match("*.example.com", "example.com"));
+    }
+}
@@ -1,5 +1,15 @@
+pub mod agent;
 pub mod algorithms;
+pub mod ansi;
 pub mod handler;
+pub mod kexinit;
+pub mod known_hosts;
+pub mod options;
+pub mod pool;
+pub mod pty;
 pub mod session;
 pub mod sftp;
+pub mod socks;
+pub mod startup;
 pub mod tunnel;
+pub mod x11;
@@ -0,0 +1,72 @@
+use russh::{client, Preferred, Pty};
+
+use std::time::Duration;
+
+use crate::config::Settings;
+use crate::models::connection::{ConnectionProfile, EnvVar};
+use crate::ssh::algorithms::preferred_algorithms;
+use crate::ssh::pty;
+
+/// Used when neither the profile nor the settings name a terminal type
+const DEFAULT_TERMINAL_TYPE: &str = "xterm-256color";
+
+/// Connection settings for one hop, from the global settings with the
+/// profile's overrides applied.
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    pub keepalive_interval: Option<Duration>,
+    /// Unanswered keepalives before the peer is considered dead
+    pub keepalive_max: usize,
+    pub inactivity_timeout: Option<Duration>,
+    pub algorithms: Preferred,
+    /// `TERM` announced with the PTY request
+    pub terminal_type: String,
+    pub environment: Vec<EnvVar>,
+    pub terminal_modes: Vec<(Pty, u32)>,
+}
+
+impl SessionOptions {
+    pub fn resolve(settings: &Settings, profile: &ConnectionProfile) -> Self {
+        let interval = profile
+            .keepalive_interval_secs
+            .unwrap_or(settings.keepalive_interval_secs);
+        let max = profile.keepalive_max.unwrap_or(settings.keepalive_max);
+        let inactivity = profile
+            .inactivity_timeout_secs
+            .unwrap_or(settings.inactivity_timeout_secs);
+        let terminal_type = [
+            profile.terminal_type.as_deref(),
+            Some(settings.default_terminal_type.as_str()),
+        ]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .find(|term| !term.is_empty())
+        .unwrap_or(DEFAULT_TERMINAL_TYPE);
+
+        Self {
+            keepalive_interval: seconds(interval),
+            keepalive_max: max as usize,
+            inactivity_timeout: seconds(inactivity),
+            algorithms: preferred_algorithms(&settings.algorithms, &profile.algorithms),
+            terminal_type: terminal_type.to_string(),
+            environment: profile.environment.clone(),
+            terminal_modes: pty::modes(&profile.terminal_modes),
+        }
+    }
+
+    pub fn client_config(&self) -> client::Config {
+        client::Config {
+            preferred: self.algorithms.clone(),
+            keepalive_interval: self.keepalive_interval,
+            keepalive_max: self.keepalive_max,
+            inactivity_timeout: self.inactivity_timeout,
+            ..Default::default()
+        }
+    }
+}
+
+/// 0 disables a timer
+fn seconds(secs: u32) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(u64::from(secs)))
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use russh::client;
+use russh::Disconnect;
+use tokio::sync::{Mutex, OnceCell};
+use uuid::Uuid;
+
+use crate::app::{ConnectionChain, DisconnectReason, SshEvent};
+use crate::error::AppError;
+use crate::config::Settings;
+use crate::models::connection::{AgentChoice, AlgorithmPreferences, AuthMethod, ConnectionProfile};
+use crate::ssh::algorithms::{preferred_names, NegotiatedAlgorithms};
+use crate::ssh::handler::ClientHandler;
+use crate::ssh::options::SessionOptions;
+use crate::ssh::session::{connection_end_reason, establish_session};
+use crate::ssh::tunnel::RemoteForwardTargets;
+use crate::ssh::x11::X11Forwarding;
+
+static POOL: OnceLock<ConnectionPool> = OnceLock::new();
+
+/// The connection pool shared by all tabs
+pub fn pool() -> &'static ConnectionPool {
+    POOL.get_or_init(ConnectionPool::default)
+}
+
+/// Identifies a pooled connection: every hop from the first jump host to the
+/// destination. What is set up per connection is part of the key, so editing
+/// it in a profile or in the settings opens a new connection instead of
+/// reusing the old one. Secrets are not: a connection made with an old
+/// password stays usable after the password is changed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    hops: Vec<HopKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct HopKey {
+    id: Uuid,
+    hostname: String,
+    port: u16,
+    username: String,
+    auth_method: AuthMethod,
+    key_pair_id: Option<Uuid>,
+    agent: AgentChoice,
+    agent_forwarding: bool,
+    x11_forwarding: bool,
+    keepalive_interval: Option<Duration>,
+    keepalive_max: usize,
+    inactivity_timeout: Option<Duration>,
+    /// The algorithm lists offered, after the settings and the profile's
+    /// overrides are combined
+    algorithms: AlgorithmPreferences,
+}
+
+impl PoolKey {
+    /// Key for a connection through `profiles`, jump hosts first
+    pub fn new<'a>(
+        settings: &Settings,
+        profiles: impl IntoIterator<Item = &'a ConnectionProfile>,
+    ) -> Self {
+        Self {
+            hops: profiles
+                .into_iter()
+                .map(|p| {
+                    let options = SessionOptions::resolve(settings, p);
+                    HopKey {
+                        id: p.id,
+                        hostname: p.hostname.clone(),
+                        port: p.port,
+                        username: p.username.clone(),
+                        auth_method: p.auth_method.clone(),
+                        key_pair_id: p.key_pair_id,
+                        agent: p.agent,
+                        agent_forwarding: p.agent_forwarding,
+                        x11_forwarding: p.x11_forwarding,
+                        keepalive_interval: options.keepalive_interval,
+                        keepalive_max: options.keepalive_max,
+                        inactivity_timeout: options.inactivity_timeout,
+                        algorithms: preferred_names(&options.algorithms),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn for_chain(chain: &ConnectionChain) -> Self {
+        Self::new(&chain.settings, chain.hops().map(|hop| &hop.profile))
+    }
+}
+
+/// An authenticated connection that any number of channels are opened on
+pub struct SharedConnection {
+    pub handle: Arc<Mutex<client::Handle<ClientHandler>>>,
+    /// Remote forwards of every tab using the connection, since the server
+    /// sends all their channels to the same handler
+    pub remote_forwards: RemoteForwardTargets,
+    pub negotiated: Option<NegotiatedAlgorithms>,
+    /// Set when the profile forwards X11 and a local display was found
+    pub x11: Option<Arc<X11Forwarding>>,
+    /// The chain the connection was made with, so tabs joining it can skip
+    /// the credential prompts and still reconnect on their own later
+    chain: ConnectionChain,
+    /// russh reports why a connection ended only once, the first user to
+    /// ask keeps the answer for the others
+    end_reason: OnceCell<DisconnectReason>,
+}
+
+impl SharedConnection {
+    /// Why the connection ended, for a channel that vanished
+    pub async fn end_reason(&self) -> DisconnectReason {
+        self.end_reason
+            .get_or_init(|| async {
+                let mut handle = self.handle.lock().await;
+                connection_end_reason(&mut handle).await
+            })
+            .await
+            .clone()
+    }
+
+    /// A connection busy with a request is taken to be open
+    fn is_closed(&self) -> bool {
+        self.handle
+            .try_lock()
+            .map(|handle| handle.is_closed())
+            .unwrap_or(false)
+    }
+
+    async fn disconnect(&self, reason: &str) {
+        let handle = self.handle.lock().await;
+        if let Err(e) = handle.disconnect(Disconnect::ByApplication, reason, "en").await {
+            log::debug!("Disconnect failed: {e}");
+        }
+    }
+}
+
+struct Entry {
+    connection: Arc<SharedConnection>,
+    /// Leases handed out and not yet dropped
+    users: usize,
+    /// Bumped on every lease, so an idle timer started before can tell the
+    /// connection was used again
+    generation: u64,
+    /// How long the connection stays open after its last lease is dropped
+    idle_timeout: Duration,
+}
+
+/// Authenticated connections shared between the terminal, SFTP and tunnel
+/// users of a profile, like OpenSSH's ControlMaster. Connections are
+/// reference counted by their leases and closed after an idle timeout.
+#[derive(Default)]
+pub struct ConnectionPool {
+    entries: std::sync::Mutex<HashMap<PoolKey, Entry>>,
+    /// Held while a connection for a key is being made, so tabs opened at
+    /// the same time wait for it instead of each authenticating. Removed
+    /// once nobody is waiting.
+    connecting: std::sync::Mutex<HashMap<PoolKey, Arc<Mutex<()>>>>,
+}
+
+impl ConnectionPool {
+    /// Lease a connection for `chain`, reusing an open one when connection
+    /// sharing is enabled. Prompts while connecting go to `event_tx`.
+    pub async fn acquire(
+        &'static self,
+        chain: &ConnectionChain,
+        event_tx: async_channel::Sender<SshEvent>,
+    ) -> Result<ConnectionLease, AppError> {
+        if !chain.settings.share_connections {
+            let connection = Arc::new(connect(chain, event_tx).await?);
+            return Ok(ConnectionLease {
+                connection,
+                pooled: None,
+            });
+        }
+
+        let key = PoolKey::for_chain(chain);
+        let connecting = self
+            .connecting
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let result = {
+            let _connecting = connecting.lock().await;
+            self.reuse_or_connect(&key, chain, event_tx).await
+        };
+
+        // The last one through takes the lock out of the map again, unless
+        // another tab started waiting on it meanwhile
+        let mut waiting = self.connecting.lock().unwrap();
+        if waiting
+            .get(&key)
+            .is_some_and(|lock| Arc::ptr_eq(lock, &connecting) && Arc::strong_count(lock) == 2)
+        {
+            waiting.remove(&key);
+        }
+        result
+    }
+
+    async fn reuse_or_connect(
+        &'static self,
+        key: &PoolKey,
+        chain: &ConnectionChain,
+        event_tx: async_channel::Sender<SshEvent>,
+    ) -> Result<ConnectionLease, AppError> {
+        if let Some(lease) = self.reuse(key) {
+            log::info!("Reusing the connection to {}", chain.profile().hostname);
+            return Ok(lease);
+        }
+
+        let connection = Arc::new(connect(chain, event_tx).await?);
+        self.entries.lock().unwrap().insert(
+            key.clone(),
+            Entry {
+                connection: connection.clone(),
+                users: 1,
+                generation: 0,
+                idle_timeout: idle_timeout(chain),
+            },
+        );
+        Ok(ConnectionLease {
+            connection,
+            pooled: Some((self, key.clone())),
+        })
+    }
+
+    /// The chain of the open connection for `key`, if there is one. Its
+    /// credentials let a new tab join the connection without prompting.
+    pub fn live_chain(&self, key: &PoolKey) -> Option<ConnectionChain> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|entry| !entry.connection.is_closed())
+            .map(|entry| entry.connection.chain.clone())
+    }
+
+    /// Take a lease on the pooled connection for `key` if it is still open
+    fn reuse(&'static self, key: &PoolKey) -> Option<ConnectionLease> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(key)?;
+        if entry.connection.is_closed() {
+            entries.remove(key);
+            return None;
+        }
+        entry.users += 1;
+        entry.generation += 1;
+        Some(ConnectionLease {
+            connection: entry.connection.clone(),
+            pooled: Some((self, key.clone())),
+        })
+    }
+
+    /// Count one more user of a pooled connection
+    fn add_user(&self, key: &PoolKey, connection: &Arc<SharedConnection>) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(key) {
+            if Arc::ptr_eq(&entry.connection, connection) {
+                entry.users += 1;
+                entry.generation += 1;
+            }
+        }
+    }
+
+    /// Give back a lease, starting the idle timer when it was the last one
+    fn release(&'static self, key: &PoolKey, connection: &Arc<SharedConnection>) {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(key) else {
+            return;
+        };
+        // The connection was replaced after it closed
+        if !Arc::ptr_eq(&entry.connection, connection) {
+            return;
+        }
+        entry.users = entry.users.saturating_sub(1);
+        if entry.users > 0 {
+            return;
+        }
+
+        let key = key.clone();
+        let generation = entry.generation;
+        let idle_timeout = entry.idle_timeout;
+        crate::runtime().spawn(async move {
+            tokio::time::sleep(idle_timeout).await;
+            self.expire(&key, generation).await;
+        });
+    }
+
+    /// Close the connection for `key` unless it was leased again since the
+    /// idle timer started
+    async fn expire(&self, key: &PoolKey, generation: u64) {
+        let connection = {
+            let mut entries = self.entries.lock().unwrap();
+            let unused = entries
+                .get(key)
+                .is_some_and(|entry| entry.users == 0 && entry.generation == generation);
+            if unused {
+                entries.remove(key).map(|entry| entry.connection)
+            } else {
+                None
+            }
+        };
+        if let Some(connection) = connection {
+            log::info!("Closing idle connection to {}", connection.chain.profile().hostname);
+            connection.disconnect("Connection idle").await;
+        }
+    }
+}
+
+/// A share of a connection. The connection stays open while any lease on it
+/// exists, and for the idle timeout after the last one is dropped.
+pub struct ConnectionLease {
+    connection: Arc<SharedConnection>,
+    /// `None` when connection sharing is off and the connection belongs to
+    /// the leases of one tab and the tabs opened from it
+    pooled: Option<(&'static ConnectionPool, PoolKey)>,
+}
+
+impl ConnectionLease {
+    /// Another lease on the same connection, whether it is pooled or not
+    pub fn share(&self) -> ConnectionLease {
+        if let Some((pool, key)) = &self.pooled {
+            pool.add_user(key, &self.connection);
+        }
+        ConnectionLease {
+            connection: self.connection.clone(),
+            pooled: self.pooled.clone(),
+        }
+    }
+}
+
+impl Deref for ConnectionLease {
+    type Target = SharedConnection;
+
+    fn deref(&self) -> &SharedConnection {
+        &self.connection
+    }
+}
+
+impl Drop for ConnectionLease {
+    fn drop(&mut self) {
+        match &self.pooled {
+            Some((pool, key)) => pool.release(key, &self.connection),
+            // The last lease closes an unpooled connection
+            None if Arc::strong_count(&self.connection) == 1 => {
+                let connection = self.connection.clone();
+                crate::runtime().spawn(async move {
+                    connection.disconnect("User disconnected").await;
+                });
+            }
+            None => {}
+        }
+    }
+}
+
+async fn connect(
+    chain: &ConnectionChain,
+    event_tx: async_channel::Sender<SshEvent>,
+) -> Result<SharedConnection, AppError> {
+    // Without a display the session still works, just without X11
+    let x11 = match chain.profile().x11_forwarding {
+        true => match X11Forwarding::from_environment().await {
+            Ok(x11) => Some(Arc::new(x11)),
+            Err(e) => {
+                let msg = format!("X11 forwarding disabled: {e}");
+                let _ = event_tx.send(SshEvent::Error(msg)).await;
+                None
+            }
+        },
+        false => None,
+    };
+
+    let remote_forwards = RemoteForwardTargets::default();
+    let (handle, negotiated) =
+        establish_session(chain, remote_forwards.clone(), x11.clone(), event_tx).await?;
+    Ok(SharedConnection {
+        handle: Arc::new(Mutex::new(handle)),
+        remote_forwards,
+        negotiated,
+        x11,
+        chain: chain.clone(),
+        end_reason: OnceCell::new(),
+    })
+}
+
+fn idle_timeout(chain: &ConnectionChain) -> Duration {
+    Duration::from_secs(u64::from(chain.settings.connection_idle_secs))
+}
@@ -0,0 +1,121 @@
+use russh::Pty;
+
+use crate::models::connection::TerminalMode;
+
+/// Terminal modes that can be configured, by their RFC 4254 names (IUTF8
+/// is from RFC 8160)
+const MODES: &[(&str, Pty)] = &[
+    ("VINTR", Pty::VINTR),
+    ("VQUIT", Pty::VQUIT),
+    ("VERASE", Pty::VERASE),
+    ("VKILL", Pty::VKILL),
+    ("VEOF", Pty::VEOF),
+    ("VEOL", Pty::VEOL),
+    ("VEOL2", Pty::VEOL2),
+    ("VSTART", Pty::VSTART),
+    ("VSTOP", Pty::VSTOP),
+    ("VSUSP", Pty::VSUSP),
+    ("VDSUSP", Pty::VDSUSP),
+    ("VREPRINT", Pty::VREPRINT),
+    ("VWERASE", Pty::VWERASE),
+    ("VLNEXT", Pty::VLNEXT),
+    ("VFLUSH", Pty::VFLUSH),
+    ("VSWTCH", Pty::VSWTCH),
+    ("VSTATUS", Pty::VSTATUS),
+    ("VDISCARD", Pty::VDISCARD),
+    ("IGNPAR", Pty::IGNPAR),
+    ("PARMRK", Pty::PARMRK),
+    ("INPCK", Pty::INPCK),
+    ("ISTRIP", Pty::ISTRIP),
+    ("INLCR", Pty::INLCR),
+    ("IGNCR", Pty::IGNCR),
+    ("ICRNL", Pty::ICRNL),
+    ("IUCLC", Pty::IUCLC),
+    ("IXON", Pty::IXON),
+    ("IXANY", Pty::IXANY),
+    ("IXOFF", Pty::IXOFF),
+    ("IMAXBEL", Pty::IMAXBEL),
+    ("IUTF8", Pty::IUTF8),
+    ("ISIG", Pty::ISIG),
+    ("ICANON", Pty::ICANON),
+    ("XCASE", Pty::XCASE),
+    ("ECHO", Pty::ECHO),
+    ("ECHOE", Pty::ECHOE),
+    ("ECHOK", Pty::ECHOK),
+    ("ECHONL", Pty::ECHONL),
+    ("NOFLSH", Pty::NOFLSH),
+    ("TOSTOP", Pty::TOSTOP),
+    ("IEXTEN", Pty::IEXTEN),
+    ("ECHOCTL", Pty::ECHOCTL),
+    ("ECHOKE", Pty::ECHOKE),
+    ("PENDIN", Pty::PENDIN),
+    ("OPOST", Pty::OPOST),
+    ("OLCUC", Pty::OLCUC),
+    ("ONLCR", Pty::ONLCR),
+    ("OCRNL", Pty::OCRNL),
+    ("ONOCR", Pty::ONOCR),
+    ("ONLRET", Pty::ONLRET),
+    ("CS7", Pty::CS7),
+    ("CS8", Pty::CS8),
+    ("PARENB", Pty::PARENB),
+    ("PARODD", Pty::PARODD),
+    ("TTY_OP_ISPEED", Pty::TTY_OP_ISPEED),
+    ("TTY_OP_OSPEED", Pty::TTY_OP_OSPEED),
+];
+
+/// The modes in the form `request_pty` takes. Unknown names are skipped.
+pub fn modes(modes: &[TerminalMode]) -> Vec<(Pty, u32)> {
+    modes
+        .iter()
+        .filter_map(|mode| match lookup(&mode.name) {
+            Some(pty) => Some((pty, mode.value)),
+            None => {
+                log::warn!("Ignoring unknown terminal mode {}", mode.name);
+                None
+            }
+        })
+        .collect()
+}
+
+fn lookup(name: &str) -> Option<Pty> {
+    MODES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|(_, pty)| *pty)
+}
+
+/// Parse a list like `VERASE=127 ICRNL=1`, separated by spaces or commas.
+/// Control characters may also be written as `^H` or `^?`.
+pub fn parse_modes(text: &str) -> Result<Vec<TerminalMode>, String> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (name, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("\"{item}\" is not NAME=value"))?;
+            let name = name.to_ascii_uppercase();
+            if lookup(&name).is_none() {
+                return Err(format!("Unknown terminal mode {name}"));
+            }
+            let value = parse_value(value).ok_or_else(|| format!("Invalid value for {name}"))?;
+            Ok(TerminalMode { name, value })
+        })
+        .collect()
+}
+
+fn parse_value(value: &str) -> Option<u32> {
+    match value.as_bytes() {
+        [b'^', b'?'] => Some(127),
+        [b'^', c] if c.is_ascii_alphabetic() => Some(u32::from(c.to_ascii_uppercase() - b'@')),
+        _ => value.parse().ok(),
+    }
+}
+
+/// The inverse of [`parse_modes`]
+pub fn format_modes(modes: &[TerminalMode]) -> String {
+    modes
+        .iter()
+        .map(|mode| format!("{}={}", mode.name, mode.value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,78 +1,192 @@
 use std::sync::Arc;
-use russh::client;
-use russh::{ChannelMsg, Disconnect};
-use tokio::sync::Mutex;
+use std::time::Duration;
+use russh::client::{self, KeyboardInteractiveAuthResponse};
+use russh::{ChannelMsg, ChannelStream};
+use tokio::net::TcpStream;
 use zeroize::Zeroizing;
 
-use crate::app::{SshCommand, SshEvent};
+use crate::app::{
+    AuthCredentials, AuthPrompt, AuthPromptField, ConnectionChain, DisconnectReason, SshCommand,
+    SshEvent,
+};
 use crate::error::AppError;
+use crate::keys::certificate;
 use crate::models::connection::{AuthMethod, ConnectionProfile};
-use crate::ssh::algorithms::preferred_algorithms;
+use crate::ssh::agent;
+use crate::ssh::algorithms::NegotiatedAlgorithms;
 use crate::ssh::handler::ClientHandler;
-use crate::ssh::tunnel;
+use crate::ssh::kexinit::{KexInitSlot, KexInitSniffer};
+use crate::ssh::options::SessionOptions;
+use crate::ssh::pool::{self, ConnectionLease};
+use crate::ssh::sftp;
+use crate::ssh::startup::StartupScript;
+use crate::ssh::tunnel::{RemoteForwardTargets, TunnelRegistry};
+use crate::ssh::x11::{self, X11Forwarding};
 use crate::storage::paths;
+use crate::storage::secrets::{self, SecretKind};
+use crate::storage::session_log::SessionLog;
 
-/// Establish an authenticated SSH session. Returns the session handle.
-/// This is shared between terminal sessions and SFTP sessions.
+/// How long to wait for russh's session task to report why it ended
+const SESSION_END_WAIT: Duration = Duration::from_secs(2);
+
+/// Establish an authenticated SSH session, hopping through the jump hosts
+/// of the chain first. Returns the session handle for the destination and
+/// the algorithms negotiated with it. Tabs get their connections through
+/// the connection pool rather than calling this directly.
 pub async fn establish_session(
-    profile: &ConnectionProfile,
-    password: Option<&Zeroizing<String>>,
-    key_passphrase: Option<&Zeroizing<String>>,
+    chain: &ConnectionChain,
+    remote_forwards: RemoteForwardTargets,
+    x11: Option<Arc<X11Forwarding>>,
     event_tx: async_channel::Sender<SshEvent>,
-) -> Result<client::Handle<ClientHandler>, AppError> {
-    let config = Arc::new(client::Config {
-        preferred: preferred_algorithms(),
-        ..Default::default()
+) -> Result<(client::Handle<ClientHandler>, Option<NegotiatedAlgorithms>), AppError> {
+    let mut jump_sessions: Vec<client::Handle<ClientHandler>> = Vec::new();
+    for hop in &chain.jump_hosts {
+        let result = async {
+            let stream = open_jump_stream(jump_sessions.last(), &hop.profile).await?;
+            let handler = ClientHandler::new(
+                event_tx.clone(),
+                hop.profile.hostname.clone(),
+                hop.profile.port,
+                RemoteForwardTargets::default(),
+            );
+            let config = SessionOptions::resolve(&chain.settings, &hop.profile).client_config();
+            let mut session =
+                connect_hop(Arc::new(config), &hop.profile, stream, handler, KexInitSlot::default())
+                    .await?;
+            authenticate(&mut session, &hop.profile, &hop.credentials, &event_tx).await?;
+            Ok::<_, AppError>(session)
+        }
+        .await;
+        let session = result.map_err(|e| jump_host_error(&hop.profile, e))?;
+        jump_sessions.push(session);
+    }
+
+    let target = &chain.target;
+    let stream = open_jump_stream(jump_sessions.last(), &target.profile).await?;
+    let mut handler = ClientHandler::new(
+        event_tx.clone(),
+        target.profile.hostname.clone(),
+        target.profile.port,
+        remote_forwards,
+    );
+    handler.jump_sessions = jump_sessions;
+    handler.agent_forwarding = target
+        .profile
+        .agent_forwarding
+        .then_some(target.profile.agent);
+    handler.x11 = x11;
+
+    let options = SessionOptions::resolve(&chain.settings, &target.profile);
+    let server_kexinit = KexInitSlot::default();
+    let mut session = connect_hop(
+        Arc::new(options.client_config()),
+        &target.profile,
+        stream,
+        handler,
+        server_kexinit.clone(),
+    )
+    .await?;
+    authenticate(&mut session, &target.profile, &target.credentials, &event_tx).await?;
+
+    let server_kexinit = server_kexinit.lock().unwrap().take();
+    let negotiated = server_kexinit.map(|server_kexinit| {
+        let negotiated = NegotiatedAlgorithms::negotiate(&options.algorithms, &server_kexinit);
+        log::info!("Negotiated with {}: {negotiated}", target.profile.hostname);
+        negotiated
     });
 
-    let handler = ClientHandler::new(event_tx.clone());
+    Ok((session, negotiated))
+}
 
-    let addr = format!("{}:{}", profile.hostname, profile.port);
-    let mut session = client::connect(config, &addr, handler)
+/// Open a `direct-tcpip` channel from the previous jump host to `profile`.
+/// Returns `None` when there is no jump host and the hop is reached directly.
+async fn open_jump_stream(
+    via: Option<&client::Handle<ClientHandler>>,
+    profile: &ConnectionProfile,
+) -> Result<Option<ChannelStream<client::Msg>>, AppError> {
+    let Some(via) = via else {
+        return Ok(None);
+    };
+    let channel = via
+        .channel_open_direct_tcpip(
+            profile.hostname.clone(),
+            profile.port as u32,
+            "127.0.0.1",
+            0,
+        )
         .await
-        .map_err(|e| AppError::Connection(e.to_string()))?;
+        .map_err(|e| {
+            AppError::Connection(format!(
+                "Failed to reach {}:{} through jump host: {e}",
+                profile.hostname, profile.port
+            ))
+        })?;
+    Ok(Some(channel.into_stream()))
+}
+
+/// Connect to one hop, over a channel of the previous hop or directly.
+/// The server's KEXINIT is left in `server_kexinit`.
+async fn connect_hop(
+    config: Arc<client::Config>,
+    profile: &ConnectionProfile,
+    stream: Option<ChannelStream<client::Msg>>,
+    handler: ClientHandler,
+    server_kexinit: KexInitSlot,
+) -> Result<client::Handle<ClientHandler>, AppError> {
+    match stream {
+        Some(stream) => {
+            let stream = KexInitSniffer::new(stream, server_kexinit);
+            client::connect_stream(config, stream, handler)
+                .await
+                .map_err(connect_error)
+        }
+        None => {
+            let addr = format!("{}:{}", profile.hostname, profile.port);
+            let socket = TcpStream::connect(&addr)
+                .await
+                .map_err(|e| AppError::Connection(format!("Cannot connect to {addr}: {e}")))?;
+            let _ = socket.set_nodelay(true);
+            let stream = KexInitSniffer::new(socket, server_kexinit);
+            client::connect_stream(config, stream, handler)
+                .await
+                .map_err(connect_error)
+        }
+    }
+}
+
+async fn authenticate(
+    session: &mut client::Handle<ClientHandler>,
+    profile: &ConnectionProfile,
+    credentials: &AuthCredentials,
+    event_tx: &async_channel::Sender<SshEvent>,
+) -> Result<(), AppError> {
+    let password = credentials.password.as_ref();
+    let key_passphrase = credentials.key_passphrase.as_ref();
+    // Whether the server got to check the password, so it is known to be right or wrong
+    let mut password_used = false;
 
-    // Authenticate
     let authenticated = match profile.auth_method {
         AuthMethod::Password => {
             let pw = password
                 .map(|p| p.as_str())
                 .ok_or_else(|| AppError::Auth("Password required".into()))?;
+            password_used = true;
             session
                 .authenticate_password(&profile.username, pw)
                 .await
                 .map_err(|e| AppError::Auth(e.to_string()))?
         }
         AuthMethod::PublicKey => {
-            let key_id = profile
-                .key_pair_id
-                .ok_or_else(|| AppError::Auth("No key pair selected".into()))?;
-            let key_path = paths::private_key_path(&key_id);
-            let key_pass = key_passphrase.map(|s| s.as_str());
-            let key_pair = russh_keys::load_secret_key(&key_path, key_pass)
-                .map_err(|e| AppError::Auth(e.to_string()))?;
-            session
-                .authenticate_publickey(&profile.username, Arc::new(key_pair))
-                .await
-                .map_err(|e| AppError::Auth(e.to_string()))?
+            authenticate_with_key(session, profile, key_passphrase).await?
         }
         AuthMethod::Both => {
-            let key_id = profile
-                .key_pair_id
-                .ok_or_else(|| AppError::Auth("No key pair selected".into()))?;
-            let key_path = paths::private_key_path(&key_id);
-            let key_pass = key_passphrase.map(|s| s.as_str());
-            let key_pair = russh_keys::load_secret_key(&key_path, key_pass)
-                .map_err(|e| AppError::Auth(e.to_string()))?;
-            let pk_ok = session
-                .authenticate_publickey(&profile.username, Arc::new(key_pair))
-                .await
-                .map_err(|e| AppError::Auth(e.to_string()))?;
+            let pk_ok = authenticate_with_key(session, profile, key_passphrase).await?;
 
             if !pk_ok {
                 let pw = password
                     .map(|p| p.as_str())
                     .ok_or_else(|| AppError::Auth("Password required for fallback".into()))?;
+                password_used = true;
                 session
                     .authenticate_password(&profile.username, pw)
                     .await
@@ -81,120 +195,545 @@ pub async fn establish_session(
                 true
             }
         }
+        AuthMethod::KeyboardInteractive => {
+            authenticate_keyboard_interactive(session, profile, event_tx).await?
+        }
+        AuthMethod::PublicKeyAndKeyboardInteractive => {
+            // A server requiring both answers the key with a partial success,
+            // which russh reports as `false`; the second factor follows.
+            let pk_ok = authenticate_with_key(session, profile, key_passphrase).await?;
+            if !pk_ok {
+                authenticate_keyboard_interactive(session, profile, event_tx).await?
+            } else {
+                true
+            }
+        }
+        AuthMethod::Agent => {
+            agent::authenticate_with_agent(session, &profile.username, profile.agent).await?
+        }
     };
 
     if !authenticated {
+        // Otherwise every later connect would fail with it again, unprompted
+        if password_used && credentials.password_saved {
+            secrets::forget(SecretKind::ProfilePassword, profile.id).await;
+            return Err(AppError::Auth(
+                "Authentication failed, the saved password was forgotten".into(),
+            ));
+        }
         return Err(AppError::Auth("Authentication failed".into()));
     }
+    remember_credentials(profile, credentials, password_used, event_tx).await;
+    Ok(())
+}
+
+/// Save the secrets the user asked to remember, now that they worked.
+async fn remember_credentials(
+    profile: &ConnectionProfile,
+    credentials: &AuthCredentials,
+    password_used: bool,
+    event_tx: &async_channel::Sender<SshEvent>,
+) {
+    let mut remember = Vec::new();
+    if password_used {
+        if let (Some(label), Some(password)) = (&credentials.remember_password, &credentials.password) {
+            remember.push((SecretKind::ProfilePassword, profile.id, label, password));
+        }
+    }
+    if let (Some(label), Some(passphrase), Some(key_id)) = (
+        &credentials.remember_passphrase,
+        &credentials.key_passphrase,
+        profile.key_pair_id,
+    ) {
+        remember.push((SecretKind::KeyPassphrase, key_id, label, passphrase));
+    }
+
+    for (kind, id, label, secret) in remember {
+        if let Err(e) = secrets::store(kind, id, label, secret).await {
+            let _ = event_tx.send(SshEvent::Error(e.to_string())).await;
+        }
+    }
+}
+
+async fn authenticate_with_key(
+    session: &mut client::Handle<ClientHandler>,
+    profile: &ConnectionProfile,
+    key_passphrase: Option<&Zeroizing<String>>,
+) -> Result<bool, AppError> {
+    let key_id = profile
+        .key_pair_id
+        .ok_or_else(|| AppError::Auth("No key pair selected".into()))?;
+    let key_path = paths::private_key_path(&key_id);
+    let key_pass = key_passphrase.map(|s| s.as_str());
+    let key_pair = Arc::new(
+        russh_keys::load_secret_key(&key_path, key_pass)
+            .map_err(|e| AppError::Auth(e.to_string()))?,
+    );
+
+    // Offer the certificate first, servers that don't trust its CA may still
+    // accept the plain key
+    if let Some(cert) = certificate::load_certificate(&key_id)? {
+        let info = certificate::CertificateInfo::from_certificate(&cert);
+        if info.status() == certificate::CertificateStatus::Expired {
+            log::warn!("Certificate for key {key_id} has expired, offering the plain key");
+        } else if session
+            .authenticate_openssh_cert(&profile.username, key_pair.clone(), cert)
+            .await
+            .map_err(|e| AppError::Auth(e.to_string()))?
+        {
+            return Ok(true);
+        }
+    }
 
-    Ok(session)
+    session
+        .authenticate_publickey(&profile.username, key_pair)
+        .await
+        .map_err(|e| AppError::Auth(e.to_string()))
 }
 
-/// Spawn an SSH session task.  Returns the command sender for controlling the session.
+/// Run keyboard-interactive authentication, forwarding every round of
+/// prompts to the UI and sending back what the user typed.
+async fn authenticate_keyboard_interactive(
+    session: &mut client::Handle<ClientHandler>,
+    profile: &ConnectionProfile,
+    event_tx: &async_channel::Sender<SshEvent>,
+) -> Result<bool, AppError> {
+    let mut response = session
+        .authenticate_keyboard_interactive_start(&profile.username, None::<String>)
+        .await
+        .map_err(|e| AppError::Auth(e.to_string()))?;
+
+    loop {
+        match response {
+            KeyboardInteractiveAuthResponse::Success => return Ok(true),
+            KeyboardInteractiveAuthResponse::Failure => return Ok(false),
+            KeyboardInteractiveAuthResponse::InfoRequest {
+                name,
+                instructions,
+                prompts,
+            } => {
+                // Some servers send an empty round before the real prompts
+                let answers = if prompts.is_empty() {
+                    Vec::new()
+                } else {
+                    let fields = prompts
+                        .into_iter()
+                        .map(|p| AuthPromptField {
+                            prompt: p.prompt,
+                            echo: p.echo,
+                        })
+                        .collect();
+                    ask_auth_prompt(profile, name, instructions, fields, event_tx).await?
+                };
+                let answers = answers.iter().map(|a| a.to_string()).collect();
+                response = session
+                    .authenticate_keyboard_interactive_respond(answers)
+                    .await
+                    .map_err(|e| AppError::Auth(e.to_string()))?;
+            }
+        }
+    }
+}
+
+async fn ask_auth_prompt(
+    profile: &ConnectionProfile,
+    name: String,
+    instructions: String,
+    fields: Vec<AuthPromptField>,
+    event_tx: &async_channel::Sender<SshEvent>,
+) -> Result<Vec<Zeroizing<String>>, AppError> {
+    let (response_tx, response_rx) = async_channel::bounded(1);
+    let prompt = AuthPrompt {
+        host: profile.hostname.clone(),
+        name,
+        instructions,
+        fields,
+        response_tx,
+    };
+    event_tx
+        .send(SshEvent::AuthPrompt(prompt))
+        .await
+        .map_err(|_| AppError::Auth("Authentication cancelled".into()))?;
+
+    match response_rx.recv().await {
+        Ok(Some(answers)) => Ok(answers),
+        _ => Err(AppError::Auth("Authentication cancelled".into())),
+    }
+}
+
+/// Prefix an error with the jump host it happened on, keeping its kind.
+fn jump_host_error(profile: &ConnectionProfile, e: AppError) -> AppError {
+    let hop = format!("Jump host \"{}\"", profile.name);
+    match e {
+        AppError::Auth(msg) => AppError::Auth(format!("{hop}: {msg}")),
+        AppError::HostKey(msg) => AppError::HostKey(format!("{hop}: {msg}")),
+        AppError::Connection(msg) => AppError::Connection(format!("{hop}: {msg}")),
+        other => other,
+    }
+}
+
+/// Recover our own error type from the handler so host key failures are
+/// reported as such instead of as a generic connection error.
+fn connect_error(e: anyhow::Error) -> AppError {
+    match e.downcast::<AppError>() {
+        Ok(app_error) => app_error,
+        Err(e) => AppError::Connection(e.to_string()),
+    }
+}
+
+/// The startup script of a profile, if it has one. A script with an invalid
+/// pattern is reported and skipped.
+async fn startup_script(
+    profile: &ConnectionProfile,
+    event_tx: &async_channel::Sender<SshEvent>,
+) -> Option<StartupScript> {
+    if profile.startup_script.is_empty() {
+        return None;
+    }
+    match StartupScript::new(&profile.startup_script) {
+        Ok(script) => Some(script),
+        Err(e) => {
+            let _ = event_tx.send(SshEvent::Error(format!("Startup script skipped: {e}"))).await;
+            None
+        }
+    }
+}
+
+async fn sleep_until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Start logging the session if the profile or the settings ask for it
+async fn open_session_log(
+    chain: &ConnectionChain,
+    event_tx: &async_channel::Sender<SshEvent>,
+) -> Option<SessionLog> {
+    let profile = chain.profile();
+    let config = profile
+        .session_log
+        .as_ref()
+        .unwrap_or(&chain.settings.session_log);
+    if !config.enabled {
+        return None;
+    }
+    match SessionLog::open(config, &chain.settings.log_directory, profile) {
+        Ok(log) => {
+            let _ = event_tx.send(SshEvent::LogStarted(log.path().to_path_buf())).await;
+            Some(log)
+        }
+        Err(e) => {
+            let msg = format!("Cannot open the session log: {e}");
+            let _ = event_tx.send(SshEvent::Error(msg)).await;
+            None
+        }
+    }
+}
+
+/// Write to the session log, and stop logging with an error message when
+/// that fails, e.g. on a full disk
+async fn write_log(
+    session_log: &mut Option<SessionLog>,
+    event_tx: &async_channel::Sender<SshEvent>,
+    write: impl FnOnce(&mut SessionLog) -> Result<(), AppError>,
+) {
+    let Some(log) = session_log.as_mut() else {
+        return;
+    };
+    if let Err(e) = write(log) {
+        let msg = format!("Session log {} stopped: {e}", log.path().display());
+        *session_log = None;
+        let _ = event_tx.send(SshEvent::Error(msg)).await;
+    }
+}
+
+/// Why the connection under a vanished channel ended, taken from the result
+/// of russh's session task. Keepalive and inactivity timeouts mean the server
+/// stopped answering, which a firewall dropping the connection looks like.
+/// Only the first call gets the real answer, see `SharedConnection::end_reason`.
+pub async fn connection_end_reason(session: &mut client::Handle<ClientHandler>) -> DisconnectReason {
+    match tokio::time::timeout(SESSION_END_WAIT, session).await {
+        Ok(Err(e)) => match e.downcast_ref::<russh::Error>() {
+            Some(russh::Error::KeepaliveTimeout) => {
+                DisconnectReason::TimedOut("The server stopped answering keepalives".into())
+            }
+            Some(russh::Error::InactivityTimeout) => {
+                DisconnectReason::TimedOut("Nothing received within the inactivity timeout".into())
+            }
+            _ => DisconnectReason::Lost(e.to_string()),
+        },
+        _ => DisconnectReason::Lost("Connection lost".into()),
+    }
+}
+
+/// Spawn an SSH session task controlled through `cmd_rx`. A tab keeps its
+/// command channel across reconnects and passes it to every new session.
+/// Tabs duplicated from another one pass `start_tunnels: false`, the
+/// original tab already runs the profile's tunnels.
 pub fn spawn_session(
-    profile: ConnectionProfile,
-    password: Option<Zeroizing<String>>,
-    key_passphrase: Option<Zeroizing<String>>,
+    chain: ConnectionChain,
     event_tx: async_channel::Sender<SshEvent>,
-) -> async_channel::Sender<SshCommand> {
-    let (cmd_tx, cmd_rx) = async_channel::bounded::<SshCommand>(64);
-
+    cmd_rx: async_channel::Receiver<SshCommand>,
+    start_tunnels: bool,
+) {
     let rt = crate::runtime();
     rt.spawn(async move {
-        if let Err(e) = run_session(profile, password, key_passphrase, event_tx.clone(), cmd_rx).await {
-            let _ = event_tx.send(SshEvent::Error(e.to_string())).await;
-            let _ = event_tx
-                .send(SshEvent::Disconnected(Some(e.to_string())))
-                .await;
-        }
+        let result = run_session(chain, event_tx.clone(), cmd_rx, start_tunnels).await;
+        report_session_end(result, &event_tx).await;
     });
+}
+
+/// Spawn a task running another shell on the connection of a session, for
+/// the tab at the other end of `event_tx` and `cmd_rx`.
+fn spawn_shell(
+    connection: ConnectionLease,
+    chain: ConnectionChain,
+    event_tx: async_channel::Sender<SshEvent>,
+    cmd_rx: async_channel::Receiver<SshCommand>,
+) {
+    crate::runtime().spawn(async move {
+        let result = run_shell(connection, chain, event_tx.clone(), cmd_rx, false).await;
+        report_session_end(result, &event_tx).await;
+    });
+}
+
+async fn report_session_end(
+    result: Result<(), AppError>,
+    event_tx: &async_channel::Sender<SshEvent>,
+) {
+    if let Err(e) = result {
+        let _ = event_tx.send(SshEvent::Error(e.to_string())).await;
+        let _ = event_tx
+            .send(SshEvent::Disconnected(disconnect_reason(&e)))
+            .await;
+    }
+}
 
-    cmd_tx
+/// Errors a retry cannot fix are reported as `Failed`, the rest as `Lost`.
+fn disconnect_reason(e: &AppError) -> DisconnectReason {
+    match e {
+        AppError::Auth(_) | AppError::HostKey(_) | AppError::Config(_) | AppError::SshKey(_) => {
+            DisconnectReason::Failed(e.to_string())
+        }
+        _ => DisconnectReason::Lost(e.to_string()),
+    }
 }
 
 async fn run_session(
-    profile: ConnectionProfile,
-    password: Option<Zeroizing<String>>,
-    key_passphrase: Option<Zeroizing<String>>,
+    chain: ConnectionChain,
     event_tx: async_channel::Sender<SshEvent>,
     cmd_rx: async_channel::Receiver<SshCommand>,
+    start_tunnels: bool,
 ) -> Result<(), AppError> {
-    let session = establish_session(
-        &profile,
-        password.as_ref(),
-        key_passphrase.as_ref(),
-        event_tx.clone(),
-    )
-    .await?;
+    let connection = pool::pool().acquire(&chain, event_tx.clone()).await?;
+    run_shell(connection, chain, event_tx, cmd_rx, start_tunnels).await
+}
 
+/// Run one shell channel on `connection` until its tab disconnects or the
+/// channel closes. Other tabs can ask it for more channels on the same
+/// connection, each of which runs in a task of its own, so closing this tab
+/// leaves them open.
+async fn run_shell(
+    connection: ConnectionLease,
+    chain: ConnectionChain,
+    event_tx: async_channel::Sender<SshEvent>,
+    cmd_rx: async_channel::Receiver<SshCommand>,
+    start_tunnels: bool,
+) -> Result<(), AppError> {
     let _ = event_tx.send(SshEvent::Connected).await;
+    if let Some(negotiated) = connection.negotiated.clone() {
+        let _ = event_tx.send(SshEvent::AlgorithmsNegotiated(negotiated)).await;
+    }
+    let profile = chain.profile();
+    if profile.auth_method == AuthMethod::Agent || profile.agent_forwarding {
+        let _ = event_tx.send(SshEvent::AgentInUse(agent::describe(profile.agent))).await;
+    }
 
     // Open a session channel with a PTY
-    let channel = session
+    let channel = connection
+        .handle
+        .lock()
+        .await
         .channel_open_session()
         .await
         .map_err(|e| AppError::Connection(e.to_string()))?;
 
-    channel
-        .request_pty(false, "xterm-256color", 80, 24, 0, 0, &[])
-        .await
-        .map_err(|e| AppError::Connection(e.to_string()))?;
+    if chain.profile().agent_forwarding {
+        channel
+            .agent_forward(false)
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))?;
+    }
+
+    if let Some(forwarding) = &connection.x11 {
+        channel
+            .request_x11(
+                false,
+                false,
+                x11::AUTH_PROTOCOL,
+                forwarding.fake_cookie_hex(),
+                forwarding.display.screen,
+            )
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))?;
+    }
+
+    // Servers silently drop variables their AcceptEnv does not list
+    let options = SessionOptions::resolve(&chain.settings, chain.profile());
+    for var in &options.environment {
+        channel
+            .set_env(false, var.name.as_str(), var.value.as_str())
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))?;
+    }
 
     channel
-        .request_shell(false)
+        .request_pty(
+            false,
+            &options.terminal_type,
+            80,
+            24,
+            0,
+            0,
+            &options.terminal_modes,
+        )
         .await
         .map_err(|e| AppError::Connection(e.to_string()))?;
 
+    match chain.profile().remote_command.as_deref() {
+        Some(command) => channel
+            .exec(false, command)
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))?,
+        None => channel
+            .request_shell(false)
+            .await
+            .map_err(|e| AppError::Connection(e.to_string()))?,
+    }
+
+    let mut session_log = open_session_log(&chain, &event_tx).await;
+    let mut script = startup_script(chain.profile(), &event_tx).await;
+    if let Some(script) = script.as_mut() {
+        let input = script.advance(&[]);
+        if !input.is_empty() {
+            channel.data(&input[..]).await
+                .map_err(|e| AppError::Connection(e.to_string()))?;
+        }
+    }
+
     // Start enabled tunnels
-    let session_handle = Arc::new(Mutex::new(session));
-    for tc in &profile.tunnels {
-        if tc.enabled {
-            tunnel::start_tunnel(session_handle.clone(), tc.clone(), event_tx.clone());
+    let mut tunnels = TunnelRegistry::new(
+        connection.handle.clone(),
+        connection.remote_forwards.clone(),
+        event_tx.clone(),
+    );
+    if start_tunnels {
+        for tc in &chain.profile().tunnels {
+            if tc.enabled {
+                tunnels.start(tc.clone()).await;
+            }
         }
     }
 
+    // Tunnel counters are pushed to the UI once per second while tunnels run
+    let mut stats_interval = tokio::time::interval(Duration::from_secs(1));
+
     // Main data loop
     let mut channel = channel;
+    let mut remote_exited = false;
 
     loop {
+        let script_deadline = script.as_ref().and_then(StartupScript::deadline);
         tokio::select! {
             cmd = cmd_rx.recv() => {
                 match cmd {
                     Ok(SshCommand::SendData(data)) => {
                         channel.data(&data[..]).await
                             .map_err(|e| AppError::Connection(e.to_string()))?;
+                        write_log(&mut session_log, &event_tx, |log| log.input(&data)).await;
                     }
                     Ok(SshCommand::Resize { cols, rows }) => {
                         channel.window_change(cols, rows, 0, 0).await
                             .map_err(|e| AppError::Connection(e.to_string()))?;
                     }
                     Ok(SshCommand::StartTunnel(tc)) => {
-                        tunnel::start_tunnel(session_handle.clone(), tc, event_tx.clone());
+                        tunnels.start(tc).await;
                     }
-                    Ok(SshCommand::StopTunnel(_id)) => {
-                        // Tunnel stop is handled via drop of the tunnel task
+                    Ok(SshCommand::StopTunnel(id)) => {
+                        // Reply even when the tunnel had already failed, so
+                        // the panel does not wait for an answer
+                        tunnels.stop(&id).await;
+                        let _ = event_tx.send(SshEvent::TunnelStopped(id)).await;
                     }
+                    Ok(SshCommand::OpenShell { event_tx, cmd_rx }) => {
+                        spawn_shell(connection.share(), chain.clone(), event_tx, cmd_rx);
+                    }
+                    Ok(SshCommand::OpenSftp { event_tx, cmd_rx }) => {
+                        sftp::spawn_sftp_channel(connection.share(), event_tx, cmd_rx);
+                    }
+                    // Other tabs may still use the connection, the pool
+                    // closes it once the last of them is gone
                     Ok(SshCommand::Disconnect) | Err(_) => {
+                        tunnels.shutdown().await;
                         let _ = channel.eof().await;
-                        let sess = session_handle.lock().await;
-                        sess.disconnect(Disconnect::ByApplication, "User disconnected", "en")
-                            .await
-                            .map_err(|e| AppError::Connection(e.to_string()))?;
-                        let _ = event_tx.send(SshEvent::Disconnected(None)).await;
+                        let _ = channel.close().await;
+                        let _ = event_tx.send(SshEvent::Disconnected(DisconnectReason::User)).await;
                         return Ok(());
                     }
                 }
             }
+            _ = sleep_until_deadline(script_deadline), if script_deadline.is_some() => {
+                if let Some(script) = script.as_mut() {
+                    let msg = format!(
+                        "Startup script gave up waiting for {:?}",
+                        script.waiting_for().unwrap_or_default()
+                    );
+                    let _ = event_tx.send(SshEvent::Error(msg)).await;
+                    script.abandon();
+                }
+            }
+            _ = stats_interval.tick() => {
+                write_log(&mut session_log, &event_tx, SessionLog::flush).await;
+                tunnels.prune_finished();
+                if !tunnels.is_empty() {
+                    let _ = event_tx.send(SshEvent::TunnelStats(tunnels.snapshots())).await;
+                }
+            }
             msg = channel.wait() => {
                 match msg {
                     Some(ChannelMsg::Data { data }) => {
                         let _ = event_tx.send(SshEvent::Data(data.to_vec())).await;
+                        write_log(&mut session_log, &event_tx, |log| log.output(&data)).await;
+                        if let Some(script) = script.as_mut() {
+                            let input = script.advance(&data);
+                            if !input.is_empty() {
+                                channel.data(&input[..]).await
+                                    .map_err(|e| AppError::Connection(e.to_string()))?;
+                            }
+                        }
                     }
                     Some(ChannelMsg::ExitStatus { exit_status }) => {
                         log::info!("Remote process exited with status {exit_status}");
+                        remote_exited = true;
+                    }
+                    Some(ChannelMsg::Eof) => {
+                        tunnels.shutdown().await;
+                        let _ = event_tx.send(SshEvent::Disconnected(DisconnectReason::RemoteClosed)).await;
+                        return Ok(());
                     }
-                    Some(ChannelMsg::Eof) | None => {
-                        let _ = event_tx.send(SshEvent::Disconnected(None)).await;
+                    // The channel vanished without an EOF: unless the shell
+                    // exited first, the connection itself is gone
+                    None => {
+                        let reason = if remote_exited {
+                            tunnels.shutdown().await;
+                            DisconnectReason::RemoteClosed
+                        } else {
+                            tunnels.stop_all();
+                            connection.end_reason().await
+                        };
+                        let _ = event_tx.send(SshEvent::Disconnected(reason)).await;
                         return Ok(());
                     }
                     _ => {}
@@ -1,13 +1,11 @@
 use russh_sftp::client::SftpSession;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use zeroize::Zeroizing;
 
 use std::path::{Path, PathBuf};
 
-use crate::app::SshEvent;
+use crate::app::{AuthPrompt, ConnectionChain, HostKeyPrompt, SshEvent};
 use crate::error::AppError;
-use crate::models::connection::ConnectionProfile;
-use crate::ssh::session::establish_session;
+use crate::ssh::pool::{self, ConnectionLease};
 
 #[derive(Debug)]
 pub enum SftpCommand {
@@ -50,6 +48,8 @@ pub enum SftpEvent {
         is_dir: bool,
         response_tx: async_channel::Sender<SftpConflictResponse>,
     },
+    HostKeyVerify(HostKeyPrompt),
+    AuthPrompt(AuthPrompt),
     Error(String),
     Disconnected,
 }
@@ -62,46 +62,75 @@ pub struct SftpEntry {
     pub modified: Option<u64>,
 }
 
-/// Spawn an SFTP session task. Returns the command sender.
+/// Spawn an SFTP session task controlled through `cmd_rx`.
 pub fn spawn_sftp_session(
-    profile: ConnectionProfile,
-    password: Option<Zeroizing<String>>,
-    key_passphrase: Option<Zeroizing<String>>,
+    chain: ConnectionChain,
     event_tx: async_channel::Sender<SftpEvent>,
-) -> async_channel::Sender<SftpCommand> {
-    let (cmd_tx, cmd_rx) = async_channel::bounded::<SftpCommand>(64);
-
+    cmd_rx: async_channel::Receiver<SftpCommand>,
+) {
     let rt = crate::runtime();
     rt.spawn(async move {
-        if let Err(e) = run_sftp_session(profile, password, key_passphrase, event_tx.clone(), cmd_rx).await {
+        if let Err(e) = run_sftp_session(chain, event_tx.clone(), cmd_rx).await {
             let _ = event_tx.send(SftpEvent::Error(e.to_string())).await;
             let _ = event_tx.send(SftpEvent::Disconnected).await;
         }
     });
+}
 
-    cmd_tx
+/// Spawn an SFTP task on the connection of a terminal session.
+pub fn spawn_sftp_channel(
+    connection: ConnectionLease,
+    event_tx: async_channel::Sender<SftpEvent>,
+    cmd_rx: async_channel::Receiver<SftpCommand>,
+) {
+    let rt = crate::runtime();
+    rt.spawn(async move {
+        if let Err(e) = run_sftp_channel(connection, event_tx.clone(), cmd_rx).await {
+            let _ = event_tx.send(SftpEvent::Error(e.to_string())).await;
+            let _ = event_tx.send(SftpEvent::Disconnected).await;
+        }
+    });
 }
 
 async fn run_sftp_session(
-    profile: ConnectionProfile,
-    password: Option<Zeroizing<String>>,
-    key_passphrase: Option<Zeroizing<String>>,
+    chain: ConnectionChain,
     event_tx: async_channel::Sender<SftpEvent>,
     cmd_rx: async_channel::Receiver<SftpCommand>,
 ) -> Result<(), AppError> {
-    // We need a separate event channel for the SSH layer (we ignore its events)
-    let (ssh_event_tx, _ssh_event_rx) = async_channel::bounded::<SshEvent>(16);
+    // The SSH layer has its own event channel; only host key and
+    // authentication prompts and errors are relevant to the SFTP browser,
+    // everything else is dropped.
+    let (ssh_event_tx, ssh_event_rx) = async_channel::bounded::<SshEvent>(16);
+    let prompt_tx = event_tx.clone();
+    tokio::spawn(async move {
+        while let Ok(event) = ssh_event_rx.recv().await {
+            let forwarded = match event {
+                SshEvent::HostKeyVerify(prompt) => SftpEvent::HostKeyVerify(prompt),
+                SshEvent::AuthPrompt(prompt) => SftpEvent::AuthPrompt(prompt),
+                SshEvent::Error(msg) => SftpEvent::Error(msg),
+                _ => continue,
+            };
+            let _ = prompt_tx.send(forwarded).await;
+        }
+    });
 
-    let session = establish_session(
-        &profile,
-        password.as_ref(),
-        key_passphrase.as_ref(),
-        ssh_event_tx,
-    )
-    .await?;
+    // Next to a terminal of the same profile this is the terminal's connection
+    let connection = pool::pool().acquire(&chain, ssh_event_tx).await?;
+    run_sftp_channel(connection, event_tx, cmd_rx).await
+}
 
+/// Browse files over an SFTP channel on `connection` until the tab
+/// disconnects
+async fn run_sftp_channel(
+    connection: ConnectionLease,
+    event_tx: async_channel::Sender<SftpEvent>,
+    cmd_rx: async_channel::Receiver<SftpCommand>,
+) -> Result<(), AppError> {
     // Open SFTP subsystem
-    let channel = session
+    let channel = connection
+        .handle
+        .lock()
+        .await
         .channel_open_session()
         .await
         .map_err(|e| AppError::Connection(format!("Failed to open channel: {e}")))?;
@@ -188,13 +217,12 @@ async fn run_sftp_session(
                     )).await;
                 }
             }
-            SftpCommand::Disconnect => {
-                let _ = event_tx.send(SftpEvent::Disconnected).await;
-                return Ok(());
-            }
+            SftpCommand::Disconnect => break,
         }
     }
 
+    // Only the channel is closed, a terminal may still use the connection
+    let _ = sftp.close().await;
     let _ = event_tx.send(SftpEvent::Disconnected).await;
     Ok(())
 }
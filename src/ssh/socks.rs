@@ -0,0 +1,283 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const SOCKS4_VERSION: u8 = 0x04;
+const SOCKS5_VERSION: u8 = 0x05;
+const CMD_CONNECT: u8 = 0x01;
+
+const SOCKS4_GRANTED: u8 = 0x5a;
+const SOCKS4_REJECTED: u8 = 0x5b;
+
+const SOCKS5_NO_AUTH: u8 = 0x00;
+const SOCKS5_NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const SOCKS5_SUCCEEDED: u8 = 0x00;
+const SOCKS5_HOST_UNREACHABLE: u8 = 0x04;
+const SOCKS5_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const SOCKS5_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocksVersion {
+    V4,
+    V5,
+}
+
+/// Destination requested by a SOCKS client.
+#[derive(Debug, Clone)]
+pub struct SocksRequest {
+    pub version: SocksVersion,
+    pub host: String,
+    pub port: u16,
+}
+
+/// Read a SOCKS4, SOCKS4a or SOCKS5 CONNECT request from a freshly accepted client.
+/// Unsupported requests are answered with the matching error reply.
+pub async fn read_request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> Result<SocksRequest, anyhow::Error> {
+    match stream.read_u8().await? {
+        SOCKS4_VERSION => read_socks4_request(stream).await,
+        SOCKS5_VERSION => read_socks5_request(stream).await,
+        other => Err(anyhow::anyhow!("Unsupported SOCKS version {other}")),
+    }
+}
+
+/// Tell the client whether the SSH channel to its destination could be opened.
+pub async fn send_reply<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    version: SocksVersion,
+    success: bool,
+) -> Result<(), anyhow::Error> {
+    match version {
+        SocksVersion::V4 => {
+            let status = if success { SOCKS4_GRANTED } else { SOCKS4_REJECTED };
+            stream.write_all(&[0x00, status, 0, 0, 0, 0, 0, 0]).await?;
+        }
+        SocksVersion::V5 => {
+            let status = if success {
+                SOCKS5_SUCCEEDED
+            } else {
+                SOCKS5_HOST_UNREACHABLE
+            };
+            send_socks5_reply(stream, status).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn read_socks4_request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> Result<SocksRequest, anyhow::Error> {
+    let command = stream.read_u8().await?;
+    let port = stream.read_u16().await?;
+    let mut ip = [0u8; 4];
+    stream.read_exact(&mut ip).await?;
+    // User ID is ignored, there is no authentication on the local listener
+    read_nul_terminated(stream).await?;
+
+    if command != CMD_CONNECT {
+        stream.write_all(&[0x00, SOCKS4_REJECTED, 0, 0, 0, 0, 0, 0]).await?;
+        return Err(anyhow::anyhow!("Unsupported SOCKS4 command {command}"));
+    }
+
+    // SOCKS4a: an address of 0.0.0.x (x != 0) means a domain name follows
+    let host = if ip[0] == 0 && ip[1] == 0 && ip[2] == 0 && ip[3] != 0 {
+        read_nul_terminated(stream).await?
+    } else {
+        Ipv4Addr::from(ip).to_string()
+    };
+
+    Ok(SocksRequest {
+        version: SocksVersion::V4,
+        host,
+        port,
+    })
+}
+
+async fn read_socks5_request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> Result<SocksRequest, anyhow::Error> {
+    let method_count = stream.read_u8().await? as usize;
+    let mut methods = vec![0u8; method_count];
+    stream.read_exact(&mut methods).await?;
+
+    if !methods.contains(&SOCKS5_NO_AUTH) {
+        stream
+            .write_all(&[SOCKS5_VERSION, SOCKS5_NO_ACCEPTABLE_METHOD])
+            .await?;
+        return Err(anyhow::anyhow!("SOCKS5 client does not offer unauthenticated access"));
+    }
+    stream.write_all(&[SOCKS5_VERSION, SOCKS5_NO_AUTH]).await?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let [version, command, _reserved, address_type] = header;
+    if version != SOCKS5_VERSION {
+        return Err(anyhow::anyhow!("Invalid SOCKS5 request version {version}"));
+    }
+
+    let host = match address_type {
+        ATYP_IPV4 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).await?;
+            Ipv4Addr::from(ip).to_string()
+        }
+        ATYP_IPV6 => {
+            let mut ip = [0u8; 16];
+            stream.read_exact(&mut ip).await?;
+            Ipv6Addr::from(ip).to_string()
+        }
+        ATYP_DOMAIN => {
+            let len = stream.read_u8().await? as usize;
+            let mut name = vec![0u8; len];
+            stream.read_exact(&mut name).await?;
+            String::from_utf8(name)?
+        }
+        other => {
+            send_socks5_reply(stream, SOCKS5_ADDRESS_NOT_SUPPORTED).await?;
+            return Err(anyhow::anyhow!("Unsupported SOCKS5 address type {other}"));
+        }
+    };
+    let port = stream.read_u16().await?;
+
+    if command != CMD_CONNECT {
+        send_socks5_reply(stream, SOCKS5_COMMAND_NOT_SUPPORTED).await?;
+        return Err(anyhow::anyhow!("Unsupported SOCKS5 command {command}"));
+    }
+
+    Ok(SocksRequest {
+        version: SocksVersion::V5,
+        host,
+        port,
+    })
+}
+
+async fn send_socks5_reply<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    status: u8,
+) -> Result<(), anyhow::Error> {
+    // The bound address is not meaningful for a tunnelled connection
+    stream
+        .write_all(&[SOCKS5_VERSION, status, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+async fn read_nul_terminated<S: AsyncRead + Unpin>(stream: &mut S) -> Result<String, anyhow::Error> {
+    let mut bytes = Vec::new();
+    loop {
+        match stream.read_u8().await? {
+            0 => break,
+            b if bytes.len() < 255 => bytes.push(b),
+            _ => return Err(anyhow::anyhow!("SOCKS4 field too long")),
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `read_request` on what a client sent and return its result
+    /// together with everything written back to the client.
+    async fn serve(sent: &[u8]) -> (Result<SocksRequest, anyhow::Error>, Vec<u8>) {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        client.write_all(sent).await.unwrap();
+        let result = read_request(&mut server).await;
+        drop(server);
+        let mut replies = Vec::new();
+        client.read_to_end(&mut replies).await.unwrap();
+        (result, replies)
+    }
+
+    #[tokio::test]
+    async fn socks4_connect() {
+        let (result, replies) = serve(b"\x04\x01\x00\x50\x0a\x00\x00\x05alice\x00").await;
+        let request = result.unwrap();
+        assert_eq!(request.version, SocksVersion::V4);
+        assert_eq!(request.host, "10.0.0.5");
+        assert_eq!(request.port, 80);
+        assert!(replies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn socks4a_domain() {
+        let (result, _) = serve(b"\x04\x01\x01\xbb\x00\x00\x00\x01\x00example.com\x00").await;
+        let request = result.unwrap();
+        assert_eq!(request.version, SocksVersion::V4);
+        assert_eq!(request.host, "example.com");
+        assert_eq!(request.port, 443);
+    }
+
+    #[tokio::test]
+    async fn socks5_ipv4() {
+        let (result, replies) =
+            serve(b"\x05\x02\x02\x00\x05\x01\x00\x01\xc0\xa8\x01\x02\x00\x16").await;
+        let request = result.unwrap();
+        assert_eq!(request.version, SocksVersion::V5);
+        assert_eq!(request.host, "192.168.1.2");
+        assert_eq!(request.port, 22);
+        // Only the method selection, the reply follows once the channel is open
+        assert_eq!(replies, [SOCKS5_VERSION, SOCKS5_NO_AUTH]);
+    }
+
+    #[tokio::test]
+    async fn socks5_domain() {
+        let (result, _) = serve(b"\x05\x01\x00\x05\x01\x00\x03\x0bexample.com\x01\xbb").await;
+        let request = result.unwrap();
+        assert_eq!(request.host, "example.com");
+        assert_eq!(request.port, 443);
+    }
+
+    #[tokio::test]
+    async fn socks5_ipv6() {
+        let mut sent = b"\x05\x01\x00\x05\x01\x00\x04".to_vec();
+        sent.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        sent.extend_from_slice(&8080u16.to_be_bytes());
+        let (result, _) = serve(&sent).await;
+        let request = result.unwrap();
+        assert_eq!(request.host, "2001:db8::1");
+        assert_eq!(request.port, 8080);
+    }
+
+    #[tokio::test]
+    async fn socks4_bind_is_rejected() {
+        let (result, replies) = serve(b"\x04\x02\x00\x50\x0a\x00\x00\x05\x00").await;
+        assert!(result.is_err());
+        assert_eq!(replies, [0x00, SOCKS4_REJECTED, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn socks5_without_no_auth_is_rejected() {
+        // Only username/password offered
+        let (result, replies) = serve(b"\x05\x01\x02").await;
+        assert!(result.is_err());
+        assert_eq!(replies, [SOCKS5_VERSION, SOCKS5_NO_ACCEPTABLE_METHOD]);
+    }
+
+    #[tokio::test]
+    async fn socks5_udp_associate_is_rejected() {
+        let (result, replies) =
+            serve(b"\x05\x01\x00\x05\x03\x00\x01\x00\x00\x00\x00\x00\x00").await;
+        assert!(result.is_err());
+        assert_eq!(replies[..2], [SOCKS5_VERSION, SOCKS5_NO_AUTH]);
+        assert_eq!(replies[2..4], [SOCKS5_VERSION, SOCKS5_COMMAND_NOT_SUPPORTED]);
+    }
+
+    #[tokio::test]
+    async fn socks5_unknown_address_type_is_rejected() {
+        let (result, replies) = serve(b"\x05\x01\x00\x05\x01\x00\x07").await;
+        assert!(result.is_err());
+        assert_eq!(replies[2..4], [SOCKS5_VERSION, SOCKS5_ADDRESS_NOT_SUPPORTED]);
+    }
+
+    #[tokio::test]
+    async fn unknown_version_is_rejected() {
+        let (result, _) = serve(b"\x06\x01").await;
+        assert!(result.is_err());
+    }
+}
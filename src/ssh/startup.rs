@@ -0,0 +1,179 @@
+use regex::Regex;
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::models::connection::StartupStep;
+use crate::ssh::ansi::AnsiStripper;
+
+/// How long a step waits for its pattern before the script is abandoned
+pub const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Output kept for matching, the prompt is always near the end
+const MAX_BUFFER: usize = 8 * 1024;
+
+struct Step {
+    wait_for: Option<Regex>,
+    send: String,
+}
+
+/// Types the lines of a profile's startup script into the session, each
+/// one after its wait-for pattern showed up in the output.
+pub struct StartupScript {
+    steps: VecDeque<Step>,
+    /// Output received since the last line was sent
+    output: Vec<u8>,
+    deadline: Option<Instant>,
+}
+
+impl StartupScript {
+    pub fn new(steps: &[StartupStep]) -> Result<Self, String> {
+        let steps = steps
+            .iter()
+            .map(|step| {
+                let wait_for = match step.wait_for.as_deref() {
+                    Some(pattern) => Some(
+                        Regex::new(pattern).map_err(|e| format!("Invalid pattern {pattern:?}: {e}"))?,
+                    ),
+                    None => None,
+                };
+                Ok(Step {
+                    wait_for,
+                    send: step.send.clone(),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let mut script = Self {
+            steps,
+            output: Vec::new(),
+            deadline: None,
+        };
+        script.arm();
+        Ok(script)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// When the current step gives up waiting
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// The pattern the script is stuck on
+    pub fn waiting_for(&self) -> Option<&str> {
+        self.steps
+            .front()
+            .and_then(|step| step.wait_for.as_ref())
+            .map(Regex::as_str)
+    }
+
+    /// Drop the remaining steps
+    pub fn abandon(&mut self) {
+        self.steps.clear();
+        self.deadline = None;
+    }
+
+    /// Feed session output, returning the input to send now. Steps without a
+    /// pattern go out together with the step before them.
+    pub fn advance(&mut self, data: &[u8]) -> Vec<u8> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        self.output.extend_from_slice(data);
+        if self.output.len() > MAX_BUFFER {
+            self.output.drain(..self.output.len() - MAX_BUFFER);
+        }
+
+        let mut input = Vec::new();
+        while let Some(step) = self.steps.front() {
+            if let Some(pattern) = &step.wait_for {
+                // Strip colours, so a pattern like `\$ $` matches a coloured prompt
+                let text = AnsiStripper::default().strip(&self.output);
+                if !pattern.is_match(&String::from_utf8_lossy(&text)) {
+                    break;
+                }
+            }
+            input.extend_from_slice(step.send.as_bytes());
+            input.push(b'\r');
+            self.steps.pop_front();
+            self.output.clear();
+            self.arm();
+        }
+        input
+    }
+
+    fn arm(&mut self) {
+        self.deadline = (!self.steps.is_empty()).then(|| Instant::now() + STEP_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(steps: &[(Option<&str>, &str)]) -> StartupScript {
+        let steps: Vec<StartupStep> = steps
+            .iter()
+            .map(|(wait_for, send)| StartupStep {
+                wait_for: wait_for.map(str::to_string),
+                send: send.to_string(),
+            })
+            .collect();
+        StartupScript::new(&steps).unwrap()
+    }
+
+    #[test]
+    fn steps_without_pattern_go_with_the_one_before() {
+        let mut script = script(&[
+            (None, "export LANG=C"),
+            (Some(r"\$ $"), "cd /srv"),
+            (None, "ls"),
+            (Some(r"\$ $"), "exit"),
+        ]);
+        assert_eq!(script.advance(b"Welcome\r\n"), b"export LANG=C\r");
+        assert_eq!(script.waiting_for(), Some(r"\$ $"));
+        assert_eq!(script.advance(b"user@host:~$ "), b"cd /srv\rls\r");
+        assert!(!script.is_finished());
+        // Output from before the last line was sent no longer counts
+        assert_eq!(script.advance(b"cd /srv\r\n"), b"");
+        assert_eq!(script.advance(b"app  logs\r\nuser@host:/srv$ "), b"exit\r");
+        assert!(script.is_finished());
+        assert_eq!(script.deadline(), None);
+        assert_eq!(script.advance(b"$ "), b"");
+    }
+
+    #[test]
+    fn pattern_split_across_chunks() {
+        let mut script = script(&[(Some("[Pp]assword: $"), "hunter2")]);
+        assert_eq!(script.advance(b"sudo: Pass"), b"");
+        assert_eq!(script.advance(b"word: "), b"hunter2\r");
+    }
+
+    #[test]
+    fn coloured_prompt_matches() {
+        let mut script = script(&[(Some(r"user@host:~\$ $"), "uptime")]);
+        let prompt = b"\x1b[1;32muser@host\x1b[0m:\x1b[1;34m~\x1b[0m$ \x1b[?2004h";
+        assert_eq!(script.advance(prompt), b"uptime\r");
+    }
+
+    #[test]
+    fn long_output_is_trimmed_but_still_matches() {
+        let mut script = script(&[(Some(r"\$ $"), "whoami")]);
+        let motd = vec![b'x'; 3 * MAX_BUFFER];
+        assert_eq!(script.advance(&motd), b"");
+        assert!(script.output.len() <= MAX_BUFFER);
+        assert_eq!(script.advance(b"\r\n$"), b"");
+        assert_eq!(script.advance(b" "), b"whoami\r");
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        let steps = [StartupStep {
+            wait_for: Some("(".into()),
+            send: "x".into(),
+        }];
+        assert!(StartupScript::new(&steps).is_err());
+    }
+}
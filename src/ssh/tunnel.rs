@@ -1,34 +1,220 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use russh::client;
+use russh::Channel;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
+use tokio::task::{JoinHandle, JoinSet};
+use uuid::Uuid;
 
 use crate::app::SshEvent;
-use crate::models::tunnel::TunnelConfig;
+use crate::models::tunnel::{TunnelConfig, TunnelType};
+use crate::ssh::socks;
 
-/// Start a local port forwarding tunnel in a background Tokio task.
+/// Local target of an active remote forward.
+#[derive(Debug, Clone)]
+pub struct RemoteForwardTarget {
+    pub local_host: String,
+    pub local_port: u16,
+    pub stats: Arc<TunnelStats>,
+    /// Hands channels the server opens to the tunnel task, which runs
+    /// them so stopping the tunnel also closes them
+    pub channels: async_channel::Sender<Channel<client::Msg>>,
+}
+
+/// Local targets of active remote forwards, keyed by the address and port
+/// the server listens on. Shared with `ClientHandler`, which looks up the
+/// target when the server opens a `forwarded-tcpip` channel.
+pub type RemoteForwardTargets = Arc<std::sync::Mutex<HashMap<(String, u32), RemoteForwardTarget>>>;
+
+/// Live counters for one tunnel, updated by its connection tasks.
+#[derive(Debug, Default)]
+pub struct TunnelStats {
+    pub active_connections: AtomicUsize,
+    pub total_connections: AtomicU64,
+    /// Bytes sent from the local side into the SSH channel
+    pub bytes_sent: AtomicU64,
+    /// Bytes received from the SSH channel and written locally
+    pub bytes_received: AtomicU64,
+}
+
+/// Point-in-time copy of `TunnelStats` that can be sent to the UI thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TunnelStatsSnapshot {
+    pub active_connections: usize,
+    pub total_connections: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl TunnelStats {
+    pub fn snapshot(&self) -> TunnelStatsSnapshot {
+        TunnelStatsSnapshot {
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct RunningTunnel {
+    config: TunnelConfig,
+    task: JoinHandle<()>,
+    stats: Arc<TunnelStats>,
+}
+
+/// Tunnels running on one SSH session. Dropping the registry aborts every
+/// tunnel task, which closes their listeners and open connections.
+/// Connections of remote forwards run in their tunnel's task as well.
+pub struct TunnelRegistry {
+    session: Arc<Mutex<client::Handle<crate::ssh::handler::ClientHandler>>>,
+    remote_forwards: RemoteForwardTargets,
+    event_tx: async_channel::Sender<SshEvent>,
+    tunnels: HashMap<Uuid, RunningTunnel>,
+}
+
+impl TunnelRegistry {
+    pub fn new(
+        session: Arc<Mutex<client::Handle<crate::ssh::handler::ClientHandler>>>,
+        remote_forwards: RemoteForwardTargets,
+        event_tx: async_channel::Sender<SshEvent>,
+    ) -> Self {
+        Self {
+            session,
+            remote_forwards,
+            event_tx,
+            tunnels: HashMap::new(),
+        }
+    }
+
+    /// Start a tunnel, replacing a running tunnel with the same id.
+    pub async fn start(&mut self, config: TunnelConfig) {
+        self.stop(&config.id).await;
+
+        let stats = Arc::new(TunnelStats::default());
+        let task = start_tunnel(
+            self.session.clone(),
+            config.clone(),
+            self.remote_forwards.clone(),
+            stats.clone(),
+            self.event_tx.clone(),
+        );
+        self.tunnels.insert(config.id, RunningTunnel { config, task, stats });
+    }
+
+    /// Stop a running tunnel. Does nothing if it was not running.
+    pub async fn stop(&mut self, id: &Uuid) {
+        let Some(tunnel) = self.tunnels.remove(id) else {
+            return;
+        };
+        tunnel.task.abort();
+
+        if let Some(key) = self.remove_remote_forward(&tunnel) {
+            let sess = self.session.lock().await;
+            if let Err(e) = sess.cancel_tcpip_forward(key.0, key.1).await {
+                log::warn!("Failed to cancel remote forward {}: {e}", tunnel.config.name);
+            }
+        }
+    }
+
+    /// Stop every tunnel and cancel its remote forwards, used when the tab
+    /// leaves a connection that other tabs keep using.
+    pub async fn shutdown(&mut self) {
+        let ids: Vec<Uuid> = self.tunnels.keys().copied().collect();
+        for id in ids {
+            self.stop(&id).await;
+        }
+    }
+
+    /// Abort every tunnel without talking to the server, used when the
+    /// connection is gone.
+    pub fn stop_all(&mut self) {
+        let tunnels: Vec<RunningTunnel> = self.tunnels.drain().map(|(_, tunnel)| tunnel).collect();
+        for tunnel in tunnels {
+            tunnel.task.abort();
+            self.remove_remote_forward(&tunnel);
+        }
+    }
+
+    /// Forget the listener of a remote forward tunnel. Returns its address
+    /// if the listener was this tunnel's, and not another tab's that made
+    /// this one fail.
+    fn remove_remote_forward(&self, tunnel: &RunningTunnel) -> Option<(String, u32)> {
+        if tunnel.config.tunnel_type != TunnelType::RemoteForward {
+            return None;
+        }
+        let key = (tunnel.config.remote_host.clone(), tunnel.config.remote_port as u32);
+        let mut remote_forwards = self.remote_forwards.lock().unwrap();
+        match remote_forwards.get(&key) {
+            Some(target) if Arc::ptr_eq(&target.stats, &tunnel.stats) => {
+                remote_forwards.remove(&key);
+                Some(key)
+            }
+            _ => None,
+        }
+    }
+
+    /// Drop tunnels whose task has ended (failed to bind, listener error, ...).
+    pub fn prune_finished(&mut self) {
+        self.tunnels.retain(|_, tunnel| !tunnel.task.is_finished());
+    }
+
+    pub fn snapshots(&self) -> Vec<(Uuid, TunnelStatsSnapshot)> {
+        self.tunnels
+            .iter()
+            .map(|(id, tunnel)| (*id, tunnel.stats.snapshot()))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tunnels.is_empty()
+    }
+}
+
+impl Drop for TunnelRegistry {
+    fn drop(&mut self) {
+        for tunnel in self.tunnels.values() {
+            tunnel.task.abort();
+        }
+    }
+}
+
+/// Start a port forwarding tunnel in a background Tokio task.
 pub fn start_tunnel(
     session: Arc<Mutex<client::Handle<crate::ssh::handler::ClientHandler>>>,
     config: TunnelConfig,
+    remote_forwards: RemoteForwardTargets,
+    stats: Arc<TunnelStats>,
     event_tx: async_channel::Sender<SshEvent>,
-) {
+) -> JoinHandle<()> {
     let tunnel_id = config.id;
     tokio::spawn(async move {
-        match run_tunnel(session, &config, event_tx.clone()).await {
-            Ok(()) => {}
-            Err(e) => {
-                let _ = event_tx
-                    .send(SshEvent::TunnelFailed(tunnel_id, e.to_string()))
-                    .await;
+        let result = match config.tunnel_type {
+            TunnelType::LocalForward => {
+                run_tunnel(session, &config, stats, event_tx.clone()).await
+            }
+            TunnelType::RemoteForward => {
+                run_remote_forward(session, &config, remote_forwards, stats, event_tx.clone()).await
             }
+            TunnelType::DynamicForward => {
+                run_dynamic_forward(session, &config, stats, event_tx.clone()).await
+            }
+        };
+        if let Err(e) = result {
+            let _ = event_tx
+                .send(SshEvent::TunnelFailed(tunnel_id, e.to_string()))
+                .await;
         }
-    });
+    })
 }
 
 async fn run_tunnel(
     session: Arc<Mutex<client::Handle<crate::ssh::handler::ClientHandler>>>,
     config: &TunnelConfig,
+    stats: Arc<TunnelStats>,
     event_tx: async_channel::Sender<SshEvent>,
 ) -> Result<(), anyhow::Error> {
     let bind_addr = format!("{}:{}", config.local_host, config.local_port);
@@ -41,12 +227,19 @@ async fn run_tunnel(
     let remote_host = config.remote_host.clone();
     let remote_port = config.remote_port as u32;
 
+    // Owned by this task so aborting the tunnel also closes its connections
+    let mut connections = JoinSet::new();
+
     loop {
-        let (mut tcp_stream, _peer_addr) = listener.accept().await?;
+        let (tcp_stream, _peer_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            Some(_) = connections.join_next() => continue,
+        };
         let session = session.clone();
         let remote_host = remote_host.clone();
+        let stats = stats.clone();
 
-        tokio::spawn(async move {
+        connections.spawn(async move {
             let sess = session.lock().await;
             let channel = match sess
                 .channel_open_direct_tcpip(&remote_host, remote_port, "127.0.0.1", 0)
@@ -60,35 +253,223 @@ async fn run_tunnel(
             };
             drop(sess);
 
-            let (mut tcp_read, mut tcp_write) = tcp_stream.split();
-            let mut channel = channel;
-
-            let mut buf = vec![0u8; 8192];
-            loop {
-                tokio::select! {
-                    n = tcp_read.read(&mut buf) => {
-                        match n {
-                            Ok(0) | Err(_) => break,
-                            Ok(n) => {
-                                if channel.data(&buf[..n]).await.is_err() {
-                                    break;
-                                }
-                            }
+            proxy_channel(channel, tcp_stream, &stats).await;
+        });
+    }
+}
+
+/// Ask the server to listen on `remote_host:remote_port`. Incoming
+/// connections arrive through `ClientHandler::server_channel_open_forwarded_tcpip`.
+async fn run_remote_forward(
+    session: Arc<Mutex<client::Handle<crate::ssh::handler::ClientHandler>>>,
+    config: &TunnelConfig,
+    remote_forwards: RemoteForwardTargets,
+    stats: Arc<TunnelStats>,
+    event_tx: async_channel::Sender<SshEvent>,
+) -> Result<(), anyhow::Error> {
+    let key = (config.remote_host.clone(), config.remote_port as u32);
+    let (channel_tx, channel_rx) = async_channel::unbounded();
+    {
+        // Tabs sharing a connection share its remote forwards as well
+        let mut targets = remote_forwards.lock().unwrap();
+        if targets.contains_key(&key) {
+            return Err(anyhow::anyhow!(
+                "{}:{} is already forwarded by another tab on this connection",
+                config.remote_host,
+                config.remote_port
+            ));
+        }
+        targets.insert(
+            key.clone(),
+            RemoteForwardTarget {
+                local_host: config.local_host.clone(),
+                local_port: config.local_port,
+                stats: stats.clone(),
+                channels: channel_tx,
+            },
+        );
+    }
+
+    let mut sess = session.lock().await;
+    let result = sess
+        .tcpip_forward(config.remote_host.clone(), config.remote_port as u32)
+        .await;
+    drop(sess);
+
+    if let Err(e) = result {
+        remote_forwards.lock().unwrap().remove(&key);
+        return Err(anyhow::anyhow!(
+            "Server refused to listen on {}:{}: {e}",
+            config.remote_host,
+            config.remote_port
+        ));
+    }
+
+    let _ = event_tx
+        .send(SshEvent::TunnelEstablished(config.id))
+        .await;
+
+    // The handler passes on the channels the server opens. Running them
+    // here means aborting the tunnel also closes its connections.
+    let mut connections = JoinSet::new();
+
+    loop {
+        let channel = tokio::select! {
+            Ok(channel) = channel_rx.recv() => channel,
+            Some(_) = connections.join_next() => continue,
+            else => return Ok(()),
+        };
+        connections.spawn(forward_to_local(
+            channel,
+            config.local_host.clone(),
+            config.local_port,
+            stats.clone(),
+        ));
+    }
+}
+
+/// Run a local SOCKS4/4a/5 proxy. Each client gets its own direct-tcpip
+/// channel to the destination it asked for.
+async fn run_dynamic_forward(
+    session: Arc<Mutex<client::Handle<crate::ssh::handler::ClientHandler>>>,
+    config: &TunnelConfig,
+    stats: Arc<TunnelStats>,
+    event_tx: async_channel::Sender<SshEvent>,
+) -> Result<(), anyhow::Error> {
+    let bind_addr = format!("{}:{}", config.local_host, config.local_port);
+    let listener = TcpListener::bind(&bind_addr).await?;
+
+    let _ = event_tx
+        .send(SshEvent::TunnelEstablished(config.id))
+        .await;
+
+    let mut connections = JoinSet::new();
+
+    loop {
+        let (mut tcp_stream, peer_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            Some(_) = connections.join_next() => continue,
+        };
+        let session = session.clone();
+        let stats = stats.clone();
+
+        connections.spawn(async move {
+            let request = match socks::read_request(&mut tcp_stream).await {
+                Ok(request) => request,
+                Err(e) => {
+                    log::warn!("Rejected SOCKS client {peer_addr}: {e}");
+                    return;
+                }
+            };
+
+            let sess = session.lock().await;
+            let channel = sess
+                .channel_open_direct_tcpip(
+                    &request.host,
+                    request.port as u32,
+                    &peer_addr.ip().to_string(),
+                    peer_addr.port() as u32,
+                )
+                .await;
+            drop(sess);
+
+            match channel {
+                Ok(channel) => {
+                    if socks::send_reply(&mut tcp_stream, request.version, true).await.is_ok() {
+                        proxy_channel(channel, tcp_stream, &stats).await;
+                    }
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to open direct-tcpip channel to {}:{}: {e}",
+                        request.host,
+                        request.port
+                    );
+                    let _ = socks::send_reply(&mut tcp_stream, request.version, false).await;
+                }
+            }
+        });
+    }
+}
+
+/// Find the local target for a `forwarded-tcpip` channel. Servers may report
+/// a normalised bind address, so fall back to matching on the port alone.
+pub fn lookup_remote_forward(
+    remote_forwards: &RemoteForwardTargets,
+    connected_address: &str,
+    connected_port: u32,
+) -> Option<RemoteForwardTarget> {
+    let table = remote_forwards.lock().unwrap();
+    if let Some(target) = table.get(&(connected_address.to_string(), connected_port)) {
+        return Some(target.clone());
+    }
+    let mut by_port = table
+        .iter()
+        .filter(|((_, port), _)| *port == connected_port)
+        .map(|(_, target)| target.clone());
+    match (by_port.next(), by_port.next()) {
+        (Some(target), None) => Some(target),
+        _ => None,
+    }
+}
+
+/// Connect a channel opened by the server for a remote forward to its local target.
+async fn forward_to_local(
+    channel: Channel<client::Msg>,
+    local_host: String,
+    local_port: u16,
+    stats: Arc<TunnelStats>,
+) {
+    match TcpStream::connect((local_host.as_str(), local_port)).await {
+        Ok(tcp_stream) => proxy_channel(channel, tcp_stream, &stats).await,
+        Err(e) => {
+            log::error!("Failed to connect remote forward to {local_host}:{local_port}: {e}");
+            let _ = channel.close().await;
+        }
+    }
+}
+
+/// Copy data in both directions between an SSH channel and a TCP stream
+/// until either side closes.
+async fn proxy_channel(
+    mut channel: Channel<client::Msg>,
+    mut tcp_stream: TcpStream,
+    stats: &TunnelStats,
+) {
+    stats.active_connections.fetch_add(1, Ordering::Relaxed);
+    stats.total_connections.fetch_add(1, Ordering::Relaxed);
+
+    let (mut tcp_read, mut tcp_write) = tcp_stream.split();
+
+    let mut buf = vec![0u8; 8192];
+    loop {
+        tokio::select! {
+            n = tcp_read.read(&mut buf) => {
+                match n {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if channel.data(&buf[..n]).await.is_err() {
+                            break;
                         }
+                        stats.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
                     }
-                    msg = channel.wait() => {
-                        match msg {
-                            Some(russh::ChannelMsg::Data { data }) => {
-                                if tcp_write.write_all(&data).await.is_err() {
-                                    break;
-                                }
-                            }
-                            Some(russh::ChannelMsg::Eof) | None => break,
-                            _ => {}
+                }
+            }
+            msg = channel.wait() => {
+                match msg {
+                    Some(russh::ChannelMsg::Data { data }) => {
+                        if tcp_write.write_all(&data).await.is_err() {
+                            break;
                         }
+                        stats.bytes_received.fetch_add(data.len() as u64, Ordering::Relaxed);
                     }
+                    Some(russh::ChannelMsg::Eof) | None => break,
+                    _ => {}
                 }
             }
-        });
+        }
     }
+
+    let _ = channel.close().await;
+    stats.active_connections.fetch_sub(1, Ordering::Relaxed);
 }
@@ -0,0 +1,359 @@
+use rand::RngCore;
+use russh::client;
+use russh::{Channel, ChannelStream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::process::Command;
+use uuid::Uuid;
+
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::AppError;
+
+/// The only X11 authentication protocol that is forwarded
+pub const AUTH_PROTOCOL: &str = "MIT-MAGIC-COOKIE-1";
+const COOKIE_LEN: usize = 16;
+/// Fixed part of the connection setup an X11 client sends first
+const SETUP_HEADER_LEN: usize = 12;
+/// Displays `:N` listen on TCP port 6000 + N
+const X11_BASE_PORT: u16 = 6000;
+
+/// Where the local X server listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayAddress {
+    Unix(PathBuf),
+    Tcp { host: String, port: u16 },
+}
+
+/// A parsed `DISPLAY` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Display {
+    /// The value as set, which is what `xauth` looks cookies up by
+    pub name: String,
+    pub address: DisplayAddress,
+    pub screen: u32,
+}
+
+impl Display {
+    /// Parse `[host]:display[.screen]`. An empty host or `unix` means the
+    /// local socket, and a value starting with `/` is a socket path itself,
+    /// as XQuartz sets it.
+    pub fn parse(name: &str) -> Option<Self> {
+        let (host, rest) = name.rsplit_once(':')?;
+        let (number, screen) = match rest.split_once('.') {
+            Some((number, screen)) => (number, screen.parse().ok()?),
+            None => (rest, 0),
+        };
+        let number: u16 = number.parse().ok()?;
+
+        let address = if host.starts_with('/') {
+            DisplayAddress::Unix(PathBuf::from(format!("{host}:{number}")))
+        } else if host.is_empty() || host == "unix" {
+            DisplayAddress::Unix(PathBuf::from(format!("/tmp/.X11-unix/X{number}")))
+        } else {
+            DisplayAddress::Tcp {
+                host: host.to_string(),
+                port: X11_BASE_PORT.checked_add(number)?,
+            }
+        };
+        Some(Self {
+            name: name.to_string(),
+            address,
+            screen,
+        })
+    }
+}
+
+/// X11 forwarding for one connection. The server only ever sees a random
+/// cookie; connections presenting it get an untrusted cookie from the local
+/// X server swapped in on their way to it, as with `ssh -X`.
+pub struct X11Forwarding {
+    pub display: Display,
+    fake_cookie: [u8; COOKIE_LEN],
+}
+
+impl X11Forwarding {
+    /// Forward to the display in `$DISPLAY`
+    pub async fn from_environment() -> Result<Self, AppError> {
+        let name = std::env::var("DISPLAY")
+            .map_err(|_| AppError::Config("X11 forwarding needs DISPLAY to be set".into()))?;
+        let display = Display::parse(&name)
+            .ok_or_else(|| AppError::Config(format!("Cannot parse DISPLAY {name:?}")))?;
+
+        // Find out now rather than on the first remote window whether the X
+        // server hands out untrusted cookies. The cookie expires unused.
+        untrusted_cookie(&name).await?;
+
+        let mut fake_cookie = [0u8; COOKIE_LEN];
+        rand::thread_rng().fill_bytes(&mut fake_cookie);
+        Ok(Self {
+            display,
+            fake_cookie,
+        })
+    }
+
+    /// The cookie to send with the `x11-req` channel request
+    pub fn fake_cookie_hex(&self) -> String {
+        self.fake_cookie.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+/// Have the X server issue an untrusted `MIT-MAGIC-COOKIE-1` for `display`.
+/// Clients using it cannot read other windows or the keyboard, and the
+/// server drops it a minute after its last client disconnects, so every
+/// forwarded connection asks for its own.
+async fn untrusted_cookie(display: &str) -> Result<Vec<u8>, AppError> {
+    // xauth only writes cookies to a file, keep it where nobody else looks
+    let dir = std::env::temp_dir().join(format!("grustyssh-xauth-{}", Uuid::new_v4()));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let result = generate_cookie(display, &dir.join("xauthfile")).await;
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        log::warn!("Failed to remove {}: {e}", dir.display());
+    }
+    result
+}
+
+async fn generate_cookie(display: &str, file: &Path) -> Result<Vec<u8>, AppError> {
+    let generate = Command::new("xauth")
+        .arg("-q")
+        .arg("-f")
+        .arg(file)
+        .args(["generate", display, AUTH_PROTOCOL, "untrusted"])
+        .output()
+        .await
+        .map_err(|e| AppError::Config(format!("Cannot run xauth: {e}")))?;
+    if !generate.status.success() {
+        // The first line only says the new file does not exist yet
+        let stderr = String::from_utf8_lossy(&generate.stderr);
+        return Err(AppError::Config(format!(
+            "The X server did not issue an untrusted cookie: {}",
+            stderr.lines().last().unwrap_or_default().trim()
+        )));
+    }
+
+    let list = Command::new("xauth")
+        .arg("-f")
+        .arg(file)
+        .arg("list")
+        .output()
+        .await
+        .map_err(|e| AppError::Config(format!("Cannot run xauth: {e}")))?;
+    parse_cookie(&list.stdout)
+        .ok_or_else(|| AppError::Config("xauth generated no cookie".into()))
+}
+
+/// The cookie in `xauth list` output:
+/// `host/unix:0  MIT-MAGIC-COOKIE-1  0123456789abcdef…`
+fn parse_cookie(output: &[u8]) -> Option<Vec<u8>> {
+    String::from_utf8_lossy(output).lines().find_map(|line| {
+        let mut fields = line.split_whitespace().skip(1);
+        if fields.next()? != AUTH_PROTOCOL {
+            return None;
+        }
+        decode_hex(fields.next()?)
+    })
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Connect a forwarded `x11` channel to the local display.
+pub async fn forward_to_display(channel: Channel<client::Msg>, x11: Arc<X11Forwarding>) {
+    if let Err(e) = proxy(channel.into_stream(), &x11).await {
+        log::warn!("Forwarded X11 connection failed: {e}");
+    }
+}
+
+async fn proxy(mut channel_stream: ChannelStream<client::Msg>, x11: &X11Forwarding) -> Result<(), AppError> {
+    let setup = ConnectionSetup::read(&mut channel_stream).await?;
+    if setup.auth_name != AUTH_PROTOCOL.as_bytes() || setup.auth_data != x11.fake_cookie {
+        return Err(AppError::Connection(
+            "The X11 client did not present the forwarded cookie".into(),
+        ));
+    }
+    let cookie = untrusted_cookie(&x11.display.name).await?;
+    let setup = setup.encode(AUTH_PROTOCOL.as_bytes(), &cookie);
+
+    match &x11.display.address {
+        DisplayAddress::Unix(path) => {
+            let stream = UnixStream::connect(path).await.map_err(|e| {
+                AppError::Connection(format!("Cannot connect to X11 socket {}: {e}", path.display()))
+            })?;
+            relay(channel_stream, stream, &setup).await
+        }
+        DisplayAddress::Tcp { host, port } => {
+            let stream = TcpStream::connect((host.as_str(), *port)).await.map_err(|e| {
+                AppError::Connection(format!("Cannot connect to X11 display {host}:{port}: {e}"))
+            })?;
+            relay(channel_stream, stream, &setup).await
+        }
+    }
+}
+
+/// Send the rewritten setup to the X server, then copy data both ways
+/// until either side closes
+async fn relay<S: AsyncRead + AsyncWrite + Unpin>(
+    mut channel_stream: ChannelStream<client::Msg>,
+    mut display: S,
+    setup: &[u8],
+) -> Result<(), AppError> {
+    display.write_all(setup).await?;
+    if let Err(e) = tokio::io::copy_bidirectional(&mut channel_stream, &mut display).await {
+        log::debug!("Forwarded X11 connection ended: {e}");
+    }
+    Ok(())
+}
+
+/// The connection setup request that starts every X11 connection
+struct ConnectionSetup {
+    header: [u8; SETUP_HEADER_LEN],
+    auth_name: Vec<u8>,
+    auth_data: Vec<u8>,
+}
+
+impl ConnectionSetup {
+    async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, AppError> {
+        let mut header = [0u8; SETUP_HEADER_LEN];
+        reader.read_exact(&mut header).await?;
+        let name_len = read_u16(&header, 6)?;
+        let data_len = read_u16(&header, 8)?;
+
+        let mut auth_name = vec![0u8; padded(name_len)];
+        reader.read_exact(&mut auth_name).await?;
+        auth_name.truncate(name_len);
+        let mut auth_data = vec![0u8; padded(data_len)];
+        reader.read_exact(&mut auth_data).await?;
+        auth_data.truncate(data_len);
+
+        Ok(Self {
+            header,
+            auth_name,
+            auth_data,
+        })
+    }
+
+    /// The request with other authentication, in the client's byte order
+    fn encode(&self, auth_name: &[u8], auth_data: &[u8]) -> Vec<u8> {
+        let mut out = self.header.to_vec();
+        write_u16(&mut out, 6, auth_name.len() as u16);
+        write_u16(&mut out, 8, auth_data.len() as u16);
+        for field in [auth_name, auth_data] {
+            out.extend_from_slice(field);
+            out.resize(out.len() + padded(field.len()) - field.len(), 0);
+        }
+        out
+    }
+}
+
+/// Fields are padded to a multiple of four bytes
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// The first byte of the setup gives the client's byte order
+fn read_u16(header: &[u8], offset: usize) -> Result<usize, AppError> {
+    let bytes = [header[offset], header[offset + 1]];
+    match header[0] {
+        b'B' => Ok(u16::from_be_bytes(bytes) as usize),
+        b'l' => Ok(u16::from_le_bytes(bytes) as usize),
+        other => Err(AppError::Connection(format!(
+            "Invalid X11 byte order {other:#04x}"
+        ))),
+    }
+}
+
+fn write_u16(header: &mut [u8], offset: usize, value: u16) {
+    let bytes = match header[0] {
+        b'B' => value.to_be_bytes(),
+        _ => value.to_le_bytes(),
+    };
+    header[offset..offset + 2].copy_from_slice(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A connection setup as an X11 client sends it, in `order`
+    fn setup(order: u8, auth_name: &[u8], auth_data: &[u8]) -> Vec<u8> {
+        let u16_bytes = |value: u16| match order {
+            b'B' => value.to_be_bytes(),
+            _ => value.to_le_bytes(),
+        };
+        let mut out = vec![order, 0];
+        out.extend_from_slice(&u16_bytes(11));
+        out.extend_from_slice(&u16_bytes(0));
+        out.extend_from_slice(&u16_bytes(auth_name.len() as u16));
+        out.extend_from_slice(&u16_bytes(auth_data.len() as u16));
+        out.extend_from_slice(&[0, 0]);
+        for field in [auth_name, auth_data] {
+            out.extend_from_slice(field);
+            out.resize(out.len() + padded(field.len()) - field.len(), 0);
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn rewrites_the_setup_in_both_byte_orders() {
+        let fake = [0x11; COOKIE_LEN];
+        let real = [0x22; 18];
+        for order in [b'B', b'l'] {
+            let sent = setup(order, AUTH_PROTOCOL.as_bytes(), &fake);
+            let parsed = ConnectionSetup::read(&mut sent.as_slice()).await.unwrap();
+            assert_eq!(parsed.auth_name, AUTH_PROTOCOL.as_bytes());
+            assert_eq!(parsed.auth_data, fake);
+
+            let rewritten = parsed.encode(AUTH_PROTOCOL.as_bytes(), &real);
+            assert_eq!(rewritten, setup(order, AUTH_PROTOCOL.as_bytes(), &real));
+            assert_eq!(parsed.encode(b"", b""), setup(order, b"", b""));
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_an_unknown_byte_order() {
+        let sent = setup(b'x', AUTH_PROTOCOL.as_bytes(), &[0; COOKIE_LEN]);
+        assert!(ConnectionSetup::read(&mut sent.as_slice()).await.is_err());
+    }
+
+    #[test]
+    fn parses_display_names() {
+        let local = Display::parse(":1.2").unwrap();
+        assert_eq!(local.address, DisplayAddress::Unix("/tmp/.X11-unix/X1".into()));
+        assert_eq!(local.screen, 2);
+        assert_eq!(
+            Display::parse("localhost:10.0").unwrap().address,
+            DisplayAddress::Tcp {
+                host: "localhost".into(),
+                port: 6010
+            }
+        );
+        assert_eq!(
+            Display::parse("/private/tmp/com.apple.launchd.abc/org.xquartz:0")
+                .unwrap()
+                .address,
+            DisplayAddress::Unix("/private/tmp/com.apple.launchd.abc/org.xquartz:0".into())
+        );
+        assert!(Display::parse("localhost").is_none());
+    }
+
+    #[test]
+    fn decodes_xauth_cookies() {
+        assert_eq!(
+            parse_cookie(b"host/unix:0  MIT-MAGIC-COOKIE-1  00ff10\n"),
+            Some(vec![0x00, 0xff, 0x10])
+        );
+        assert_eq!(parse_cookie(b"host/unix:0  XDM-AUTHORIZATION-1  00ff10\n"), None);
+        assert_eq!(decode_hex("00ff10"), Some(vec![0x00, 0xff, 0x10]));
+        assert_eq!(decode_hex("0f0"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+}
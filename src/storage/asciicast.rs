@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::error::AppError;
+
+/// First line of an asciicast v2 file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    /// Pauses longer than this many seconds are shortened on playback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<HashMap<String, String>>,
+}
+
+/// Writes terminal output with its timing to an asciicast v2 file, the
+/// format of asciinema (https://docs.asciinema.org/manual/asciicast/v2/).
+pub struct CastRecorder {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+    /// Start of a UTF-8 sequence split across two chunks of output
+    utf8_tail: Vec<u8>,
+}
+
+impl CastRecorder {
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        title: &str,
+        term: &str,
+    ) -> Result<Self, AppError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+
+        let header = Header {
+            version: 2,
+            width,
+            height,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs() as i64),
+            idle_time_limit: None,
+            title: Some(title.to_string()),
+            env: Some(HashMap::from([("TERM".to_string(), term.to_string())])),
+        };
+
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        Ok(Self {
+            writer,
+            path: path.to_path_buf(),
+            started: Instant::now(),
+            utf8_tail: Vec::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record output received from the server
+    pub fn output(&mut self, data: &[u8]) -> Result<(), AppError> {
+        let mut bytes = std::mem::take(&mut self.utf8_tail);
+        bytes.extend_from_slice(data);
+
+        // Events are JSON strings, so hold back an incomplete character
+        // until the rest of it arrives
+        let complete = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => bytes.len(),
+        };
+        self.utf8_tail = bytes.split_off(complete);
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        self.event("o", &text)
+    }
+
+    /// Record a terminal size change
+    pub fn resize(&mut self, cols: u32, rows: u32) -> Result<(), AppError> {
+        self.event("r", &format!("{cols}x{rows}"))
+    }
+
+    pub fn flush(&mut self) -> Result<(), AppError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> Result<(), AppError> {
+        let time = self.started.elapsed().as_secs_f64();
+        serde_json::to_writer(&mut self.writer, &(time, code, data))?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// What happens at one point of a recording
+#[derive(Debug, Clone, PartialEq)]
+pub enum CastEventKind {
+    Output(String),
+    Resize { cols: u32, rows: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    /// Seconds since the start of the recording
+    pub time: f64,
+    pub kind: CastEventKind,
+}
+
+/// A recording loaded for playback
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub header: Header,
+    pub events: Vec<CastEvent>,
+}
+
+impl Recording {
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |event| event.time)
+    }
+}
+
+/// Read an asciicast v2 file. Input and marker events are skipped, and
+/// pauses are shortened to the header's `idle_time_limit`.
+pub fn load(path: &Path) -> Result<Recording, AppError> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header_line = lines
+        .next()
+        .ok_or_else(|| AppError::Other("The recording is empty".into()))??;
+    let header: Header = serde_json::from_str(&header_line)?;
+    if header.version != 2 {
+        return Err(AppError::Other(format!(
+            "Unsupported asciicast version {}, only version 2 can be played",
+            header.version
+        )));
+    }
+
+    let mut events = Vec::new();
+    let mut last_original = 0.0;
+    let mut last_adjusted = 0.0;
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (time, code, data): (f64, String, String) = serde_json::from_str(&line)
+            .map_err(|e| AppError::Other(format!("Invalid event on line {}: {e}", index + 2)))?;
+
+        let mut gap = (time - last_original).max(0.0);
+        if let Some(limit) = header.idle_time_limit {
+            gap = gap.min(limit);
+        }
+        last_original = time;
+        last_adjusted += gap;
+
+        let kind = match code.as_str() {
+            "o" => CastEventKind::Output(data),
+            "r" => match parse_size(&data) {
+                Some((cols, rows)) => CastEventKind::Resize { cols, rows },
+                None => continue,
+            },
+            _ => continue,
+        };
+        events.push(CastEvent {
+            time: last_adjusted,
+            kind,
+        });
+    }
+
+    Ok(Recording { header, events })
+}
+
+/// Parse the `COLSxROWS` of a resize event
+fn parse_size(data: &str) -> Option<(u32, u32)> {
+    let (cols, rows) = data.split_once('x')?;
+    Some((cols.parse().ok()?, rows.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    /// A fresh directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("grustyssh-test-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, lines: &[&str]) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, lines.join("\n")).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const HEADER: &str = r#"{"version":2,"width":80,"height":24,"idle_time_limit":2.0}"#;
+
+    fn output(text: &str) -> CastEventKind {
+        CastEventKind::Output(text.to_string())
+    }
+
+    #[test]
+    fn recording_round_trip() {
+        let dir = TempDir::new();
+        let path = dir.0.join("session.cast");
+        let mut recorder = CastRecorder::create(&path, 120, 40, "web01", "xterm-256color").unwrap();
+        // "é" is C3 A9, split between two reads
+        recorder.output(b"caf\xc3").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        recorder.output(b"\xa9 ok\r\n").unwrap();
+        recorder.resize(100, 30).unwrap();
+        recorder.flush().unwrap();
+        assert!(CastRecorder::create(&path, 80, 24, "again", "xterm").is_err());
+
+        let recording = load(&path).unwrap();
+        assert_eq!(recording.header.width, 120);
+        assert_eq!(recording.header.height, 40);
+        assert_eq!(recording.header.title.as_deref(), Some("web01"));
+        assert_eq!(recording.header.env.unwrap()["TERM"], "xterm-256color");
+
+        let kinds: Vec<CastEventKind> = recording.events.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [output("caf"), output("é ok\r\n"), CastEventKind::Resize { cols: 100, rows: 30 }]
+        );
+        let times: Vec<f64> = recording.events.iter().map(|e| e.time).collect();
+        assert!(times[1] - times[0] >= 0.02);
+        assert!(times[2] >= times[1]);
+    }
+
+    #[test]
+    fn idle_time_is_limited_and_other_events_skipped() {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "idle.cast",
+            &[
+                HEADER,
+                r#"[0.5, "o", "a"]"#,
+                r#"[1.0, "i", "typed"]"#,
+                "",
+                r#"[10.0, "o", "b"]"#,
+                r#"[10.5, "m", "marker"]"#,
+                r#"[11.0, "r", "100x30"]"#,
+                r#"[12.0, "r", "wide"]"#,
+            ],
+        );
+
+        let recording = load(&path).unwrap();
+        assert_eq!(
+            recording.events,
+            [
+                CastEvent { time: 0.5, kind: output("a") },
+                // 9 idle seconds shortened to 2, after the skipped input
+                CastEvent { time: 3.0, kind: output("b") },
+                CastEvent { time: 4.0, kind: CastEventKind::Resize { cols: 100, rows: 30 } },
+            ]
+        );
+        assert_eq!(recording.duration(), 4.0);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let dir = TempDir::new();
+        let path = dir.write("bad.cast", &[HEADER, r#"[0.5, "o", "a"]"#, "[0.7, \"o\""]);
+        let error = load(&path).unwrap_err().to_string();
+        assert!(error.contains("line 3"), "{error}");
+
+        let path = dir.write("v1.cast", &[r#"{"version":1,"width":80,"height":24}"#]);
+        assert!(load(&path).is_err());
+
+        let path = dir.write("empty.cast", &[]);
+        assert!(load(&path).is_err());
+    }
+}
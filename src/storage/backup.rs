@@ -0,0 +1,242 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::error::AppError;
+
+/// Backup format version. Version 2 added passphrase encryption; version 1
+/// files are plain and still accepted.
+pub const BACKUP_VERSION: u32 = 2;
+
+const KDF_ARGON2ID: &str = "argon2id";
+const CIPHER_XCHACHA20POLY1305: &str = "xchacha20poly1305";
+
+// Argon2id cost for new backups (64 MiB, 3 passes), as recommended by RFC 9106
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 1;
+
+// Refuse KDF costs beyond these when reading, so a crafted file cannot
+// exhaust memory or hang the restore. They leave a little room above the
+// cost of new backups and nothing more.
+const MAX_MEMORY_KIB: u32 = 2 * ARGON2_MEMORY_KIB;
+const MAX_ITERATIONS: u32 = 2 * ARGON2_ITERATIONS;
+const MAX_PARALLELISM: u32 = 4;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// The encrypted contents of a backup together with everything needed to
+/// derive its key again, except the passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedPayload {
+    pub kdf: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Whether a backup file is passphrase-protected, so the caller knows to
+/// ask for the passphrase before importing it.
+pub fn is_encrypted(json: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(json)
+        .ok()
+        .and_then(|value| value.get("encrypted").map(|e| !e.is_null()))
+        .unwrap_or(false)
+}
+
+/// Reject backups written by a newer version of the app.
+pub fn check_version(version: u32) -> Result<(), AppError> {
+    if version > BACKUP_VERSION {
+        return Err(AppError::Other(format!(
+            "The backup has format version {version}, this version of GrustySSH reads up to {BACKUP_VERSION}"
+        )));
+    }
+    Ok(())
+}
+
+/// Encrypt `plaintext` under a key derived from `passphrase`. `context`
+/// names the kind of backup and is authenticated, so a key backup cannot
+/// be passed off as a profile backup.
+pub fn seal(plaintext: &[u8], passphrase: &str, context: &str) -> Result<EncryptedPayload, AppError> {
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+
+    let key = derive_key(
+        passphrase,
+        &salt,
+        ARGON2_MEMORY_KIB,
+        ARGON2_ITERATIONS,
+        ARGON2_PARALLELISM,
+    )?;
+    let cipher = XChaCha20Poly1305::new_from_slice(&key[..])
+        .map_err(|e| AppError::Other(format!("Cannot set up backup encryption: {e}")))?;
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| AppError::Other("Failed to encrypt the backup".into()))?;
+
+    Ok(EncryptedPayload {
+        kdf: KDF_ARGON2ID.into(),
+        memory_kib: ARGON2_MEMORY_KIB,
+        iterations: ARGON2_ITERATIONS,
+        parallelism: ARGON2_PARALLELISM,
+        salt: BASE64.encode(salt),
+        cipher: CIPHER_XCHACHA20POLY1305.into(),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+/// Decrypt a payload written by [`seal`] with the same `context`.
+pub fn open(
+    payload: &EncryptedPayload,
+    passphrase: &str,
+    context: &str,
+) -> Result<Zeroizing<Vec<u8>>, AppError> {
+    if payload.kdf != KDF_ARGON2ID || payload.cipher != CIPHER_XCHACHA20POLY1305 {
+        return Err(AppError::Other(format!(
+            "Unsupported backup encryption {}/{}",
+            payload.kdf, payload.cipher
+        )));
+    }
+    if payload.memory_kib > MAX_MEMORY_KIB
+        || payload.iterations > MAX_ITERATIONS
+        || payload.parallelism > MAX_PARALLELISM
+    {
+        return Err(AppError::Other(
+            "The backup asks for an unreasonable key derivation cost".into(),
+        ));
+    }
+
+    let salt = decode(&payload.salt)?;
+    let nonce = decode(&payload.nonce)?;
+    let ciphertext = decode(&payload.ciphertext)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(AppError::Other("Invalid backup file: bad nonce".into()));
+    }
+
+    let key = derive_key(
+        passphrase,
+        &salt,
+        payload.memory_kib,
+        payload.iterations,
+        payload.parallelism,
+    )?;
+    let cipher = XChaCha20Poly1305::new_from_slice(&key[..])
+        .map_err(|e| AppError::Other(format!("Cannot set up backup decryption: {e}")))?;
+    let plaintext = cipher
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| AppError::Other("Wrong passphrase, or the backup is damaged".into()))?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Result<Zeroizing<[u8; 32]>, AppError> {
+    let params = Params::new(memory_kib, iterations, parallelism, Some(32))
+        .map_err(|e| AppError::Other(format!("Invalid key derivation parameters: {e}")))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+        .map_err(|e| AppError::Other(format!("Key derivation failed: {e}")))?;
+    Ok(key)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, AppError> {
+    BASE64
+        .decode(value)
+        .map_err(|e| AppError::Other(format!("Invalid backup file: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXT: &str = "grustyssh-test-backup";
+
+    #[test]
+    fn seal_and_open() {
+        let payload = seal(b"secret profiles", "correct horse", CONTEXT).unwrap();
+        assert_eq!(payload.memory_kib, ARGON2_MEMORY_KIB);
+        assert_eq!(payload.iterations, ARGON2_ITERATIONS);
+
+        let plaintext = open(&payload, "correct horse", CONTEXT).unwrap();
+        assert_eq!(plaintext.as_slice(), b"secret profiles");
+
+        // A wrong passphrase and a backup of another kind fail the same way
+        for (passphrase, context) in [("wrong horse", CONTEXT), ("correct horse", "grustyssh-other")] {
+            let err = open(&payload, passphrase, context).unwrap_err();
+            assert_eq!(err.to_string(), "Wrong passphrase, or the backup is damaged");
+        }
+    }
+
+    #[test]
+    fn open_rejects_tampered_payloads() {
+        let payload = seal(b"secret keys", "passphrase", CONTEXT).unwrap();
+
+        let mut ciphertext = decode(&payload.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = EncryptedPayload {
+            ciphertext: BASE64.encode(ciphertext),
+            ..payload.clone()
+        };
+        assert!(open(&tampered, "passphrase", CONTEXT).is_err());
+
+        // Refused before any key derivation is attempted
+        let costly = EncryptedPayload {
+            memory_kib: MAX_MEMORY_KIB + 1,
+            ..payload.clone()
+        };
+        let err = open(&costly, "passphrase", CONTEXT).unwrap_err();
+        assert!(err.to_string().contains("unreasonable"));
+
+        let unknown = EncryptedPayload {
+            cipher: "aes256-gcm".into(),
+            ..payload
+        };
+        assert!(open(&unknown, "passphrase", CONTEXT).is_err());
+    }
+
+    #[test]
+    fn detects_encrypted_backups() {
+        assert!(is_encrypted(r#"{"version": 2, "profiles": [], "encrypted": {"kdf": "argon2id"}}"#));
+        assert!(!is_encrypted(r#"{"version": 2, "profiles": [], "encrypted": null}"#));
+        assert!(!is_encrypted(r#"{"version": 1, "profiles": []}"#));
+        assert!(!is_encrypted("not json"));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        assert!(check_version(1).is_ok());
+        assert!(check_version(BACKUP_VERSION).is_ok());
+        assert!(check_version(BACKUP_VERSION + 1).is_err());
+    }
+}
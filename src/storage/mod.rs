@@ -1,2 +1,7 @@
+pub mod asciicast;
+pub mod backup;
 pub mod paths;
 pub mod profiles;
+pub mod secrets;
+pub mod session_log;
+pub mod ssh_config;
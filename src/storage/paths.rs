@@ -10,3 +10,8 @@ pub fn private_key_path(id: &Uuid) -> PathBuf {
 pub fn public_key_path(id: &Uuid) -> PathBuf {
     config::keys_dir().join(format!("{}.pub", id))
 }
+
+/// OpenSSH user certificate for a key, named like `ssh-keygen -s` output.
+pub fn certificate_path(id: &Uuid) -> PathBuf {
+    config::keys_dir().join(format!("{}-cert.pub", id))
+}
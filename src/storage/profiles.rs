@@ -1,14 +1,39 @@
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 use crate::config;
 use crate::error::AppError;
-use crate::models::connection::ConnectionProfile;
+use crate::keys::generate;
+use crate::keys::storage::KeyStore;
+use crate::models::connection::{AuthMethod, ConnectionProfile};
+use crate::storage::backup::{self, EncryptedPayload, BACKUP_VERSION};
+use crate::storage::ssh_config::{self, JumpSpec, SshConfigHost};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProfileBackup {
     pub version: u32,
+    #[serde(default)]
     pub profiles: Vec<ConnectionProfile>,
+    /// The serialized `profiles`, when the backup is passphrase-protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<EncryptedPayload>,
+}
+
+/// Authenticated with encrypted profile backups
+const PROFILE_BACKUP_CONTEXT: &str = "grustyssh-profile-backup";
+
+/// Bookkeeping while turning ssh_config hosts into profiles.
+struct SshConfigImport<'a> {
+    hosts: &'a [SshConfigHost],
+    /// Profile for each alias or ProxyJump spec, existing or created
+    by_name: HashMap<String, Uuid>,
+    resolving: Vec<String>,
+    /// New profiles with their identity files, kept apart until every
+    /// selected host resolved so a failed import changes nothing
+    created: Vec<(ConnectionProfile, Vec<PathBuf>)>,
 }
 
 #[derive(Debug)]
@@ -63,18 +88,98 @@ impl ProfileStore {
         self.profiles.iter().find(|p| &p.id == id)
     }
 
-    pub fn export_backup(&self) -> Result<String, AppError> {
-        Ok(serde_json::to_string_pretty(&ProfileBackup {
-            version: 1,
-            profiles: self.profiles.clone(),
-        })?)
+    /// Resolve the jump hosts of `profile` in connection order. Jump hosts that
+    /// have their own jump hosts are expanded in place, like nested ProxyJump.
+    pub fn jump_chain(&self, profile: &ConnectionProfile) -> Result<Vec<ConnectionProfile>, AppError> {
+        let mut chain = Vec::new();
+        let mut visiting = vec![profile.id];
+        self.collect_jump_hosts(profile, &mut visiting, &mut chain)?;
+        Ok(chain)
+    }
+
+    fn collect_jump_hosts(
+        &self,
+        profile: &ConnectionProfile,
+        visiting: &mut Vec<Uuid>,
+        chain: &mut Vec<ConnectionProfile>,
+    ) -> Result<(), AppError> {
+        for id in &profile.jump_hosts {
+            if visiting.contains(id) {
+                return Err(AppError::Config(format!(
+                    "Jump hosts of \"{}\" form a loop",
+                    profile.name
+                )));
+            }
+            let jump = self.get(id).ok_or_else(|| {
+                AppError::Config(format!(
+                    "A jump host of \"{}\" no longer exists",
+                    profile.name
+                ))
+            })?;
+            visiting.push(*id);
+            self.collect_jump_hosts(jump, visiting, chain)?;
+            visiting.pop();
+            chain.push(jump.clone());
+        }
+        Ok(())
     }
 
-    pub fn import_backup(&mut self, json: &str) -> Result<usize, AppError> {
+    /// Serialize `profiles`, a copy taken from the store. With a passphrase
+    /// they are encrypted, see [`backup::seal`]. Deriving the key takes a
+    /// while, so call this off the main thread without the store locked.
+    pub fn export_backup(
+        profiles: &[ConnectionProfile],
+        passphrase: Option<&str>,
+    ) -> Result<String, AppError> {
+        let backup = match passphrase {
+            Some(passphrase) => {
+                let plaintext = serde_json::to_vec(profiles)?;
+                ProfileBackup {
+                    version: BACKUP_VERSION,
+                    profiles: Vec::new(),
+                    encrypted: Some(backup::seal(&plaintext, passphrase, PROFILE_BACKUP_CONTEXT)?),
+                }
+            }
+            None => ProfileBackup {
+                version: BACKUP_VERSION,
+                profiles: profiles.to_vec(),
+                encrypted: None,
+            },
+        };
+        Ok(serde_json::to_string_pretty(&backup)?)
+    }
+
+    /// The profiles as an ssh_config snippet, see [`ssh_config::render`].
+    pub fn export_ssh_config(&self, key_store: &KeyStore) -> String {
+        ssh_config::render(&self.profiles, key_store)
+    }
+
+    /// Read the profiles of a backup. Encrypted backups need `passphrase`,
+    /// use [`backup::is_encrypted`] to find out beforehand. Like
+    /// [`Self::export_backup`] this is slow and needs no store.
+    pub fn read_backup(
+        json: &str,
+        passphrase: Option<&str>,
+    ) -> Result<Vec<ConnectionProfile>, AppError> {
         let backup: ProfileBackup = serde_json::from_str(json)
             .map_err(|e| AppError::Other(format!("Invalid backup file: {e}")))?;
+        backup::check_version(backup.version)?;
+        match backup.encrypted {
+            Some(ref payload) => {
+                let passphrase = passphrase
+                    .ok_or_else(|| AppError::Other("This backup is protected by a passphrase".into()))?;
+                let plaintext = backup::open(payload, passphrase, PROFILE_BACKUP_CONTEXT)?;
+                serde_json::from_slice::<Vec<ConnectionProfile>>(&plaintext)
+                    .map_err(|e| AppError::Other(format!("Invalid backup file: {e}")))
+            }
+            None => Ok(backup.profiles),
+        }
+    }
+
+    /// Add the profiles read from a backup, skipping those already present.
+    pub fn import_backup(&mut self, profiles: Vec<ConnectionProfile>) -> Result<usize, AppError> {
         let mut imported = 0;
-        for profile in backup.profiles {
+        for profile in profiles {
             if self.profiles.iter().any(|p| p.id == profile.id) {
                 continue;
             }
@@ -84,4 +189,119 @@ impl ProfileStore {
         self.save()?;
         Ok(imported)
     }
+
+    /// Add a profile for each of the `selected` ssh_config hosts. Identity
+    /// files go into the key manager and ProxyJump hops become jump hosts,
+    /// created as profiles of their own when no profile has their name yet.
+    /// Hosts whose name is already taken are left alone. When a host cannot
+    /// be resolved, e.g. for a ProxyJump loop, nothing is imported.
+    pub fn import_ssh_config(
+        &mut self,
+        hosts: &[SshConfigHost],
+        selected: &[String],
+        key_store: &mut KeyStore,
+    ) -> Result<usize, AppError> {
+        let mut import = SshConfigImport {
+            hosts,
+            by_name: self
+                .profiles
+                .iter()
+                .map(|p| (p.name.clone(), p.id))
+                .collect(),
+            resolving: Vec::new(),
+            created: Vec::new(),
+        };
+        for host in hosts.iter().filter(|h| selected.contains(&h.alias)) {
+            if !import.by_name.contains_key(&host.alias) {
+                Self::import_ssh_config_host(host, &mut import)?;
+            }
+        }
+
+        let imported = import.created.len();
+        for (mut profile, identity_files) in import.created {
+            // Like ssh, use the first identity file that can be loaded
+            for path in identity_files.iter().filter(|p| p.exists()) {
+                match generate::find_or_import_keypair(key_store, path) {
+                    Ok(id) => {
+                        profile.auth_method = AuthMethod::PublicKey;
+                        profile.key_pair_id = Some(id);
+                        break;
+                    }
+                    Err(e) => log::warn!("Skipping identity {} of {}: {e}", path.display(), profile.name),
+                }
+            }
+            self.profiles.push(profile);
+        }
+        self.save()?;
+        Ok(imported)
+    }
+
+    fn import_ssh_config_host(
+        host: &SshConfigHost,
+        import: &mut SshConfigImport,
+    ) -> Result<Uuid, AppError> {
+        if import.resolving.contains(&host.alias) {
+            return Err(AppError::Config(format!(
+                "ProxyJump of \"{}\" forms a loop",
+                host.alias
+            )));
+        }
+
+        let mut profile = ConnectionProfile::new(
+            host.alias.clone(),
+            host.hostname.clone(),
+            host.port,
+            host.user.clone().unwrap_or_else(ssh_config::local_username),
+        );
+        profile.tunnels = host.forwards.clone();
+        profile.agent_forwarding = host.forward_agent;
+        profile.x11_forwarding = host.forward_x11;
+
+        import.resolving.push(host.alias.clone());
+        for spec in &host.proxy_jump {
+            let id = Self::import_ssh_config_jump(spec, import)?;
+            profile.jump_hosts.push(id);
+        }
+        import.resolving.pop();
+
+        let id = profile.id;
+        import.by_name.insert(profile.name.clone(), id);
+        import.created.push((profile, host.identity_files.clone()));
+        Ok(id)
+    }
+
+    /// Find or create the profile for one ProxyJump hop. A hop naming another
+    /// ssh_config host uses that host's settings, with the hop's user and
+    /// port taking precedence.
+    fn import_ssh_config_jump(
+        spec: &JumpSpec,
+        import: &mut SshConfigImport,
+    ) -> Result<Uuid, AppError> {
+        let name = spec.to_string();
+        if let Some(id) = import.by_name.get(&name) {
+            return Ok(*id);
+        }
+
+        let host = match import.hosts.iter().find(|h| h.alias == spec.host) {
+            Some(host) => {
+                let mut host = host.clone();
+                host.user = spec.user.clone().or(host.user);
+                host.port = spec.port.unwrap_or(host.port);
+                host
+            }
+            None => SshConfigHost {
+                alias: spec.host.clone(),
+                hostname: spec.host.clone(),
+                port: spec.port.unwrap_or(22),
+                user: spec.user.clone(),
+                identity_files: Vec::new(),
+                forwards: Vec::new(),
+                proxy_jump: Vec::new(),
+                forward_agent: false,
+                forward_x11: false,
+            },
+        };
+        let host = SshConfigHost { alias: name, ..host };
+        Self::import_ssh_config_host(&host, import)
+    }
 }
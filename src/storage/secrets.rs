@@ -0,0 +1,234 @@
+use secret_service::{EncryptionType, SecretService};
+use uuid::Uuid;
+use zbus::DBusError;
+use zeroize::Zeroizing;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+use crate::config;
+use crate::error::AppError;
+
+/// Attribute every item we create carries, so other applications' items
+/// never match our lookups
+const APPLICATION_ATTRIBUTE: &str = "grustyssh";
+
+/// What a saved secret unlocks. Together with the profile or key UUID it
+/// identifies the secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretKind {
+    ProfilePassword,
+    KeyPassphrase,
+}
+
+impl SecretKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            SecretKind::ProfilePassword => "password",
+            SecretKind::KeyPassphrase => "key-passphrase",
+        }
+    }
+}
+
+/// Where `store` puts secrets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The Secret Service keyring
+    Keyring,
+    /// The plaintext fallback file, used when no keyring daemon is running
+    File,
+}
+
+/// Find out where secrets would be saved. A keyring that is running but
+/// fails is an error, not a reason to write secrets to the file.
+pub async fn backend() -> Result<Backend, AppError> {
+    match SecretService::connect(EncryptionType::Dh).await {
+        Ok(_) => Ok(Backend::Keyring),
+        Err(e) if is_unavailable(&e) => Ok(Backend::File),
+        Err(e) => Err(AppError::Other(format!("Cannot reach the keyring: {e}"))),
+    }
+}
+
+/// Look up a saved secret, in the Secret Service if one is running and in
+/// the fallback file otherwise.
+pub async fn lookup(kind: SecretKind, id: Uuid) -> Option<Zeroizing<String>> {
+    match keyring_lookup(kind, id).await {
+        Ok(secret) => secret,
+        Err(e) if is_unavailable(&e) => {
+            log::debug!("Secret Service unavailable, using {}: {e}", config::secrets_path().display());
+            file_lookup(kind, id)
+        }
+        Err(e) => {
+            log::warn!("Failed to read a saved secret: {e}");
+            None
+        }
+    }
+}
+
+/// Save a secret, replacing an earlier one for the same `kind` and `id`.
+/// `label` is what keyring managers such as Seahorse show.
+pub async fn store(kind: SecretKind, id: Uuid, label: &str, secret: &str) -> Result<(), AppError> {
+    match keyring_store(kind, id, label, secret).await {
+        Ok(()) => Ok(()),
+        Err(e) if is_unavailable(&e) => {
+            log::info!("Secret Service unavailable, saving to {}: {e}", config::secrets_path().display());
+            file_store(kind, id, secret)
+        }
+        Err(e) => Err(AppError::Other(format!("Cannot save to the keyring: {e}"))),
+    }
+}
+
+/// Remove a saved secret from both the Secret Service and the fallback file.
+pub async fn forget(kind: SecretKind, id: Uuid) {
+    if let Err(e) = keyring_forget(kind, id).await {
+        log::debug!("Secret Service unavailable: {e}");
+    }
+    if let Err(e) = file_forget(kind, id) {
+        log::warn!("Failed to update {}: {e}", config::secrets_path().display());
+    }
+}
+
+/// No keyring daemon, or no session bus to reach one. Other errors, such as
+/// a timeout, a denied request or a dismissed unlock prompt, must not
+/// silently fall back to the file.
+fn is_unavailable(error: &secret_service::Error) -> bool {
+    match error {
+        secret_service::Error::Unavailable => true,
+        secret_service::Error::Zbus(zbus::Error::MethodError(name, _, _)) => {
+            is_missing_service(name.as_str())
+        }
+        secret_service::Error::Zbus(zbus::Error::FDO(e)) => is_missing_service(e.name().as_str()),
+        secret_service::Error::ZbusFdo(e) => is_missing_service(e.name().as_str()),
+        _ => false,
+    }
+}
+
+/// D-Bus errors for a name nobody owns and that cannot be activated
+fn is_missing_service(error_name: &str) -> bool {
+    matches!(
+        error_name,
+        "org.freedesktop.DBus.Error.ServiceUnknown" | "org.freedesktop.DBus.Error.NameHasNoOwner"
+    )
+}
+
+fn attributes(kind: SecretKind, id: &str) -> HashMap<&'static str, &str> {
+    HashMap::from([
+        ("application", APPLICATION_ATTRIBUTE),
+        ("kind", kind.as_str()),
+        ("id", id),
+    ])
+}
+
+async fn keyring_lookup(
+    kind: SecretKind,
+    id: Uuid,
+) -> Result<Option<Zeroizing<String>>, secret_service::Error> {
+    let service = SecretService::connect(EncryptionType::Dh).await?;
+    let id = id.to_string();
+    let found = service.search_items(attributes(kind, &id)).await?;
+
+    let item = match (found.unlocked.into_iter().next(), found.locked.into_iter().next()) {
+        (Some(item), _) => item,
+        // Unlocking may show the keyring's own password prompt
+        (None, Some(item)) => {
+            item.unlock().await?;
+            item
+        }
+        (None, None) => return Ok(None),
+    };
+    let secret = Zeroizing::new(item.get_secret().await?);
+    Ok(Some(Zeroizing::new(String::from_utf8_lossy(&secret).into_owned())))
+}
+
+async fn keyring_store(
+    kind: SecretKind,
+    id: Uuid,
+    label: &str,
+    secret: &str,
+) -> Result<(), secret_service::Error> {
+    let service = SecretService::connect(EncryptionType::Dh).await?;
+    let collection = service.get_default_collection().await?;
+    if collection.is_locked().await? {
+        collection.unlock().await?;
+    }
+    let id = id.to_string();
+    collection
+        .create_item(label, attributes(kind, &id), secret.as_bytes(), true, "text/plain")
+        .await?;
+    Ok(())
+}
+
+async fn keyring_forget(kind: SecretKind, id: Uuid) -> Result<(), secret_service::Error> {
+    let service = SecretService::connect(EncryptionType::Dh).await?;
+    let id = id.to_string();
+    let found = service.search_items(attributes(kind, &id)).await?;
+    for item in found.unlocked.iter().chain(found.locked.iter()) {
+        item.delete().await?;
+    }
+    Ok(())
+}
+
+// Fallback for systems without a keyring daemon: a JSON map in the data
+// directory that only the user can read, like ~/.pgpass or git's
+// credential store.
+
+fn file_key(kind: SecretKind, id: Uuid) -> String {
+    format!("{}:{id}", kind.as_str())
+}
+
+/// Read the fallback file. A file that exists but cannot be read or parsed
+/// is an error, so callers never write an empty map over saved secrets.
+fn read_file() -> Result<BTreeMap<String, String>, AppError> {
+    let path = config::secrets_path();
+    let data = match std::fs::read_to_string(&path) {
+        Ok(data) => Zeroizing::new(data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(AppError::Other(format!("Cannot read {}: {e}", path.display()))),
+    };
+    serde_json::from_str(&data).map_err(|e| {
+        AppError::Other(format!(
+            "{} is not valid, fix or remove it to save secrets again: {e}",
+            path.display()
+        ))
+    })
+}
+
+fn write_file(secrets: &BTreeMap<String, String>) -> Result<(), AppError> {
+    let path = config::secrets_path();
+    let data = Zeroizing::new(serde_json::to_string_pretty(secrets)?);
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)?;
+    // The mode only applies to new files
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(data.as_bytes())?;
+    Ok(())
+}
+
+fn file_lookup(kind: SecretKind, id: Uuid) -> Option<Zeroizing<String>> {
+    match read_file() {
+        Ok(mut secrets) => secrets.remove(&file_key(kind, id)).map(Zeroizing::new),
+        Err(e) => {
+            log::warn!("{e}");
+            None
+        }
+    }
+}
+
+fn file_store(kind: SecretKind, id: Uuid, secret: &str) -> Result<(), AppError> {
+    let mut secrets = read_file()?;
+    secrets.insert(file_key(kind, id), secret.to_string());
+    write_file(&secrets)
+}
+
+fn file_forget(kind: SecretKind, id: Uuid) -> Result<(), AppError> {
+    let mut secrets = read_file()?;
+    if secrets.remove(&file_key(kind, id)).is_some() {
+        write_file(&secrets)?;
+    }
+    Ok(())
+}
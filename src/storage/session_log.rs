@@ -0,0 +1,209 @@
+use chrono::Local;
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::models::connection::{ConnectionProfile, LogFormat, SessionLogConfig};
+use crate::ssh::ansi::AnsiStripper;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Transcript of one terminal session. Files are appended to, so a template
+/// without `{time}` collects all sessions of a day in one file.
+pub struct SessionLog {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    format: LogFormat,
+    timestamps: bool,
+    log_input: bool,
+    stripper: AnsiStripper,
+    at_line_start: bool,
+    /// Typed characters of the current input line, text logs only
+    input_line: Vec<u8>,
+}
+
+impl SessionLog {
+    /// Open the log file for a session with `profile` in `directory`
+    pub fn open(
+        config: &SessionLogConfig,
+        directory: &Path,
+        profile: &ConnectionProfile,
+    ) -> Result<Self, AppError> {
+        std::fs::create_dir_all(directory)?;
+        let path = directory.join(file_name(&config.file_template, profile));
+        // Transcripts can contain secrets, keep them private like ~/.ssh
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&path)?;
+
+        let mut log = Self {
+            writer: BufWriter::new(file),
+            path,
+            format: config.format,
+            timestamps: config.timestamps,
+            log_input: config.log_input,
+            stripper: AnsiStripper::default(),
+            at_line_start: true,
+            input_line: Vec::new(),
+        };
+        if log.format == LogFormat::Text {
+            let header = format!(
+                "=== Session started {} ({}@{}:{}) ===\n",
+                Local::now().format(TIMESTAMP_FORMAT),
+                profile.username,
+                profile.hostname,
+                profile.port
+            );
+            log.write(header.as_bytes())?;
+        }
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record data received from the server
+    pub fn output(&mut self, data: &[u8]) -> Result<(), AppError> {
+        match self.format {
+            LogFormat::Raw => self.write(data),
+            LogFormat::Text => {
+                let text = self.stripper.strip(data);
+                self.write_text(&text)
+            }
+        }
+    }
+
+    /// Record what was typed, if the configuration asks for it. Text logs
+    /// get one `> ` line per line entered.
+    pub fn input(&mut self, data: &[u8]) -> Result<(), AppError> {
+        if !self.log_input {
+            return Ok(());
+        }
+        if self.format == LogFormat::Raw {
+            return self.write(data);
+        }
+
+        for &b in data {
+            match b {
+                b'\r' | b'\n' => {
+                    let mut line = b"> ".to_vec();
+                    line.append(&mut self.input_line);
+                    line.push(b'\n');
+                    if !self.at_line_start {
+                        self.write_text(b"\n")?;
+                    }
+                    self.write_text(&line)?;
+                }
+                // Backspace and DEL
+                0x08 | 0x7f => {
+                    self.input_line.pop();
+                }
+                c if c >= 0x20 => self.input_line.push(c),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), AppError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Write text, starting each line with a timestamp if enabled
+    fn write_text(&mut self, text: &[u8]) -> Result<(), AppError> {
+        if !self.timestamps {
+            if let Some(&last) = text.last() {
+                self.at_line_start = last == b'\n';
+            }
+            return self.write(text);
+        }
+
+        for line in text.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start {
+                let stamp = format!("[{}] ", Local::now().format(TIMESTAMP_FORMAT));
+                self.write(stamp.as_bytes())?;
+            }
+            self.write(line)?;
+            self.at_line_start = line.ends_with(b"\n");
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), AppError> {
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+}
+
+// However the session ends, close the transcript properly
+impl Drop for SessionLog {
+    fn drop(&mut self) {
+        if self.format == LogFormat::Text {
+            let footer = format!(
+                "{}=== Session ended {} ===\n",
+                if self.at_line_start { "" } else { "\n" },
+                Local::now().format(TIMESTAMP_FORMAT)
+            );
+            let _ = self.write(footer.as_bytes());
+        }
+        if let Err(e) = self.flush() {
+            log::error!("Failed to write {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Expand the placeholders of a file name template
+pub fn file_name(template: &str, profile: &ConnectionProfile) -> String {
+    let now = Local::now();
+    let name = template
+        .replace("{profile}", &profile.name)
+        .replace("{host}", &profile.hostname)
+        .replace("{user}", &profile.username)
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H%M%S").to_string());
+
+    // Profile names may contain anything, the result must stay one file
+    let name: String = name
+        .chars()
+        .map(|c| if c == '/' || c == '\0' { '_' } else { c })
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "session.log".into(),
+        name => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str) -> ConnectionProfile {
+        ConnectionProfile::new(name.into(), "db.example.com".into(), 22, "alice".into())
+    }
+
+    #[test]
+    fn placeholders() {
+        let name = file_name("{profile}_{user}@{host}_{date}.log", &profile("prod"));
+        let date = Local::now().format("%Y-%m-%d").to_string();
+        assert_eq!(name, format!("prod_alice@db.example.com_{date}.log"));
+
+        let name = file_name("{time}.log", &profile("prod"));
+        assert_eq!(name.len(), "HHMMSS.log".len());
+        assert!(name[..6].bytes().all(|b| b.is_ascii_digit()));
+    }
+
+    #[test]
+    fn slashes_stay_in_the_file_name() {
+        assert_eq!(file_name("{profile}.log", &profile("web/01")), "web_01.log");
+        assert_eq!(file_name("../{profile}", &profile("x")), ".._x");
+        assert_eq!(file_name("{profile}", &profile("..")), "session.log");
+        assert_eq!(file_name("{profile}", &profile(" ")), "session.log");
+    }
+}
@@ -0,0 +1,749 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::keys::storage::KeyStore;
+use crate::models::connection::{AuthMethod, ConnectionProfile};
+use crate::models::tunnel::{TunnelConfig, TunnelType};
+use crate::ssh::known_hosts::wildcard_match;
+use crate::storage::paths;
+
+/// Nested `Include` depth at which we give up, like OpenSSH
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A `ProxyJump` hop: `[user@]host[:port]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpSpec {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl std::fmt::Display for JumpSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref user) = self.user {
+            write!(f, "{user}@")?;
+        }
+        write!(f, "{}", self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A concrete host from ssh_config with every matching block applied.
+#[derive(Debug, Clone)]
+pub struct SshConfigHost {
+    pub alias: String,
+    pub hostname: String,
+    pub port: u16,
+    pub user: Option<String>,
+    pub identity_files: Vec<PathBuf>,
+    pub forwards: Vec<TunnelConfig>,
+    pub proxy_jump: Vec<JumpSpec>,
+    pub forward_agent: bool,
+    pub forward_x11: bool,
+}
+
+/// A `Host` block, or the options before the first one (patterns `*`).
+#[derive(Debug, Clone)]
+struct Block {
+    patterns: Vec<String>,
+    options: Vec<(String, Vec<String>)>,
+}
+
+impl Block {
+    fn matches(&self, alias: &str) -> bool {
+        let mut matched = false;
+        for pattern in &self.patterns {
+            if let Some(negated) = pattern.strip_prefix('!') {
+                if wildcard_match(negated, alias) {
+                    return false;
+                }
+            } else if wildcard_match(pattern, alias) {
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+/// The user's own config, `~/.ssh/config`.
+pub fn default_config_path() -> Option<PathBuf> {
+    home_dir().map(|home| home.join(".ssh").join("config"))
+}
+
+/// Parse an ssh_config file and resolve every concrete (non-wildcard) host.
+pub fn load(path: &Path) -> Result<Vec<SshConfigHost>, AppError> {
+    let mut blocks = vec![Block {
+        patterns: vec!["*".into()],
+        options: Vec::new(),
+    }];
+    read_blocks(path, 0, &mut blocks)?;
+
+    let mut aliases: Vec<String> = Vec::new();
+    for block in &blocks {
+        for pattern in &block.patterns {
+            let concrete = !pattern.contains(['*', '?', '!']);
+            if concrete && !aliases.contains(pattern) {
+                aliases.push(pattern.clone());
+            }
+        }
+    }
+
+    Ok(aliases
+        .into_iter()
+        .map(|alias| resolve_host(&blocks, alias))
+        .collect())
+}
+
+fn read_blocks(path: &Path, depth: usize, blocks: &mut Vec<Block>) -> Result<(), AppError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(AppError::Config(format!(
+            "Include nested too deeply at {}",
+            path.display()
+        )));
+    }
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| AppError::Config(format!("Cannot read {}: {e}", path.display())))?;
+
+    for line in data.lines() {
+        let Some((key, args)) = split_line(line) else {
+            continue;
+        };
+        match key.as_str() {
+            "host" => blocks.push(Block {
+                patterns: args,
+                options: Vec::new(),
+            }),
+            // Match conditions are not evaluated, their options never apply
+            "match" => blocks.push(Block {
+                patterns: Vec::new(),
+                options: Vec::new(),
+            }),
+            "include" => {
+                let parent_patterns = blocks.last().map(|b| b.patterns.clone()).unwrap_or_default();
+                for pattern in &args {
+                    for included in expand_include(pattern) {
+                        read_blocks(&included, depth + 1, blocks)?;
+                    }
+                }
+                // Lines after the Include still belong to the enclosing block
+                blocks.push(Block {
+                    patterns: parent_patterns,
+                    options: Vec::new(),
+                });
+            }
+            _ => {
+                if let Some(block) = blocks.last_mut() {
+                    block.options.push((key, args));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Relative Include paths are relative to `~/.ssh`, globs are expanded in
+/// lexical order.
+fn expand_include(pattern: &str) -> Vec<PathBuf> {
+    let ssh_dir = home_dir().map(|home| home.join(".ssh"));
+    include_paths(pattern, ssh_dir.as_deref())
+}
+
+fn include_paths(pattern: &str, ssh_dir: Option<&Path>) -> Vec<PathBuf> {
+    let expanded = expand_tilde(pattern);
+    let full = if Path::new(&expanded).is_absolute() {
+        expanded
+    } else {
+        match ssh_dir {
+            Some(dir) => dir.join(expanded).to_string_lossy().into_owned(),
+            None => expanded,
+        }
+    };
+    match glob::glob(&full) {
+        Ok(paths) => paths.filter_map(Result::ok).filter(|p| p.is_file()).collect(),
+        Err(e) => {
+            log::warn!("Invalid Include pattern {pattern}: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Apply every block matching `alias`. As in OpenSSH the first value
+/// obtained for an option wins, except for options that may repeat.
+fn resolve_host(blocks: &[Block], alias: String) -> SshConfigHost {
+    let mut hostname: Option<String> = None;
+    let mut port: Option<u16> = None;
+    let mut user: Option<String> = None;
+    let mut identity_files: Vec<String> = Vec::new();
+    let mut forwards: Vec<TunnelConfig> = Vec::new();
+    let mut proxy_jump: Option<Vec<JumpSpec>> = None;
+    let mut forward_agent: Option<bool> = None;
+    let mut forward_x11: Option<bool> = None;
+
+    for block in blocks.iter().filter(|b| b.matches(&alias)) {
+        for (key, args) in &block.options {
+            let Some(first) = args.first() else {
+                continue;
+            };
+            match key.as_str() {
+                "hostname" => {
+                    hostname.get_or_insert_with(|| first.replace("%h", &alias));
+                }
+                "port" if port.is_none() => port = first.parse().ok(),
+                "user" => {
+                    user.get_or_insert_with(|| first.clone());
+                }
+                "identityfile" => identity_files.push(first.clone()),
+                "localforward" | "remoteforward" | "dynamicforward" => {
+                    match parse_forward(key, args) {
+                        Some(forward) => forwards.push(forward),
+                        None => log::warn!("Skipping unsupported {key} {} for {alias}", args.join(" ")),
+                    }
+                }
+                "proxyjump" => {
+                    proxy_jump.get_or_insert_with(|| parse_proxy_jump(first));
+                }
+                "forwardagent" => {
+                    forward_agent.get_or_insert(first.eq_ignore_ascii_case("yes"));
+                }
+                "forwardx11" => {
+                    forward_x11.get_or_insert(first.eq_ignore_ascii_case("yes"));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let hostname = hostname.unwrap_or_else(|| alias.clone());
+    let identity_files = identity_files
+        .iter()
+        .map(|file| PathBuf::from(expand_tokens(file, &alias, &hostname, user.as_deref())))
+        .collect();
+
+    SshConfigHost {
+        alias,
+        hostname,
+        port: port.unwrap_or(22),
+        user,
+        identity_files,
+        forwards,
+        proxy_jump: proxy_jump.unwrap_or_default(),
+        forward_agent: forward_agent.unwrap_or(false),
+        forward_x11: forward_x11.unwrap_or(false),
+    }
+}
+
+/// Split a config line into its lowercased keyword and arguments.
+/// Accepts both `Key value` and `Key=value`, and double-quoted arguments.
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let key_end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let key = line[..key_end].to_lowercase();
+    let rest = line[key_end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in rest.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+    Some((key, args))
+}
+
+fn parse_proxy_jump(value: &str) -> Vec<JumpSpec> {
+    if value.eq_ignore_ascii_case("none") {
+        return Vec::new();
+    }
+    value
+        .split(',')
+        .filter_map(|hop| {
+            let hop = hop.trim().trim_start_matches("ssh://");
+            let (user, host_port) = match hop.rsplit_once('@') {
+                Some((user, rest)) => (Some(user.to_string()), rest),
+                None => (None, hop),
+            };
+            let (host, port) = split_host_port(host_port)?;
+            let (host, port) = match host {
+                Some(host) => (host, port.parse().ok()),
+                None => (port, None),
+            };
+            Some(JumpSpec { user, host, port })
+        })
+        .collect()
+}
+
+fn parse_forward(key: &str, args: &[String]) -> Option<TunnelConfig> {
+    let (bind_host, listen_port) = parse_listen(args.first()?)?;
+    let mut tunnel = match key {
+        "localforward" => {
+            let (target_host, target_port) = split_host_port(args.get(1)?)?;
+            let mut tunnel = TunnelConfig::new(
+                format!("LocalForward {listen_port}"),
+                listen_port,
+                target_host?,
+                target_port.parse().ok()?,
+            );
+            tunnel.local_host = bind_host.unwrap_or_else(|| "127.0.0.1".into());
+            tunnel
+        }
+        "remoteforward" => {
+            // The single-argument form is a reverse SOCKS proxy, not supported
+            let (target_host, target_port) = split_host_port(args.get(1)?)?;
+            let mut tunnel = TunnelConfig::new(
+                format!("RemoteForward {listen_port}"),
+                target_port.parse().ok()?,
+                bind_host.unwrap_or_else(|| "localhost".into()),
+                listen_port,
+            );
+            tunnel.tunnel_type = TunnelType::RemoteForward;
+            tunnel.local_host = target_host?;
+            tunnel
+        }
+        "dynamicforward" => {
+            let mut tunnel =
+                TunnelConfig::new(format!("DynamicForward {listen_port}"), listen_port, String::new(), 0);
+            tunnel.tunnel_type = TunnelType::DynamicForward;
+            tunnel.local_host = bind_host.unwrap_or_else(|| "127.0.0.1".into());
+            tunnel
+        }
+        _ => return None,
+    };
+    tunnel.enabled = true;
+    Some(tunnel)
+}
+
+/// `[bind_address:]port`, where `*` binds every interface.
+fn parse_listen(spec: &str) -> Option<(Option<String>, u16)> {
+    let (host, port) = split_host_port(spec)?;
+    let host = host.map(|h| if h == "*" { "0.0.0.0".to_string() } else { h });
+    Some((host, port.parse().ok()?))
+}
+
+/// Split `host:port` or `[v6]:port` into its parts. A bare value is
+/// returned as the port with no host.
+fn split_host_port(spec: &str) -> Option<(Option<String>, String)> {
+    if let Some(rest) = spec.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        let port = after.strip_prefix(':')?;
+        return Some((Some(host.to_string()), port.to_string()));
+    }
+    match spec.rsplit_once(':') {
+        Some((host, port)) => Some((Some(host.to_string()), port.to_string())),
+        None => Some((None, spec.to_string())),
+    }
+}
+
+/// Expand `~` and the `%d %u %h %r %%` tokens OpenSSH allows in IdentityFile.
+fn expand_tokens(value: &str, alias: &str, hostname: &str, user: Option<&str>) -> String {
+    let home = home_dir()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let local_user = local_username();
+    let value = expand_tilde(value);
+
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('d') => out.push_str(&home),
+            Some('u') => out.push_str(&local_user),
+            Some('h') => out.push_str(hostname),
+            Some('n') => out.push_str(alias),
+            Some('r') => out.push_str(user.unwrap_or(&local_user)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+fn expand_tilde(value: &str) -> String {
+    match (value.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => value.to_string(),
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf())
+}
+
+/// The login name used when a host has no `User`, as ssh would.
+pub fn local_username() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "root".into())
+}
+
+/// Render profiles as an ssh_config snippet usable with plain `ssh`.
+/// Keys point at the files in the key manager, jump hosts at the `Host`
+/// alias of their profile.
+pub fn render(profiles: &[ConnectionProfile], key_store: &KeyStore) -> String {
+    let aliases = host_aliases(profiles);
+    let mut out = String::from("# Exported from GrustySSH\n");
+
+    for (profile, alias) in profiles.iter().zip(&aliases) {
+        out.push('\n');
+        if *alias != profile.name {
+            out.push_str(&format!("# {}\n", profile.name));
+        }
+        out.push_str(&format!("Host {alias}\n"));
+        out.push_str(&format!("    HostName {}\n", profile.hostname));
+        if profile.port != 22 {
+            out.push_str(&format!("    Port {}\n", profile.port));
+        }
+        out.push_str(&format!("    User {}\n", profile.username));
+
+        let preferred = match profile.auth_method {
+            AuthMethod::Password => Some("password"),
+            AuthMethod::Both => Some("publickey,password"),
+            AuthMethod::KeyboardInteractive => Some("keyboard-interactive"),
+            AuthMethod::PublicKey | AuthMethod::Agent => None,
+            AuthMethod::PublicKeyAndKeyboardInteractive => {
+                out.push_str("    AuthenticationMethods publickey,keyboard-interactive\n");
+                None
+            }
+        };
+        if let Some(methods) = preferred {
+            out.push_str(&format!("    PreferredAuthentications {methods}\n"));
+        }
+
+        if profile.auth_method.uses_key() {
+            match profile.key_pair_id.and_then(|id| key_store.get(&id)) {
+                Some(key) => {
+                    out.push_str(&format!("    # Key \"{}\"\n", key.name));
+                    out.push_str(&format!(
+                        "    IdentityFile {}\n",
+                        quote(&paths::private_key_path(&key.id).to_string_lossy())
+                    ));
+                    if key.certificate_filename.is_some() {
+                        out.push_str(&format!(
+                            "    CertificateFile {}\n",
+                            quote(&paths::certificate_path(&key.id).to_string_lossy())
+                        ));
+                    }
+                    out.push_str("    IdentitiesOnly yes\n");
+                }
+                None => out.push_str("    # The key of this connection no longer exists\n"),
+            }
+        }
+
+        if profile.agent_forwarding {
+            out.push_str("    ForwardAgent yes\n");
+        }
+        if profile.x11_forwarding {
+            out.push_str("    ForwardX11 yes\n");
+        }
+
+        for var in &profile.environment {
+            let assignment = format!("{}={}", var.name, var.value);
+            if assignment.contains(char::is_whitespace) {
+                out.push_str(&format!("    SetEnv \"{assignment}\"\n"));
+            } else {
+                out.push_str(&format!("    SetEnv {assignment}\n"));
+            }
+        }
+
+        if let Some(command) = &profile.remote_command {
+            out.push_str(&format!("    RemoteCommand {command}\n"));
+            out.push_str("    RequestTTY yes\n");
+        }
+
+        let algorithms = &profile.algorithms;
+        for (keyword, list) in [
+            ("KexAlgorithms", &algorithms.kex),
+            ("HostKeyAlgorithms", &algorithms.host_key),
+            ("Ciphers", &algorithms.cipher),
+            ("MACs", &algorithms.mac),
+        ] {
+            if !list.is_empty() {
+                out.push_str(&format!("    {keyword} {}\n", list.join(",")));
+            }
+        }
+
+        let jumps: Vec<&str> = profile
+            .jump_hosts
+            .iter()
+            .filter_map(|id| profiles.iter().position(|p| &p.id == id))
+            .map(|idx| aliases[idx].as_str())
+            .collect();
+        if !jumps.is_empty() {
+            out.push_str(&format!("    ProxyJump {}\n", jumps.join(",")));
+        }
+
+        for tunnel in &profile.tunnels {
+            // Disabled tunnels are kept, but commented out
+            let prefix = if tunnel.enabled { "    " } else { "    # " };
+            let line = match tunnel.tunnel_type {
+                TunnelType::LocalForward => format!(
+                    "LocalForward {}:{} {}:{}",
+                    bracket(&tunnel.local_host),
+                    tunnel.local_port,
+                    bracket(&tunnel.remote_host),
+                    tunnel.remote_port
+                ),
+                TunnelType::RemoteForward => format!(
+                    "RemoteForward {}:{} {}:{}",
+                    bracket(&tunnel.remote_host),
+                    tunnel.remote_port,
+                    bracket(&tunnel.local_host),
+                    tunnel.local_port
+                ),
+                TunnelType::DynamicForward => format!(
+                    "DynamicForward {}:{}",
+                    bracket(&tunnel.local_host),
+                    tunnel.local_port
+                ),
+            };
+            out.push_str(&format!("{prefix}{line}\n"));
+        }
+    }
+    out
+}
+
+/// A unique `Host` pattern per profile: the name without whitespace and
+/// pattern characters.
+fn host_aliases(profiles: &[ConnectionProfile]) -> Vec<String> {
+    let mut aliases: Vec<String> = Vec::with_capacity(profiles.len());
+    for profile in profiles {
+        let mut base: String = profile
+            .name
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-")
+            .chars()
+            .filter(|c| !matches!(c, '*' | '?' | '!' | ',' | '"' | '#'))
+            .collect();
+        if base.is_empty() {
+            base = profile.hostname.clone();
+        }
+        let mut alias = base.clone();
+        let mut n = 2;
+        while aliases.contains(&alias) {
+            alias = format!("{base}-{n}");
+            n += 1;
+        }
+        aliases.push(alias);
+    }
+    aliases
+}
+
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{value}\"")
+    } else {
+        value.to_string()
+    }
+}
+
+fn bracket(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{host}]")
+    } else {
+        host.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("grustyssh-test-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn load_str(contents: &str) -> Vec<SshConfigHost> {
+        let dir = TempDir::new();
+        load(&dir.write("config", contents)).unwrap()
+    }
+
+    fn host<'a>(hosts: &'a [SshConfigHost], alias: &str) -> &'a SshConfigHost {
+        hosts.iter().find(|h| h.alias == alias).unwrap()
+    }
+
+    #[test]
+    fn wildcard_and_negated_host_patterns() {
+        let hosts = load_str(
+            "Host *.example.com !secret.example.com\n\
+             User deploy\n\
+             Host web.example.com secret.example.com db\n\
+             Port 2222\n\
+             Host *\n\
+             User fallback\n",
+        );
+        let aliases: Vec<&str> = hosts.iter().map(|h| h.alias.as_str()).collect();
+        assert_eq!(aliases, ["web.example.com", "secret.example.com", "db"]);
+        assert_eq!(host(&hosts, "web.example.com").user.as_deref(), Some("deploy"));
+        assert_eq!(host(&hosts, "secret.example.com").user.as_deref(), Some("fallback"));
+        assert_eq!(host(&hosts, "db").port, 2222);
+    }
+
+    #[test]
+    fn first_value_wins() {
+        let hosts = load_str(
+            "Host app\n\
+             HostName=10.0.0.5\n\
+             Port 2200\n\
+             IdentityFile \"/keys/app key\"\n\
+             Host *\n\
+             HostName %h.internal\n\
+             Port 22\n\
+             IdentityFile /keys/%h_%r\n\
+             User admin\n\
+             ForwardAgent yes\n",
+        );
+        let app = host(&hosts, "app");
+        assert_eq!(app.hostname, "10.0.0.5");
+        assert_eq!(app.port, 2200);
+        assert_eq!(app.user.as_deref(), Some("admin"));
+        assert!(app.forward_agent);
+        assert!(!app.forward_x11);
+        // IdentityFile repeats instead, with tokens expanded
+        assert_eq!(
+            app.identity_files,
+            [PathBuf::from("/keys/app key"), PathBuf::from("/keys/10.0.0.5_admin")]
+        );
+    }
+
+    #[test]
+    fn bad_port_is_ignored() {
+        let hosts = load_str("Host a\n    Port ssh\nHost b\n    Port 70000\n");
+        assert_eq!(host(&hosts, "a").port, 22);
+        assert_eq!(host(&hosts, "b").port, 22);
+    }
+
+    #[test]
+    fn include_globs_relative_to_ssh_dir() {
+        let ssh_dir = TempDir::new();
+        let b = ssh_dir.write("config.d/20-b.conf", "Host b\n    User bob\n");
+        let a = ssh_dir.write("config.d/10-a.conf", "Host a\n    User alice\n");
+        ssh_dir.write("config.d/notes.txt", "Host ignored\n");
+        assert_eq!(include_paths("config.d/*.conf", Some(&ssh_dir.0)), [a, b]);
+
+        let hosts = load(&ssh_dir.write(
+            "config",
+            &format!(
+                "Host c\n\
+                 Include {}/config.d/*.conf\n\
+                 Port 2222\n",
+                ssh_dir.0.display()
+            ),
+        ))
+        .unwrap();
+        let aliases: Vec<&str> = hosts.iter().map(|h| h.alias.as_str()).collect();
+        assert_eq!(aliases, ["c", "a", "b"]);
+        assert_eq!(host(&hosts, "a").user.as_deref(), Some("alice"));
+        // Lines after the Include still belong to `Host c`
+        assert_eq!(host(&hosts, "c").port, 2222);
+        assert_eq!(host(&hosts, "a").port, 22);
+    }
+
+    fn forward(line: &str) -> Option<TunnelConfig> {
+        let (key, args) = split_line(line).unwrap();
+        parse_forward(&key, &args)
+    }
+
+    #[test]
+    fn local_forwards() {
+        let tunnel = forward("LocalForward 8080 db:5432").unwrap();
+        assert_eq!(tunnel.tunnel_type, TunnelType::LocalForward);
+        assert_eq!((tunnel.local_host.as_str(), tunnel.local_port), ("127.0.0.1", 8080));
+        assert_eq!((tunnel.remote_host.as_str(), tunnel.remote_port), ("db", 5432));
+
+        let tunnel = forward("LocalForward 192.168.1.2:8080 [::1]:80").unwrap();
+        assert_eq!((tunnel.local_host.as_str(), tunnel.local_port), ("192.168.1.2", 8080));
+        assert_eq!((tunnel.remote_host.as_str(), tunnel.remote_port), ("::1", 80));
+
+        let tunnel = forward("LocalForward *:8080 db:5432").unwrap();
+        assert_eq!(tunnel.local_host, "0.0.0.0");
+
+        assert!(forward("LocalForward 8080").is_none());
+        assert!(forward("LocalForward 8080 db:postgres").is_none());
+    }
+
+    #[test]
+    fn remote_and_dynamic_forwards() {
+        let tunnel = forward("RemoteForward 0.0.0.0:9000 localhost:3000").unwrap();
+        assert_eq!(tunnel.tunnel_type, TunnelType::RemoteForward);
+        assert_eq!((tunnel.remote_host.as_str(), tunnel.remote_port), ("0.0.0.0", 9000));
+        assert_eq!((tunnel.local_host.as_str(), tunnel.local_port), ("localhost", 3000));
+        // Reverse SOCKS is not supported
+        assert!(forward("RemoteForward 9000").is_none());
+
+        let tunnel = forward("DynamicForward 1080").unwrap();
+        assert_eq!(tunnel.tunnel_type, TunnelType::DynamicForward);
+        assert_eq!((tunnel.local_host.as_str(), tunnel.local_port), ("127.0.0.1", 1080));
+    }
+
+    #[test]
+    fn proxy_jump_hops() {
+        assert_eq!(
+            parse_proxy_jump("admin@bastion:2222,ssh://gw,ops@[fe80::1]:22"),
+            [
+                JumpSpec {
+                    user: Some("admin".into()),
+                    host: "bastion".into(),
+                    port: Some(2222),
+                },
+                JumpSpec {
+                    user: None,
+                    host: "gw".into(),
+                    port: None,
+                },
+                JumpSpec {
+                    user: Some("ops".into()),
+                    host: "fe80::1".into(),
+                    port: Some(22),
+                },
+            ]
+        );
+        assert!(parse_proxy_jump("none").is_empty());
+        assert!(parse_proxy_jump("NONE").is_empty());
+        assert_eq!(parse_proxy_jump("admin@bastion:2222")[0].to_string(), "admin@bastion:2222");
+    }
+}
@@ -0,0 +1,134 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use libadwaita as adw;
+use adw::prelude::*;
+
+use std::rc::Rc;
+
+use crate::models::connection::AlgorithmPreferences;
+use crate::ssh::algorithms::{self, AlgorithmKind};
+
+/// Entry rows for the five algorithm lists, shared by the connection and
+/// preferences dialogs.
+#[derive(Clone)]
+pub struct AlgorithmRows {
+    pub expander: adw::ExpanderRow,
+    entries: Rc<Vec<(AlgorithmKind, adw::EntryRow)>>,
+}
+
+pub fn build_algorithm_rows(subtitle: &str, prefs: &AlgorithmPreferences) -> AlgorithmRows {
+    let expander = adw::ExpanderRow::builder()
+        .title("Algorithms")
+        .subtitle(subtitle)
+        .build();
+
+    let presets_row = adw::ActionRow::builder()
+        .title("Presets")
+        .subtitle("Lists are comma-separated, most preferred first")
+        .build();
+    let hardened_btn = gtk::Button::builder()
+        .label("Hardened")
+        .tooltip_text("Only modern algorithms")
+        .valign(gtk::Align::Center)
+        .build();
+    let legacy_btn = gtk::Button::builder()
+        .label("Legacy")
+        .tooltip_text("Everything supported, including SHA-1 and CBC for old devices")
+        .valign(gtk::Align::Center)
+        .build();
+    let clear_btn = gtk::Button::builder()
+        .label("Clear")
+        .valign(gtk::Align::Center)
+        .build();
+    presets_row.add_suffix(&hardened_btn);
+    presets_row.add_suffix(&legacy_btn);
+    presets_row.add_suffix(&clear_btn);
+    expander.add_row(&presets_row);
+
+    let mut entries = Vec::new();
+    for (kind, title) in [
+        (AlgorithmKind::Kex, "Key Exchange"),
+        (AlgorithmKind::HostKey, "Host Key"),
+        (AlgorithmKind::Cipher, "Ciphers"),
+        (AlgorithmKind::Mac, "MACs"),
+        (AlgorithmKind::Compression, "Compression"),
+    ] {
+        let row = adw::EntryRow::builder()
+            .title(title)
+            .tooltip_text(format!("Supported: {}", kind.supported().join(", ")))
+            .build();
+        row.connect_changed(|row| row.remove_css_class("error"));
+        expander.add_row(&row);
+        entries.push((kind, row));
+    }
+
+    let rows = AlgorithmRows {
+        expander,
+        entries: Rc::new(entries),
+    };
+    rows.set(prefs);
+
+    let rows_for_hardened = rows.clone();
+    hardened_btn.connect_clicked(move |_| rows_for_hardened.set(&algorithms::hardened()));
+    let rows_for_legacy = rows.clone();
+    legacy_btn.connect_clicked(move |_| rows_for_legacy.set(&algorithms::legacy()));
+    let rows_for_clear = rows.clone();
+    clear_btn.connect_clicked(move |_| rows_for_clear.set(&AlgorithmPreferences::default()));
+
+    rows
+}
+
+impl AlgorithmRows {
+    pub fn set(&self, prefs: &AlgorithmPreferences) {
+        for (kind, row) in self.entries.iter() {
+            row.set_text(&list_of(prefs, *kind).join(","));
+        }
+    }
+
+    /// The entered lists, or `None` after marking the rows that name
+    /// algorithms russh does not implement.
+    pub fn preferences(&self) -> Option<AlgorithmPreferences> {
+        let mut prefs = AlgorithmPreferences::default();
+        let mut valid = true;
+        for (kind, row) in self.entries.iter() {
+            let list: Vec<String> = row
+                .text()
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+            let unsupported = kind.unsupported(&list);
+            if !unsupported.is_empty() {
+                log::warn!("Unsupported algorithms: {}", unsupported.join(", "));
+                row.add_css_class("error");
+                valid = false;
+            }
+            *list_of_mut(&mut prefs, *kind) = list;
+        }
+        if !valid {
+            self.expander.set_expanded(true);
+        }
+        valid.then_some(prefs)
+    }
+}
+
+fn list_of(prefs: &AlgorithmPreferences, kind: AlgorithmKind) -> &[String] {
+    match kind {
+        AlgorithmKind::Kex => &prefs.kex,
+        AlgorithmKind::HostKey => &prefs.host_key,
+        AlgorithmKind::Cipher => &prefs.cipher,
+        AlgorithmKind::Mac => &prefs.mac,
+        AlgorithmKind::Compression => &prefs.compression,
+    }
+}
+
+fn list_of_mut(prefs: &mut AlgorithmPreferences, kind: AlgorithmKind) -> &mut Vec<String> {
+    match kind {
+        AlgorithmKind::Kex => &mut prefs.kex,
+        AlgorithmKind::HostKey => &mut prefs.host_key,
+        AlgorithmKind::Cipher => &mut prefs.cipher,
+        AlgorithmKind::Mac => &mut prefs.mac,
+        AlgorithmKind::Compression => &mut prefs.compression,
+    }
+}
@@ -0,0 +1,183 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use gtk::glib;
+use libadwaita as adw;
+use adw::prelude::*;
+use zeroize::Zeroizing;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::app::{AuthPrompt, KeyUnlockPrompt};
+
+/// Show one keyboard-interactive round and send the answers back to the SSH
+/// task waiting in `authenticate_keyboard_interactive`.
+pub fn prompt_keyboard_interactive(anchor: &impl IsA<gtk::Widget>, prompt: AuthPrompt) {
+    let AuthPrompt {
+        host,
+        name,
+        instructions,
+        fields,
+        response_tx,
+    } = prompt;
+
+    let heading = if name.trim().is_empty() {
+        format!("Authentication for {host}")
+    } else {
+        name.trim().to_string()
+    };
+    let body = if instructions.trim().is_empty() {
+        format!("The server {host} asks for more information.")
+    } else {
+        instructions.trim().to_string()
+    };
+
+    let dialog = adw::AlertDialog::builder()
+        .heading(heading)
+        .body(body)
+        .build();
+
+    let fields_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    let mut entries: Vec<gtk::Editable> = Vec::new();
+    for field in &fields {
+        let label = gtk::Label::builder()
+            .label(field.prompt.trim())
+            .halign(gtk::Align::Start)
+            .wrap(true)
+            .build();
+        fields_box.append(&label);
+
+        // Answers the server does not want echoed (passwords, codes) are masked
+        let entry: gtk::Editable = if field.echo {
+            gtk::Entry::new().upcast()
+        } else {
+            gtk::PasswordEntry::builder()
+                .show_peek_icon(true)
+                .build()
+                .upcast()
+        };
+        fields_box.append(&entry);
+        entries.push(entry);
+    }
+    dialog.set_extra_child(Some(&fields_box));
+
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("continue", "Continue");
+    dialog.set_response_appearance("continue", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("continue"));
+    dialog.set_close_response("cancel");
+
+    // The answer must be sent exactly once, whichever way the dialog ends
+    let response_tx: Rc<RefCell<Option<async_channel::Sender<Option<Vec<Zeroizing<String>>>>>>> =
+        Rc::new(RefCell::new(Some(response_tx)));
+    let entries = Rc::new(entries);
+
+    let send_answer = {
+        let response_tx = response_tx.clone();
+        let entries = entries.clone();
+        move |submit: bool| {
+            let Some(tx) = response_tx.borrow_mut().take() else {
+                return;
+            };
+            let answers = submit.then(|| {
+                entries
+                    .iter()
+                    .map(|e| Zeroizing::new(e.text().to_string()))
+                    .collect::<Vec<_>>()
+            });
+            glib::spawn_future_local(async move {
+                let _ = tx.send(answers).await;
+            });
+        }
+    };
+
+    // Enter in the last field submits, earlier fields move to the next one
+    for (idx, entry) in entries.iter().enumerate() {
+        let next = entries.get(idx + 1).cloned();
+        let dialog_for_entry = dialog.clone();
+        let send_for_entry = send_answer.clone();
+        let activate = move || match next {
+            Some(ref next) => {
+                next.grab_focus();
+            }
+            None => {
+                send_for_entry(true);
+                dialog_for_entry.close();
+            }
+        };
+        if let Some(entry) = entry.downcast_ref::<gtk::Entry>() {
+            entry.connect_activate(move |_| activate());
+        } else if let Some(entry) = entry.downcast_ref::<gtk::PasswordEntry>() {
+            entry.connect_activate(move |_| activate());
+        }
+    }
+
+    let send_for_response = send_answer.clone();
+    dialog.connect_response(None, move |_dialog, response| {
+        send_for_response(response == "continue");
+    });
+
+    if let Some(root) = anchor.as_ref().root() {
+        if let Ok(window) = root.downcast::<gtk::Window>() {
+            dialog.present(Some(&window));
+            if let Some(first) = entries.first() {
+                first.grab_focus();
+            }
+            return;
+        }
+    }
+
+    send_answer(false);
+}
+
+/// Ask for the passphrase of a key the built-in agent was asked to sign with.
+pub fn prompt_key_unlock(parent: &adw::ApplicationWindow, prompt: KeyUnlockPrompt) {
+    let KeyUnlockPrompt {
+        key_name,
+        response_tx,
+    } = prompt;
+
+    let dialog = adw::AlertDialog::builder()
+        .heading(format!("Unlock {key_name}"))
+        .body("A program is using this key through the GrustySSH agent. Enter its passphrase to allow it:")
+        .build();
+
+    let entry = gtk::PasswordEntry::builder()
+        .show_peek_icon(true)
+        .build();
+    dialog.set_extra_child(Some(&entry));
+
+    dialog.add_response("cancel", "Deny");
+    dialog.add_response("unlock", "Unlock");
+    dialog.set_response_appearance("unlock", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("unlock"));
+    dialog.set_close_response("cancel");
+
+    let response_tx = Rc::new(RefCell::new(Some(response_tx)));
+    let send_answer = {
+        let response_tx = response_tx.clone();
+        let entry = entry.clone();
+        move |submit: bool| {
+            let Some(tx) = response_tx.borrow_mut().take() else {
+                return;
+            };
+            let passphrase = submit.then(|| Zeroizing::new(entry.text().to_string()));
+            glib::spawn_future_local(async move {
+                let _ = tx.send(passphrase).await;
+            });
+        }
+    };
+
+    let dialog_for_entry = dialog.clone();
+    let send_for_entry = send_answer.clone();
+    entry.connect_activate(move |_| {
+        send_for_entry(true);
+        dialog_for_entry.close();
+    });
+
+    dialog.connect_response(None, move |_dialog, response| {
+        send_answer(response == "unlock");
+    });
+
+    dialog.present(Some(parent));
+}
@@ -0,0 +1,144 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use libadwaita as adw;
+use adw::prelude::*;
+use zeroize::Zeroizing;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Ask for the passphrase a new backup is encrypted with, entered twice.
+/// With `allow_unencrypted` the user may also save the backup in plain
+/// JSON, which calls `on_submit` with `None`.
+pub fn prompt_backup_passphrase(
+    parent: &adw::ApplicationWindow,
+    body: &str,
+    allow_unencrypted: bool,
+    on_submit: impl FnOnce(Option<Zeroizing<String>>) + 'static,
+) {
+    let dialog = adw::AlertDialog::builder()
+        .heading("Encrypt Backup")
+        .body(body)
+        .build();
+
+    let fields_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    let passphrase_entry = gtk::PasswordEntry::builder()
+        .show_peek_icon(true)
+        .placeholder_text("Passphrase")
+        .build();
+    let confirm_entry = gtk::PasswordEntry::builder()
+        .show_peek_icon(true)
+        .placeholder_text("Repeat passphrase")
+        .build();
+    fields_box.append(&passphrase_entry);
+    fields_box.append(&confirm_entry);
+    dialog.set_extra_child(Some(&fields_box));
+
+    dialog.add_response("cancel", "Cancel");
+    if allow_unencrypted {
+        dialog.add_response("plain", "Save Unencrypted");
+        dialog.set_response_appearance("plain", adw::ResponseAppearance::Destructive);
+    }
+    dialog.add_response("encrypt", "Encrypt");
+    dialog.set_response_appearance("encrypt", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("encrypt"));
+    dialog.set_close_response("cancel");
+    dialog.set_response_enabled("encrypt", false);
+
+    // Only allow encrypting once both entries agree
+    let update_enabled = {
+        let dialog = dialog.clone();
+        let passphrase_entry = passphrase_entry.clone();
+        let confirm_entry = confirm_entry.clone();
+        move || {
+            let passphrase = passphrase_entry.text();
+            let matches = !passphrase.is_empty() && passphrase == confirm_entry.text();
+            dialog.set_response_enabled("encrypt", matches);
+        }
+    };
+    let update_for_passphrase = update_enabled.clone();
+    passphrase_entry.connect_changed(move |_| update_for_passphrase());
+    confirm_entry.connect_changed(move |_| update_enabled());
+
+    let on_submit: Rc<RefCell<Option<Box<dyn FnOnce(Option<Zeroizing<String>>) + 'static>>>> =
+        Rc::new(RefCell::new(Some(Box::new(on_submit))));
+
+    let confirm_for_focus = confirm_entry.clone();
+    passphrase_entry.connect_activate(move |_| {
+        confirm_for_focus.grab_focus();
+    });
+
+    let dialog_for_entry = dialog.clone();
+    let on_submit_for_entry = on_submit.clone();
+    let passphrase_for_entry = passphrase_entry.clone();
+    confirm_entry.connect_activate(move |confirm| {
+        let passphrase = passphrase_for_entry.text();
+        if passphrase.is_empty() || passphrase != confirm.text() {
+            return;
+        }
+        if let Some(callback) = on_submit_for_entry.borrow_mut().take() {
+            callback(Some(Zeroizing::new(passphrase.to_string())));
+        }
+        dialog_for_entry.close();
+    });
+
+    let passphrase_for_response = passphrase_entry.clone();
+    dialog.connect_response(None, move |_dialog, response| {
+        let value = match response {
+            "encrypt" => Some(Zeroizing::new(passphrase_for_response.text().to_string())),
+            "plain" => None,
+            _ => return,
+        };
+        if let Some(callback) = on_submit.borrow_mut().take() {
+            callback(value);
+        }
+    });
+
+    dialog.present(Some(parent));
+    passphrase_entry.grab_focus();
+}
+
+/// Ask for the passphrase of an encrypted backup being restored.
+pub fn prompt_restore_passphrase(
+    parent: &adw::ApplicationWindow,
+    on_submit: impl FnOnce(Zeroizing<String>) + 'static,
+) {
+    let dialog = adw::AlertDialog::builder()
+        .heading("Encrypted Backup")
+        .body("Enter the passphrase this backup was encrypted with:")
+        .build();
+
+    let entry = gtk::PasswordEntry::builder()
+        .show_peek_icon(true)
+        .build();
+    dialog.set_extra_child(Some(&entry));
+
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("restore", "Restore");
+    dialog.set_response_appearance("restore", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("restore"));
+    dialog.set_close_response("cancel");
+
+    let on_submit: Rc<RefCell<Option<Box<dyn FnOnce(Zeroizing<String>) + 'static>>>> =
+        Rc::new(RefCell::new(Some(Box::new(on_submit))));
+
+    let dialog_for_entry = dialog.clone();
+    let on_submit_for_entry = on_submit.clone();
+    entry.connect_activate(move |entry| {
+        if let Some(callback) = on_submit_for_entry.borrow_mut().take() {
+            callback(Zeroizing::new(entry.text().to_string()));
+        }
+        dialog_for_entry.close();
+    });
+
+    let entry_clone = entry.clone();
+    dialog.connect_response(None, move |_dialog, response| {
+        if response == "restore" {
+            if let Some(callback) = on_submit.borrow_mut().take() {
+                callback(Zeroizing::new(entry_clone.text().to_string()));
+            }
+        }
+    });
+
+    dialog.present(Some(parent));
+}
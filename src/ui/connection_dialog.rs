@@ -8,8 +8,13 @@ use std::rc::Rc;
 use uuid::Uuid;
 
 use crate::app::SharedState;
-use crate::models::connection::{AuthMethod, ConnectionProfile};
+use crate::models::connection::{
+    AgentChoice, AlgorithmPreferences, AuthMethod, ConnectionProfile, EnvVar, StartupStep,
+};
 use crate::models::tunnel::TunnelConfig;
+use crate::ssh::{agent, pty};
+use crate::storage::secrets::{self, SecretKind};
+use crate::ui::{algorithm_rows, session_log_rows};
 
 /// Show a dialog to create or edit a connection profile.
 /// `existing` is Some for editing, None for creating new.
@@ -65,12 +70,186 @@ pub fn show_connection_dialog(
 
     let user_row = adw::EntryRow::builder().title("Username").build();
 
+    let auto_reconnect_row = adw::SwitchRow::builder()
+        .title("Reconnect Automatically")
+        .subtitle("Retry with increasing delays when the connection drops")
+        .build();
+
     details_group.add(&name_row);
     details_group.add(&host_row);
     details_group.add(&port_row);
     details_group.add(&user_row);
+    details_group.add(&auto_reconnect_row);
     content_box.append(&details_group);
 
+    // Keepalive overrides, for hosts behind NATs that drop idle connections
+    // sooner than the global settings expect
+    let defaults = state.settings.lock().unwrap().clone();
+    let keepalive_row = adw::ExpanderRow::builder()
+        .title("Override Keepalive")
+        .subtitle("Use different keepalive settings for this host")
+        .show_enable_switch(true)
+        .enable_expansion(false)
+        .build();
+
+    let keepalive_interval_adj = gtk::Adjustment::new(
+        defaults.keepalive_interval_secs as f64,
+        0.0,
+        3600.0,
+        1.0,
+        10.0,
+        0.0,
+    );
+    let keepalive_interval_row = adw::SpinRow::builder()
+        .title("Interval (seconds)")
+        .subtitle("0 sends no keepalives")
+        .adjustment(&keepalive_interval_adj)
+        .build();
+
+    let keepalive_max_adj =
+        gtk::Adjustment::new(defaults.keepalive_max as f64, 1.0, 100.0, 1.0, 5.0, 0.0);
+    let keepalive_max_row = adw::SpinRow::builder()
+        .title("Missed Keepalives")
+        .adjustment(&keepalive_max_adj)
+        .build();
+
+    let inactivity_adj = gtk::Adjustment::new(
+        defaults.inactivity_timeout_secs as f64,
+        0.0,
+        86400.0,
+        1.0,
+        60.0,
+        0.0,
+    );
+    let inactivity_row = adw::SpinRow::builder()
+        .title("Inactivity Timeout (seconds)")
+        .subtitle("0 = never")
+        .adjustment(&inactivity_adj)
+        .build();
+
+    keepalive_row.add_row(&keepalive_interval_row);
+    keepalive_row.add_row(&keepalive_max_row);
+    keepalive_row.add_row(&inactivity_row);
+    details_group.add(&keepalive_row);
+
+    let algorithm_rows = algorithm_rows::build_algorithm_rows(
+        "Empty lists use the global preferences",
+        &AlgorithmPreferences::default(),
+    );
+    details_group.add(&algorithm_rows.expander);
+
+    // Terminal group
+    let terminal_group = adw::PreferencesGroup::builder()
+        .title("Terminal")
+        .description("Leave empty to use the terminal type from Preferences")
+        .build();
+
+    let term_type_row = adw::EntryRow::builder().title("Terminal Type").build();
+    let term_modes_row = adw::EntryRow::builder()
+        .title("Terminal Modes, e.g. VERASE=^? ICRNL=1")
+        .build();
+    term_modes_row.connect_changed(|row| row.remove_css_class("error"));
+
+    let environment: Rc<RefCell<Vec<EnvVar>>> = Rc::new(RefCell::new(Vec::new()));
+    let env_row = adw::ExpanderRow::builder()
+        .title("Environment Variables")
+        .subtitle("Only names the server's AcceptEnv lists are applied, usually LANG and LC_*")
+        .build();
+    let env_add_row = adw::EntryRow::builder()
+        .title("Add NAME=value")
+        .show_apply_button(true)
+        .build();
+    env_row.add_row(&env_add_row);
+
+    let env_for_add = environment.clone();
+    let env_row_for_add = env_row.clone();
+    env_add_row.connect_apply(move |entry| {
+        let text = entry.text();
+        let Some((name, value)) = text.split_once('=') else {
+            entry.add_css_class("error");
+            return;
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            entry.add_css_class("error");
+            return;
+        }
+        let var = EnvVar {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        add_env_row(&env_row_for_add, &env_for_add, var.clone());
+        env_for_add.borrow_mut().push(var);
+        entry.set_text("");
+    });
+    env_add_row.connect_changed(|row| row.remove_css_class("error"));
+
+    terminal_group.add(&term_type_row);
+    terminal_group.add(&term_modes_row);
+    terminal_group.add(&env_row);
+    content_box.append(&terminal_group);
+
+    // Session group: what runs after login and how it is logged
+    let startup_group = adw::PreferencesGroup::builder()
+        .title("Session")
+        .build();
+
+    let remote_command_row = adw::EntryRow::builder()
+        .title("Remote Command, e.g. tmux new -A -s main")
+        .build();
+
+    let startup_steps: Rc<RefCell<Vec<StartupStep>>> = Rc::new(RefCell::new(Vec::new()));
+    let script_row = adw::ExpanderRow::builder()
+        .title("Startup Script")
+        .subtitle("Lines typed after login, each once its pattern shows up")
+        .build();
+    let wait_for_row = adw::EntryRow::builder()
+        .title("Wait For (regular expression, optional)")
+        .build();
+    let send_row = adw::EntryRow::builder()
+        .title("Then Type")
+        .show_apply_button(true)
+        .build();
+    script_row.add_row(&wait_for_row);
+    script_row.add_row(&send_row);
+    wait_for_row.connect_changed(|row| row.remove_css_class("error"));
+
+    let steps_for_add = startup_steps.clone();
+    let script_row_for_add = script_row.clone();
+    let wait_for_for_add = wait_for_row.clone();
+    send_row.connect_apply(move |entry| {
+        let pattern = wait_for_for_add.text().to_string();
+        if !pattern.is_empty() && regex::Regex::new(&pattern).is_err() {
+            wait_for_for_add.add_css_class("error");
+            return;
+        }
+        let step = StartupStep {
+            wait_for: (!pattern.is_empty()).then_some(pattern),
+            send: entry.text().to_string(),
+        };
+        add_step_row(&script_row_for_add, &steps_for_add, step.clone());
+        steps_for_add.borrow_mut().push(step);
+        wait_for_for_add.set_text("");
+        entry.set_text("");
+    });
+
+    startup_group.add(&remote_command_row);
+    startup_group.add(&script_row);
+    content_box.append(&startup_group);
+
+    // Session logging, off = follow the global settings
+    let session_log_row = adw::ExpanderRow::builder()
+        .title("Override Session Logging")
+        .subtitle("Log this host differently from the global settings")
+        .show_enable_switch(true)
+        .enable_expansion(false)
+        .build();
+    let session_log_rows = session_log_rows::build_session_log_rows(&defaults.session_log);
+    for row in session_log_rows.widgets() {
+        session_log_row.add_row(&row);
+    }
+    startup_group.add(&session_log_row);
+
     // Authentication group
     let auth_group = adw::PreferencesGroup::builder()
         .title("Authentication")
@@ -79,7 +258,14 @@ pub fn show_connection_dialog(
     let auth_method_row = adw::ComboRow::builder()
         .title("Method")
         .build();
-    let auth_list = gtk::StringList::new(&["Password", "Public Key", "Both"]);
+    let auth_list = gtk::StringList::new(&[
+        "Password",
+        "Public Key",
+        "Both",
+        "Keyboard-Interactive",
+        "Public Key + Keyboard-Interactive",
+        "SSH Agent",
+    ]);
     auth_method_row.set_model(Some(&auth_list));
 
     let key_row = adw::ComboRow::builder()
@@ -105,21 +291,67 @@ pub fn show_connection_dialog(
         *key_ids.borrow_mut() = ids;
     }
 
+    // Both agents may run at once, the profile says which one it means
+    let agent_choices = [AgentChoice::System, AgentChoice::BuiltIn];
+    let agent_row = adw::ComboRow::builder()
+        .title("SSH Agent")
+        .subtitle(agent::describe(AgentChoice::System))
+        .model(&gtk::StringList::new(&["System Agent", "Built-in Agent"]))
+        .build();
+    agent_row.connect_selected_notify(move |row| {
+        if let Some(choice) = agent_choices.get(row.selected() as usize) {
+            row.set_subtitle(&agent::describe(*choice));
+        }
+    });
+
+    let agent_forward_row = adw::SwitchRow::builder()
+        .title("Forward SSH Agent")
+        .subtitle("Let the remote host use the agent above, e.g. for git")
+        .build();
+
     auth_group.add(&auth_method_row);
     auth_group.add(&key_row);
+    auth_group.add(&agent_row);
+    let x11_forward_row = adw::SwitchRow::builder()
+        .title("Forward X11")
+        .subtitle("Show windows of remote GUI programs on this display, without access to other windows")
+        .build();
+
+    auth_group.add(&agent_forward_row);
+    auth_group.add(&x11_forward_row);
+
+    // A saved password cannot be checked before connecting, so offer a way
+    // to drop one that has gone stale
+    if let Some(profile_id) = existing.as_ref().map(|p| p.id) {
+        let forget_row = adw::ActionRow::builder()
+            .title("Saved Password")
+            .subtitle("Stored in the keyring when you chose \"Remember\"")
+            .build();
+        let forget_btn = gtk::Button::builder()
+            .label("Forget")
+            .valign(gtk::Align::Center)
+            .build();
+        forget_btn.connect_clicked(move |btn| {
+            crate::runtime().spawn(secrets::forget(SecretKind::ProfilePassword, profile_id));
+            btn.set_sensitive(false);
+        });
+        forget_row.add_suffix(&forget_btn);
+        auth_group.add(&forget_row);
+    }
     content_box.append(&auth_group);
 
-    // Grey out key row when Password is selected, since it's not needed.
-    // Grey out nothing when Public Key or Both is selected (key is needed).
+    // Grey out key row when no stored key is involved (Password,
+    // Keyboard-Interactive, SSH Agent).
+    // Grey out nothing when a method with a public key is selected.
     let key_row_for_auth = key_row.clone();
     let update_auth_sensitivity = move |selected: u32| {
         match selected {
-            0 => {
-                // Password only: disable key selection
+            0 | 3 | 5 => {
+                // Password, keyboard-interactive or agent: disable key selection
                 key_row_for_auth.set_sensitive(false);
             }
-            1 | 2 => {
-                // Public Key or Both: enable key selection
+            1 | 2 | 4 => {
+                // Any method involving a key: enable key selection
                 key_row_for_auth.set_sensitive(true);
             }
             _ => {}
@@ -133,10 +365,126 @@ pub fn show_connection_dialog(
         update_fn(row.selected());
     });
 
+    // Jump hosts group
+    let jump_group = adw::PreferencesGroup::builder()
+        .title("Jump Hosts")
+        .description("Reach this host through other saved connections, in order (ProxyJump)")
+        .build();
+
+    // Every saved connection except this one can be used as a jump host
+    let own_id = existing.as_ref().map(|p| p.id);
+    let jump_candidates: Vec<(Uuid, String)> = {
+        let store = state.profile_store.lock().unwrap();
+        store
+            .profiles
+            .iter()
+            .filter(|p| Some(p.id) != own_id)
+            .map(|p| (p.id, p.name.clone()))
+            .collect()
+    };
+
+    let jump_add_row = adw::ComboRow::builder()
+        .title("Add Jump Host")
+        .build();
+    let jump_names: Vec<&str> = jump_candidates.iter().map(|(_, name)| name.as_str()).collect();
+    jump_add_row.set_model(Some(&gtk::StringList::new(&jump_names)));
+    jump_add_row.set_sensitive(!jump_candidates.is_empty());
+
+    let jump_add_btn = gtk::Button::builder()
+        .icon_name("list-add-symbolic")
+        .tooltip_text("Add jump host")
+        .valign(gtk::Align::Center)
+        .css_classes(["flat"])
+        .build();
+    jump_add_row.add_suffix(&jump_add_btn);
+    jump_group.add(&jump_add_row);
+
+    let jump_hosts: Rc<RefCell<Vec<Uuid>>> = Rc::new(RefCell::new(
+        existing
+            .as_ref()
+            .map(|p| p.jump_hosts.clone())
+            .unwrap_or_default(),
+    ));
+
+    let jump_listbox = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+
+    content_box.append(&jump_group);
+    content_box.append(&jump_listbox);
+
+    // Self-referencing rebuild closure so remove buttons can refresh the list
+    let jump_rebuild_holder: Rc<RefCell<Option<Rc<dyn Fn()>>>> = Rc::new(RefCell::new(None));
+    let jump_rebuild: Rc<dyn Fn()> = Rc::new({
+        let jump_hosts = jump_hosts.clone();
+        let jump_listbox = jump_listbox.clone();
+        let jump_candidates = jump_candidates.clone();
+        let jump_rebuild_holder = jump_rebuild_holder.clone();
+        move || {
+            while let Some(child) = jump_listbox.first_child() {
+                jump_listbox.remove(&child);
+            }
+            let ids = jump_hosts.borrow().clone();
+            jump_listbox.set_visible(!ids.is_empty());
+            for (idx, id) in ids.iter().enumerate() {
+                let name = jump_candidates
+                    .iter()
+                    .find(|(candidate, _)| candidate == id)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| "(Deleted connection)".into());
+                let row = adw::ActionRow::builder()
+                    .title(&name)
+                    .subtitle(format!("Hop {}", idx + 1))
+                    .build();
+
+                let remove_btn = gtk::Button::builder()
+                    .icon_name("user-trash-symbolic")
+                    .tooltip_text("Remove jump host")
+                    .valign(gtk::Align::Center)
+                    .css_classes(["flat"])
+                    .build();
+                row.add_suffix(&remove_btn);
+
+                let jump_hosts_c = jump_hosts.clone();
+                let rebuild_c = jump_rebuild_holder.clone();
+                remove_btn.connect_clicked(move |_| {
+                    {
+                        let mut hosts = jump_hosts_c.borrow_mut();
+                        if idx < hosts.len() {
+                            hosts.remove(idx);
+                        }
+                    }
+                    if let Some(ref rebuild_fn) = *rebuild_c.borrow() {
+                        rebuild_fn();
+                    }
+                });
+
+                jump_listbox.append(&row);
+            }
+        }
+    });
+    *jump_rebuild_holder.borrow_mut() = Some(jump_rebuild.clone());
+    jump_rebuild();
+
+    let jump_hosts_for_add = jump_hosts.clone();
+    let jump_add_row_c = jump_add_row.clone();
+    jump_add_btn.connect_clicked(move |_| {
+        let selected = jump_add_row_c.selected() as usize;
+        if let Some((id, _)) = jump_candidates.get(selected) {
+            let mut hosts = jump_hosts_for_add.borrow_mut();
+            if hosts.contains(id) {
+                return;
+            }
+            hosts.push(*id);
+        }
+        jump_rebuild();
+    });
+
     // Tunnels group
     let tunnels_group = adw::PreferencesGroup::builder()
         .title("Tunnels")
-        .description("Local port forwarding")
+        .description("Local, remote and dynamic port forwarding")
         .build();
 
     let tunnels: Rc<RefCell<Vec<TunnelConfig>>> = Rc::new(RefCell::new(Vec::new()));
@@ -170,6 +518,9 @@ pub fn show_connection_dialog(
             AuthMethod::Password => 0,
             AuthMethod::PublicKey => 1,
             AuthMethod::Both => 2,
+            AuthMethod::KeyboardInteractive => 3,
+            AuthMethod::PublicKeyAndKeyboardInteractive => 4,
+            AuthMethod::Agent => 5,
         };
         auth_method_row.set_selected(auth_idx);
 
@@ -180,6 +531,51 @@ pub fn show_connection_dialog(
             }
         }
 
+        let agent_idx = agent_choices
+            .iter()
+            .position(|choice| *choice == profile.agent)
+            .unwrap_or(0);
+        agent_row.set_selected(agent_idx as u32);
+        agent_forward_row.set_active(profile.agent_forwarding);
+        x11_forward_row.set_active(profile.x11_forwarding);
+        auto_reconnect_row.set_active(profile.auto_reconnect);
+        algorithm_rows.set(&profile.algorithms);
+
+        term_type_row.set_text(profile.terminal_type.as_deref().unwrap_or(""));
+        term_modes_row.set_text(&pty::format_modes(&profile.terminal_modes));
+        for var in &profile.environment {
+            add_env_row(&env_row, &environment, var.clone());
+        }
+        *environment.borrow_mut() = profile.environment.clone();
+
+        remote_command_row.set_text(profile.remote_command.as_deref().unwrap_or(""));
+        if let Some(config) = &profile.session_log {
+            session_log_row.set_enable_expansion(true);
+            session_log_rows.set(config);
+        }
+        for step in &profile.startup_script {
+            add_step_row(&script_row, &startup_steps, step.clone());
+        }
+        *startup_steps.borrow_mut() = profile.startup_script.clone();
+
+        let overrides = [
+            profile.keepalive_interval_secs,
+            profile.keepalive_max,
+            profile.inactivity_timeout_secs,
+        ];
+        if overrides.iter().any(Option::is_some) {
+            keepalive_row.set_enable_expansion(true);
+            if let Some(secs) = profile.keepalive_interval_secs {
+                keepalive_interval_row.set_value(secs as f64);
+            }
+            if let Some(max) = profile.keepalive_max {
+                keepalive_max_row.set_value(max as f64);
+            }
+            if let Some(secs) = profile.inactivity_timeout_secs {
+                inactivity_row.set_value(secs as f64);
+            }
+        }
+
         *tunnels.borrow_mut() = profile.tunnels.clone();
         profile_id = profile.id;
         created_at = profile.created_at;
@@ -188,10 +584,7 @@ pub fn show_connection_dialog(
         for tc in &profile.tunnels {
             let row = adw::ActionRow::builder()
                 .title(&tc.name)
-                .subtitle(&format!(
-                    "{}:{} → {}:{}",
-                    tc.local_host, tc.local_port, tc.remote_host, tc.remote_port
-                ))
+                .subtitle(tc.summary())
                 .build();
             tunnels_listbox.append(&row);
         }
@@ -218,10 +611,7 @@ pub fn show_connection_dialog(
                 move |tc: TunnelConfig| {
                     let row = adw::ActionRow::builder()
                         .title(&tc.name)
-                        .subtitle(&format!(
-                            "{}:{} → {}:{}",
-                            tc.local_host, tc.local_port, tc.remote_host, tc.remote_port
-                        ))
+                        .subtitle(tc.summary())
                         .build();
                     listbox_c.append(&row);
                     tunnels_c.borrow_mut().push(tc);
@@ -255,6 +645,7 @@ pub fn show_connection_dialog(
     let dialog_clone = dialog.clone();
     let key_ids_clone = key_ids.clone();
     let tunnels_clone = tunnels.clone();
+    let jump_hosts_clone = jump_hosts.clone();
     save_btn.connect_clicked(move |_| {
         let name = name_row.text().to_string();
         let hostname = host_row.text().to_string();
@@ -264,11 +655,27 @@ pub fn show_connection_dialog(
         if name.is_empty() || hostname.is_empty() || username.is_empty() {
             return;
         }
+        let Some(algorithms) = algorithm_rows.preferences() else {
+            return;
+        };
+        let terminal_modes = match pty::parse_modes(&term_modes_row.text()) {
+            Ok(modes) => modes,
+            Err(e) => {
+                log::warn!("{e}");
+                term_modes_row.add_css_class("error");
+                return;
+            }
+        };
+        let terminal_type = term_type_row.text().trim().to_string();
+        let remote_command = remote_command_row.text().trim().to_string();
 
         let auth_method = match auth_method_row.selected() {
             0 => AuthMethod::Password,
             1 => AuthMethod::PublicKey,
             2 => AuthMethod::Both,
+            3 => AuthMethod::KeyboardInteractive,
+            4 => AuthMethod::PublicKeyAndKeyboardInteractive,
+            5 => AuthMethod::Agent,
             _ => AuthMethod::Password,
         };
 
@@ -285,6 +692,7 @@ pub fn show_connection_dialog(
             .unwrap_or_default()
             .as_secs() as i64;
 
+        let keepalive_override = keepalive_row.enables_expansion();
         let profile = ConnectionProfile {
             id: profile_id,
             name,
@@ -294,6 +702,27 @@ pub fn show_connection_dialog(
             auth_method,
             key_pair_id,
             tunnels: tunnels_clone.borrow().clone(),
+            jump_hosts: jump_hosts_clone.borrow().clone(),
+            agent: agent_choices
+                .get(agent_row.selected() as usize)
+                .copied()
+                .unwrap_or_default(),
+            agent_forwarding: agent_forward_row.is_active(),
+            x11_forwarding: x11_forward_row.is_active(),
+            auto_reconnect: auto_reconnect_row.is_active(),
+            keepalive_interval_secs: keepalive_override
+                .then(|| keepalive_interval_row.value() as u32),
+            keepalive_max: keepalive_override.then(|| keepalive_max_row.value() as u32),
+            inactivity_timeout_secs: keepalive_override.then(|| inactivity_row.value() as u32),
+            algorithms,
+            terminal_type: (!terminal_type.is_empty()).then_some(terminal_type),
+            environment: environment.borrow().clone(),
+            terminal_modes,
+            remote_command: (!remote_command.is_empty()).then_some(remote_command),
+            startup_script: startup_steps.borrow().clone(),
+            session_log: session_log_row
+                .enables_expansion()
+                .then(|| session_log_rows.config()),
             created_at,
             updated_at: now,
         };
@@ -304,3 +733,71 @@ pub fn show_connection_dialog(
 
     dialog.present(Some(parent));
 }
+
+/// Show an environment variable in the expander, with a button removing it
+/// from `environment` again.
+fn add_env_row(
+    expander: &adw::ExpanderRow,
+    environment: &Rc<RefCell<Vec<EnvVar>>>,
+    var: EnvVar,
+) {
+    let row = adw::ActionRow::builder()
+        .title(&var.name)
+        .subtitle(&var.value)
+        .use_markup(false)
+        .build();
+    let remove_btn = gtk::Button::builder()
+        .icon_name("user-trash-symbolic")
+        .tooltip_text("Remove variable")
+        .valign(gtk::Align::Center)
+        .css_classes(["flat"])
+        .build();
+    row.add_suffix(&remove_btn);
+    expander.add_row(&row);
+
+    let expander = expander.clone();
+    let environment = environment.clone();
+    let row_for_remove = row.clone();
+    remove_btn.connect_clicked(move |_| {
+        environment.borrow_mut().retain(|v| v != &var);
+        expander.remove(&row_for_remove);
+    });
+}
+
+/// Show a startup script step in the expander, with a button removing it
+/// from `steps` again.
+fn add_step_row(
+    expander: &adw::ExpanderRow,
+    steps: &Rc<RefCell<Vec<StartupStep>>>,
+    step: StartupStep,
+) {
+    let subtitle = match &step.wait_for {
+        Some(pattern) => format!("After {pattern}"),
+        None => "Right away".to_string(),
+    };
+    let row = adw::ActionRow::builder()
+        .title(&step.send)
+        .subtitle(&subtitle)
+        .use_markup(false)
+        .build();
+    let remove_btn = gtk::Button::builder()
+        .icon_name("user-trash-symbolic")
+        .tooltip_text("Remove step")
+        .valign(gtk::Align::Center)
+        .css_classes(["flat"])
+        .build();
+    row.add_suffix(&remove_btn);
+    expander.add_row(&row);
+
+    let expander = expander.clone();
+    let steps = steps.clone();
+    let row_for_remove = row.clone();
+    remove_btn.connect_clicked(move |_| {
+        // Remove one step only, a script may type the same line twice
+        let mut steps = steps.borrow_mut();
+        if let Some(pos) = steps.iter().position(|s| s == &step) {
+            steps.remove(pos);
+        }
+        expander.remove(&row_for_remove);
+    });
+}
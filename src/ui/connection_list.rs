@@ -1,16 +1,28 @@
 use gtk4 as gtk;
 use gtk::prelude::*;
+use gtk::glib;
 use libadwaita as adw;
 use adw::prelude::*;
+use ssh_key::PrivateKey;
+use uuid::Uuid;
 use zeroize::Zeroizing;
 
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::rc::Rc;
 
-use crate::app::SharedState;
-use crate::models::connection::{AuthMethod, ConnectionProfile};
+use crate::app::{AuthCredentials, ConnectionChain, Hop, SharedState};
+use crate::config;
+use crate::error::AppError;
+use crate::models::connection::ConnectionProfile;
+use crate::ssh::pool::{self, PoolKey};
+use crate::storage::secrets::{self, Backend, SecretKind};
+use crate::storage::profiles::ProfileStore;
+use crate::storage::{backup, paths, ssh_config};
+use crate::ui::backup_passphrase_dialog;
 use crate::ui::connection_dialog;
 use crate::ui::sftp_tab;
+use crate::ui::ssh_config_import_dialog;
 use crate::ui::terminal_tab;
 
 /// Build the sidebar connection list widget.
@@ -41,22 +53,48 @@ pub fn build_connection_list(
         .css_classes(["flat"])
         .build();
 
-    let backup_btn = gtk::Button::builder()
+    let backup_btn = gtk::MenuButton::builder()
         .icon_name("document-save-symbolic")
         .tooltip_text("Backup connections")
         .css_classes(["flat"])
         .build();
 
+    let backup_popover = gtk::Popover::builder()
+        .has_arrow(false)
+        .build();
+    let backup_menu_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    let backup_json_btn = gtk::Button::builder()
+        .label("Backup as JSON…")
+        .halign(gtk::Align::Start)
+        .css_classes(["flat"])
+        .build();
+    let export_ssh_config_btn = gtk::Button::builder()
+        .label("Export as SSH Config…")
+        .halign(gtk::Align::Start)
+        .css_classes(["flat"])
+        .build();
+    backup_menu_box.append(&backup_json_btn);
+    backup_menu_box.append(&export_ssh_config_btn);
+    backup_popover.set_child(Some(&backup_menu_box));
+    backup_btn.set_popover(Some(&backup_popover));
+
     let restore_btn = gtk::Button::builder()
         .icon_name("document-open-symbolic")
         .tooltip_text("Restore connections")
         .css_classes(["flat"])
         .build();
 
+    let ssh_config_btn = gtk::Button::builder()
+        .icon_name("network-server-symbolic")
+        .tooltip_text("Import from SSH config")
+        .css_classes(["flat"])
+        .build();
+
     list_header.append(&title_label);
     list_header.append(&add_btn);
     list_header.append(&backup_btn);
     list_header.append(&restore_btn);
+    list_header.append(&ssh_config_btn);
     sidebar_box.append(&list_header);
 
     let listbox = gtk::ListBox::builder()
@@ -156,83 +194,10 @@ pub fn build_connection_list(
                 let state_sftp = state.clone();
                 let window_sftp = window_rc.clone();
                 sftp_btn.connect_clicked(move |_| {
-                    let needs_password = matches!(
-                        profile_for_sftp.auth_method,
-                        AuthMethod::Password | AuthMethod::Both
-                    );
-
-                    let key_has_passphrase = if let Some(key_id) = profile_for_sftp.key_pair_id {
-                        let store = state_sftp.key_store.lock().unwrap();
-                        store.get(&key_id).map(|k| k.has_passphrase).unwrap_or(false)
-                    } else {
-                        false
-                    };
-
-                    let profile_c = profile_for_sftp.clone();
                     let tab_view_cc = tab_view_sftp.clone();
-
-                    if key_has_passphrase && needs_password {
-                        let window_c2 = window_sftp.clone();
-                        let profile_c2 = profile_c.clone();
-                        prompt_secret(
-                            &window_sftp,
-                            &format!("Key passphrase for {}", profile_c.name),
-                            "Enter the passphrase for your SSH key:",
-                            move |key_pass| {
-                                let key_passphrase = Some(Zeroizing::new(key_pass));
-                                let profile_c3 = profile_c2.clone();
-                                let tab_view_cc2 = tab_view_cc.clone();
-                                prompt_secret(
-                                    &window_c2,
-                                    &format!("Password for {}", profile_c2.name),
-                                    "Enter your SSH password:",
-                                    move |password| {
-                                        sftp_tab::create_sftp_tab(
-                                            &tab_view_cc2,
-                                            &profile_c3,
-                                            Some(Zeroizing::new(password)),
-                                            key_passphrase,
-                                        );
-                                    },
-                                );
-                            },
-                        );
-                    } else if key_has_passphrase {
-                        prompt_secret(
-                            &window_sftp,
-                            &format!("Key passphrase for {}", profile_c.name),
-                            "Enter the passphrase for your SSH key:",
-                            move |key_pass| {
-                                sftp_tab::create_sftp_tab(
-                                    &tab_view_cc,
-                                    &profile_c,
-                                    None,
-                                    Some(Zeroizing::new(key_pass)),
-                                );
-                            },
-                        );
-                    } else if needs_password {
-                        prompt_secret(
-                            &window_sftp,
-                            &format!("Password for {}", profile_c.name),
-                            "Enter your SSH password:",
-                            move |password| {
-                                sftp_tab::create_sftp_tab(
-                                    &tab_view_cc,
-                                    &profile_c,
-                                    Some(Zeroizing::new(password)),
-                                    None,
-                                );
-                            },
-                        );
-                    } else {
-                        sftp_tab::create_sftp_tab(
-                            &tab_view_cc,
-                            &profile_c,
-                            None,
-                            None,
-                        );
-                    }
+                    collect_credentials(&window_sftp, &state_sftp, &profile_for_sftp, move |chain| {
+                        sftp_tab::create_sftp_tab(&tab_view_cc, chain, None);
+                    });
                 });
 
                 // Connect button
@@ -241,93 +206,11 @@ pub fn build_connection_list(
                 let state_c = state.clone();
                 let window_c = window_rc.clone();
                 connect_btn.connect_clicked(move |_| {
-                    let needs_password = matches!(
-                        profile_for_connect.auth_method,
-                        AuthMethod::Password | AuthMethod::Both
-                    );
-
-                    // Check if the selected key has a passphrase
-                    let key_has_passphrase = if let Some(key_id) = profile_for_connect.key_pair_id {
-                        let store = state_c.key_store.lock().unwrap();
-                        store.get(&key_id).map(|k| k.has_passphrase).unwrap_or(false)
-                    } else {
-                        false
-                    };
-
-                    let profile_c = profile_for_connect.clone();
                     let tab_view_cc = tab_view_c.clone();
                     let state_cc = state_c.clone();
-
-                    if key_has_passphrase && needs_password {
-                        // Need both key passphrase and SSH password
-                        let window_c2 = window_c.clone();
-                        let profile_c2 = profile_c.clone();
-                        prompt_secret(
-                            &window_c,
-                            &format!("Key passphrase for {}", profile_c.name),
-                            "Enter the passphrase for your SSH key:",
-                            move |key_pass| {
-                                let key_passphrase = Some(Zeroizing::new(key_pass));
-                                let profile_c3 = profile_c2.clone();
-                                let tab_view_cc2 = tab_view_cc.clone();
-                                let state_cc2 = state_cc.clone();
-                                prompt_secret(
-                                    &window_c2,
-                                    &format!("Password for {}", profile_c2.name),
-                                    "Enter your SSH password:",
-                                    move |password| {
-                                        terminal_tab::create_terminal_tab(
-                                            &tab_view_cc2,
-                                            &profile_c3,
-                                            Some(Zeroizing::new(password)),
-                                            key_passphrase,
-                                            &state_cc2,
-                                        );
-                                    },
-                                );
-                            },
-                        );
-                    } else if key_has_passphrase {
-                        // Only key passphrase needed
-                        prompt_secret(
-                            &window_c,
-                            &format!("Key passphrase for {}", profile_c.name),
-                            "Enter the passphrase for your SSH key:",
-                            move |key_pass| {
-                                terminal_tab::create_terminal_tab(
-                                    &tab_view_cc,
-                                    &profile_c,
-                                    None,
-                                    Some(Zeroizing::new(key_pass)),
-                                    &state_cc,
-                                );
-                            },
-                        );
-                    } else if needs_password {
-                        // Only SSH password needed
-                        prompt_secret(
-                            &window_c,
-                            &format!("Password for {}", profile_c.name),
-                            "Enter your SSH password:",
-                            move |password| {
-                                terminal_tab::create_terminal_tab(
-                                    &tab_view_cc,
-                                    &profile_c,
-                                    Some(Zeroizing::new(password)),
-                                    None,
-                                    &state_cc,
-                                );
-                            },
-                        );
-                    } else {
-                        terminal_tab::create_terminal_tab(
-                            &tab_view_cc,
-                            &profile_c,
-                            None,
-                            None,
-                            &state_cc,
-                        );
-                    }
+                    collect_credentials(&window_c, &state_c, &profile_for_connect, move |chain| {
+                        terminal_tab::create_terminal_tab(&tab_view_cc, chain, &state_cc);
+                    });
                 });
 
                 // Edit button
@@ -362,6 +245,7 @@ pub fn build_connection_list(
                     let mut store = state_del.profile_store.lock().unwrap();
                     let _ = store.remove(&profile_id);
                     drop(store);
+                    crate::runtime().spawn(secrets::forget(SecretKind::ProfilePassword, profile_id));
                     if let Some(ref rebuild_fn) = *rebuild_del.borrow() {
                         rebuild_fn();
                     }
@@ -397,44 +281,84 @@ pub fn build_connection_list(
         );
     });
 
-    // Backup button
+    // Backup menu: JSON backup
     let state_for_backup = state.clone();
     let window_for_backup = window.clone();
-    backup_btn.connect_clicked(move |_| {
-        let backup_json = {
-            let store = state_for_backup.profile_store.lock().unwrap();
-            store.export_backup()
+    let popover_for_backup = backup_popover.clone();
+    backup_json_btn.connect_clicked(move |_| {
+        popover_for_backup.popdown();
+        let state_clone = state_for_backup.clone();
+        let window_clone = window_for_backup.clone();
+        backup_passphrase_dialog::prompt_backup_passphrase(
+            &window_for_backup,
+            "Choose a passphrase to protect the backup. It is needed to restore it.",
+            true,
+            move |passphrase| {
+                let profiles = state_clone.profile_store.lock().unwrap().profiles.clone();
+                // Deriving the backup key takes a moment, keep it off the UI
+                glib::spawn_future_local(async move {
+                    let backup_json = crate::runtime()
+                        .spawn_blocking(move || {
+                            ProfileStore::export_backup(&profiles, passphrase.as_deref().map(|p| p.as_str()))
+                        })
+                        .await
+                        .unwrap_or_else(|e| Err(AppError::Other(e.to_string())));
+                    match backup_json {
+                        Ok(json) => save_backup_file(&window_clone, json),
+                        Err(e) => {
+                            log::error!("Failed to export connections: {e}");
+                            let alert = adw::AlertDialog::builder()
+                                .heading("Backup Failed")
+                                .body(format!("{e}"))
+                                .build();
+                            alert.add_response("ok", "OK");
+                            alert.present(Some(&window_clone));
+                        }
+                    }
+                });
+            },
+        );
+    });
+
+    // Backup menu: ssh_config export
+    let state_for_export = state.clone();
+    let window_for_export = window.clone();
+    let popover_for_export = backup_popover.clone();
+    export_ssh_config_btn.connect_clicked(move |_| {
+        popover_for_export.popdown();
+        let config_text = {
+            let store = state_for_export.profile_store.lock().unwrap();
+            let key_store = state_for_export.key_store.lock().unwrap();
+            store.export_ssh_config(&key_store)
         };
-        match backup_json {
-            Ok(json) => {
-                let file_dialog = gtk::FileDialog::builder()
-                    .title("Save Connections Backup")
-                    .initial_name("grustyssh-connections-backup.json")
-                    .build();
-                let parent_clone = window_for_backup.clone();
-                file_dialog.save(
-                    Some(&window_for_backup),
-                    gtk::gio::Cancellable::NONE,
-                    move |result| {
-                        if let Ok(file) = result {
-                            if let Some(path) = file.path() {
-                                if let Err(e) = std::fs::write(&path, &json) {
-                                    log::error!("Failed to write backup: {e}");
-                                } else {
-                                    let alert = adw::AlertDialog::builder()
-                                        .heading("Backup Saved")
-                                        .body(format!("Connections backed up to {}", path.display()))
-                                        .build();
-                                    alert.add_response("ok", "OK");
-                                    alert.present(Some(&parent_clone));
-                                }
-                            }
+        let file_dialog = gtk::FileDialog::builder()
+            .title("Export Connections as SSH Config")
+            .initial_name("grustyssh.ssh_config")
+            .build();
+        let parent_clone = window_for_export.clone();
+        file_dialog.save(
+            Some(&window_for_export),
+            gtk::gio::Cancellable::NONE,
+            move |result| {
+                if let Ok(file) = result {
+                    if let Some(path) = file.path() {
+                        if let Err(e) = std::fs::write(&path, &config_text) {
+                            log::error!("Failed to write ssh_config export: {e}");
+                        } else {
+                            let alert = adw::AlertDialog::builder()
+                                .heading("Export Saved")
+                                .body(format!(
+                                    "Connections exported to {}. Include it from ~/.ssh/config to use it with ssh.",
+                                    path.display()
+                                ))
+                                .build();
+                            alert.add_response("ok", "OK");
+                            alert.present(Some(&parent_clone));
                         }
-                    },
-                );
-            }
-            Err(e) => log::error!("Failed to export connections: {e}"),
-        }
+                    }
+                }
+            },
+        );
     });
 
     // Restore button
@@ -463,31 +387,25 @@ pub fn build_connection_list(
                 if let Ok(file) = result {
                     if let Some(path) = file.path() {
                         match std::fs::read_to_string(&path) {
+                            Ok(json) if backup::is_encrypted(&json) => {
+                                let state_clone = state_clone.clone();
+                                let parent_for_restore = parent_clone.clone();
+                                let rebuild = rebuild.clone();
+                                backup_passphrase_dialog::prompt_restore_passphrase(
+                                    &parent_clone,
+                                    move |passphrase| {
+                                        restore_backup(
+                                            &parent_for_restore,
+                                            &state_clone,
+                                            rebuild,
+                                            json,
+                                            Some(passphrase),
+                                        );
+                                    },
+                                );
+                            }
                             Ok(json) => {
-                                let import_result = {
-                                    let mut store = state_clone.profile_store.lock().unwrap();
-                                    store.import_backup(&json)
-                                };
-                                match import_result {
-                                    Ok(count) => {
-                                        rebuild();
-                                        let alert = adw::AlertDialog::builder()
-                                            .heading("Restore Complete")
-                                            .body(format!("Imported {count} connection(s)."))
-                                            .build();
-                                        alert.add_response("ok", "OK");
-                                        alert.present(Some(&parent_clone));
-                                    }
-                                    Err(e) => {
-                                        log::error!("Failed to import backup: {e}");
-                                        let alert = adw::AlertDialog::builder()
-                                            .heading("Restore Failed")
-                                            .body(format!("{e}"))
-                                            .build();
-                                        alert.add_response("ok", "OK");
-                                        alert.present(Some(&parent_clone));
-                                    }
-                                }
+                                restore_backup(&parent_clone, &state_clone, rebuild.clone(), json, None)
                             }
                             Err(e) => log::error!("Failed to read backup file: {e}"),
                         }
@@ -497,15 +415,364 @@ pub fn build_connection_list(
         );
     });
 
+    // Import from ssh_config button
+    let state_for_ssh_config = state.clone();
+    let window_for_ssh_config = window.clone();
+    let rebuild_for_ssh_config = rebuild.clone();
+    ssh_config_btn.connect_clicked(move |_| {
+        let file_dialog = gtk::FileDialog::builder()
+            .title("Import Hosts from SSH Config")
+            .build();
+        if let Some(path) = ssh_config::default_config_path().filter(|p| p.exists()) {
+            file_dialog.set_initial_file(Some(&gtk::gio::File::for_path(path)));
+        }
+
+        let state_clone = state_for_ssh_config.clone();
+        let parent_clone = window_for_ssh_config.clone();
+        let rebuild = rebuild_for_ssh_config.clone();
+        file_dialog.open(
+            Some(&window_for_ssh_config),
+            gtk::gio::Cancellable::NONE,
+            move |result| {
+                if let Some(path) = result.ok().and_then(|file| file.path()) {
+                    let rebuild = rebuild.clone();
+                    ssh_config_import_dialog::show_ssh_config_import_dialog(
+                        &parent_clone,
+                        &state_clone,
+                        &path,
+                        move || rebuild(),
+                    );
+                }
+            },
+        );
+    });
+
     (sidebar_box, rebuild)
 }
 
+fn save_backup_file(parent: &adw::ApplicationWindow, json: String) {
+    let file_dialog = gtk::FileDialog::builder()
+        .title("Save Connections Backup")
+        .initial_name("grustyssh-connections-backup.json")
+        .build();
+    let parent_clone = parent.clone();
+    file_dialog.save(
+        Some(parent),
+        gtk::gio::Cancellable::NONE,
+        move |result| {
+            if let Ok(file) = result {
+                if let Some(path) = file.path() {
+                    if let Err(e) = std::fs::write(&path, &json) {
+                        log::error!("Failed to write backup: {e}");
+                    } else {
+                        let alert = adw::AlertDialog::builder()
+                            .heading("Backup Saved")
+                            .body(format!("Connections backed up to {}", path.display()))
+                            .build();
+                        alert.add_response("ok", "OK");
+                        alert.present(Some(&parent_clone));
+                    }
+                }
+            }
+        },
+    );
+}
+
+fn restore_backup(
+    parent: &adw::ApplicationWindow,
+    state: &SharedState,
+    rebuild: Rc<dyn Fn()>,
+    json: String,
+    passphrase: Option<Zeroizing<String>>,
+) {
+    let parent = parent.clone();
+    let state = state.clone();
+    // Decrypt off the UI, the store is only locked to add the result
+    glib::spawn_future_local(async move {
+        let import_result = crate::runtime()
+            .spawn_blocking(move || {
+                ProfileStore::read_backup(&json, passphrase.as_deref().map(|p| p.as_str()))
+            })
+            .await
+            .unwrap_or_else(|e| Err(AppError::Other(e.to_string())))
+            .and_then(|profiles| state.profile_store.lock().unwrap().import_backup(profiles));
+        match import_result {
+            Ok(count) => {
+                rebuild();
+                let alert = adw::AlertDialog::builder()
+                    .heading("Restore Complete")
+                    .body(format!("Imported {count} connection(s)."))
+                    .build();
+                alert.add_response("ok", "OK");
+                alert.present(Some(&parent));
+            }
+            Err(e) => {
+                log::error!("Failed to import backup: {e}");
+                let alert = adw::AlertDialog::builder()
+                    .heading("Restore Failed")
+                    .body(format!("{e}"))
+                    .build();
+                alert.add_response("ok", "OK");
+                alert.present(Some(&parent));
+            }
+        }
+    });
+}
+
+/// Resolve the jump hosts of `profile`, ask for the secrets of every hop in
+/// connection order and hand the complete chain to `on_ready`.
+fn collect_credentials(
+    window: &adw::ApplicationWindow,
+    state: &SharedState,
+    profile: &ConnectionProfile,
+    on_ready: impl FnOnce(ConnectionChain) + 'static,
+) {
+    let jump_chain = {
+        let store = state.profile_store.lock().unwrap();
+        store.jump_chain(profile)
+    };
+    let jump_profiles = match jump_chain {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            let alert = adw::AlertDialog::builder()
+                .heading("Cannot Connect")
+                .body(format!("{e}"))
+                .build();
+            alert.add_response("ok", "OK");
+            alert.present(Some(window));
+            return;
+        }
+    };
+
+    // An open shared connection needs no credentials, reuse the ones it was
+    // made with so the new tab can still reconnect by itself
+    let settings = state.settings.lock().unwrap().clone();
+    if settings.share_connections {
+        let key = PoolKey::new(&settings, jump_profiles.iter().chain(std::iter::once(profile)));
+        if let Some(chain) = pool::pool().live_chain(&key) {
+            // The profiles may have been edited since, only the secrets are kept
+            let jump_hosts = chain
+                .jump_hosts
+                .into_iter()
+                .zip(jump_profiles)
+                .map(|(hop, profile)| Hop {
+                    profile,
+                    credentials: hop.credentials,
+                })
+                .collect();
+            on_ready(ConnectionChain {
+                jump_hosts,
+                target: Hop {
+                    profile: profile.clone(),
+                    credentials: chain.target.credentials,
+                },
+                settings,
+            });
+            return;
+        }
+    }
+
+    let mut pending: VecDeque<ConnectionProfile> = jump_profiles.into();
+    pending.push_back(profile.clone());
+
+    let state_for_chain = state.clone();
+    collect_hops(
+        window.clone(),
+        state.clone(),
+        pending,
+        Vec::new(),
+        Box::new(move |mut hops| {
+            if let Some(target) = hops.pop() {
+                let settings = state_for_chain.settings.lock().unwrap().clone();
+                on_ready(ConnectionChain {
+                    jump_hosts: hops,
+                    target,
+                    settings,
+                });
+            }
+        }),
+    );
+}
+
+fn collect_hops(
+    window: adw::ApplicationWindow,
+    state: SharedState,
+    mut pending: VecDeque<ConnectionProfile>,
+    mut hops: Vec<Hop>,
+    on_done: Box<dyn FnOnce(Vec<Hop>)>,
+) {
+    let Some(profile) = pending.pop_front() else {
+        on_done(hops);
+        return;
+    };
+
+    let window_next = window.clone();
+    let state_next = state.clone();
+    prompt_hop_credentials(&window, &state, profile.clone(), move |credentials| {
+        hops.push(Hop {
+            profile,
+            credentials,
+        });
+        collect_hops(window_next, state_next, pending, hops, on_done);
+    });
+}
+
+/// Ask for the key passphrase and/or password a single profile needs.
+/// Secrets saved in the keyring are used without asking.
+fn prompt_hop_credentials(
+    window: &adw::ApplicationWindow,
+    state: &SharedState,
+    profile: ConnectionProfile,
+    on_done: impl FnOnce(AuthCredentials) + 'static,
+) {
+    // Keyboard-interactive prompts are answered later, as the server sends them
+    let needs_password = profile.auth_method.uses_password();
+
+    // Check if the selected key has a passphrase
+    let passphrase_key = match profile.key_pair_id {
+        Some(key_id) if profile.auth_method.uses_key() => {
+            let store = state.key_store.lock().unwrap();
+            store
+                .get(&key_id)
+                .filter(|k| k.has_passphrase)
+                .map(|k| (k.id, k.name.clone()))
+        }
+        _ => None,
+    };
+
+    if !needs_password && passphrase_key.is_none() {
+        on_done(AuthCredentials::default());
+        return;
+    }
+
+    let window = window.clone();
+    let profile_id = profile.id;
+    let key_id = passphrase_key.as_ref().map(|(id, _)| *id);
+    glib::spawn_future_local(async move {
+        let (saved, backend) = crate::runtime()
+            .spawn(async move {
+                let saved = saved_credentials(profile_id, needs_password, key_id).await;
+                (saved, secrets::backend().await)
+            })
+            .await
+            .unwrap_or_else(|e| (AuthCredentials::default(), Err(AppError::Other(e.to_string()))));
+        let AuthCredentials {
+            password: saved_password,
+            key_passphrase: saved_passphrase,
+            ..
+        } = saved;
+        let backend = Rc::new(backend);
+
+        let window_for_password = window.clone();
+        let backend_for_password = backend.clone();
+        let ask_password = move |key_passphrase: Option<Zeroizing<String>>,
+                                 remember_passphrase: Option<String>| {
+            if !needs_password {
+                on_done(AuthCredentials {
+                    key_passphrase,
+                    remember_passphrase,
+                    ..Default::default()
+                });
+                return;
+            }
+            let password_saved = saved_password.is_some();
+            ask_secret_if_missing(
+                &window_for_password,
+                saved_password,
+                &format!("Password for {}", profile.name),
+                "Enter your SSH password:",
+                &backend_for_password,
+                move |password, remember| {
+                    on_done(AuthCredentials {
+                        password: Some(password),
+                        key_passphrase,
+                        remember_password: remember
+                            .then(|| format!("GrustySSH password for {}", profile.name)),
+                        remember_passphrase,
+                        password_saved,
+                    });
+                },
+            );
+        };
+
+        match passphrase_key {
+            Some((_, key_name)) => ask_secret_if_missing(
+                &window,
+                saved_passphrase,
+                &format!("Key passphrase for {key_name}"),
+                "Enter the passphrase for your SSH key:",
+                &backend,
+                move |passphrase, remember| {
+                    let label = format!("GrustySSH passphrase for key {key_name}");
+                    ask_password(Some(passphrase), remember.then_some(label))
+                },
+            ),
+            None => ask_password(None, None),
+        }
+    });
+}
+
+/// Load the saved password and key passphrase of a hop. A saved passphrase
+/// that no longer decrypts the key is forgotten, so the user is asked again.
+async fn saved_credentials(
+    profile_id: Uuid,
+    needs_password: bool,
+    key_id: Option<Uuid>,
+) -> AuthCredentials {
+    let password = match needs_password {
+        true => secrets::lookup(SecretKind::ProfilePassword, profile_id).await,
+        false => None,
+    };
+
+    let mut key_passphrase = None;
+    if let Some(key_id) = key_id {
+        if let Some(passphrase) = secrets::lookup(SecretKind::KeyPassphrase, key_id).await {
+            let decrypts = PrivateKey::read_openssh_file(&paths::private_key_path(&key_id))
+                .map(|key| key.decrypt(passphrase.as_bytes()).is_ok())
+                .unwrap_or(false);
+            if decrypts {
+                key_passphrase = Some(passphrase);
+            } else {
+                log::info!("Saved passphrase of key {key_id} no longer works, forgetting it");
+                secrets::forget(SecretKind::KeyPassphrase, key_id).await;
+            }
+        }
+    }
+
+    AuthCredentials {
+        password,
+        key_passphrase,
+        ..Default::default()
+    }
+}
+
+/// Use `saved` if there is one, otherwise prompt. `then` also learns whether
+/// the user ticked "Remember"; the secret is only saved once the connection
+/// shows it works.
+fn ask_secret_if_missing(
+    window: &adw::ApplicationWindow,
+    saved: Option<Zeroizing<String>>,
+    heading: &str,
+    body: &str,
+    backend: &Result<Backend, AppError>,
+    then: impl FnOnce(Zeroizing<String>, bool) + 'static,
+) {
+    if let Some(secret) = saved {
+        then(secret, false);
+        return;
+    }
+    prompt_secret(window, heading, body, backend, move |value, remember| {
+        then(Zeroizing::new(value), remember);
+    });
+}
+
 /// Show a prompt dialog for a secret value (password or passphrase).
 fn prompt_secret(
     parent: &adw::ApplicationWindow,
     heading: &str,
     body: &str,
-    on_submit: impl FnOnce(String) + 'static,
+    backend: &Result<Backend, AppError>,
+    on_submit: impl FnOnce(String, bool) + 'static,
 ) {
     let dialog = adw::AlertDialog::builder()
         .heading(heading)
@@ -515,7 +782,24 @@ fn prompt_secret(
     let entry = gtk::PasswordEntry::builder()
         .show_peek_icon(true)
         .build();
-    dialog.set_extra_child(Some(&entry));
+    // Say where a remembered secret ends up, and why it can't be remembered
+    let remember_check = match backend {
+        Ok(Backend::Keyring) => gtk::CheckButton::builder()
+            .label("Remember in keyring")
+            .build(),
+        Ok(Backend::File) => gtk::CheckButton::builder()
+            .label("Remember (no keyring found, saved unencrypted in secrets.json)")
+            .tooltip_text(config::secrets_path().display().to_string())
+            .build(),
+        Err(e) => gtk::CheckButton::builder()
+            .label(format!("Cannot remember: {e}"))
+            .sensitive(false)
+            .build(),
+    };
+    let fields_box = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    fields_box.append(&entry);
+    fields_box.append(&remember_check);
+    dialog.set_extra_child(Some(&fields_box));
 
     dialog.add_response("cancel", "Cancel");
     dialog.add_response("connect", "Connect");
@@ -523,16 +807,20 @@ fn prompt_secret(
     dialog.set_default_response(Some("connect"));
 
     // Wrap FnOnce in Rc<RefCell<Option<>>> so it can be shared between closures
-    let on_submit: Rc<RefCell<Option<Box<dyn FnOnce(String) + 'static>>>> =
+    let on_submit: Rc<RefCell<Option<Box<dyn FnOnce(String, bool) + 'static>>>> =
         Rc::new(RefCell::new(Some(Box::new(on_submit))));
 
     // Enter key in the password field triggers connect
     let dialog_for_entry = dialog.clone();
     let on_submit_for_entry = on_submit.clone();
     let entry_for_activate = entry.clone();
+    let remember_for_activate = remember_check.clone();
     entry.connect_activate(move |_| {
         if let Some(callback) = on_submit_for_entry.borrow_mut().take() {
-            callback(entry_for_activate.text().to_string());
+            callback(
+                entry_for_activate.text().to_string(),
+                remember_for_activate.is_active(),
+            );
         }
         dialog_for_entry.close();
     });
@@ -542,7 +830,7 @@ fn prompt_secret(
         if response == "connect" {
             if let Some(callback) = on_submit.borrow_mut().take() {
                 let value = entry_clone.text().to_string();
-                callback(value);
+                callback(value, remember_check.is_active());
             }
         }
     });
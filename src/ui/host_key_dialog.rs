@@ -0,0 +1,76 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use gtk::glib;
+use libadwaita as adw;
+use adw::prelude::*;
+
+use crate::app::{HostKeyDecision, HostKeyPrompt};
+
+/// Ask the user whether to trust an unknown server key.
+/// The decision is sent back to the SSH task waiting in `check_server_key`.
+pub fn prompt_host_key(anchor: &impl IsA<gtk::Widget>, prompt: HostKeyPrompt) {
+    let HostKeyPrompt {
+        host,
+        key_type,
+        fingerprint,
+        randomart,
+        cert_authority,
+        response_tx,
+    } = prompt;
+
+    let mut body = format!(
+        "The authenticity of host \"{host}\" can't be established.\n\n\
+         {key_type} key fingerprint is\n{fingerprint}\n\n\
+         Only continue if this matches the key published by the server's administrator."
+    );
+    if cert_authority {
+        body.push_str(
+            "\n\nknown_hosts lists a certificate authority for this host, but host \
+             certificates are not supported, so the key has to be checked by hand.",
+        );
+    }
+    let dialog = adw::AlertDialog::builder()
+        .heading("Unknown Host Key")
+        .body(body)
+        .build();
+
+    let art_label = gtk::Label::builder()
+        .label(&randomart)
+        .css_classes(["monospace", "host-key-randomart"])
+        .halign(gtk::Align::Center)
+        .selectable(true)
+        .build();
+    dialog.set_extra_child(Some(&art_label));
+
+    dialog.add_response("reject", "Reject");
+    dialog.add_response("once", "Accept Once");
+    dialog.add_response("save", "Accept and Save");
+    dialog.set_response_appearance("reject", adw::ResponseAppearance::Destructive);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("reject");
+
+    let response_tx_dialog = response_tx.clone();
+    dialog.connect_response(None, move |_dialog, response| {
+        let decision = match response {
+            "once" => HostKeyDecision::AcceptOnce,
+            "save" => HostKeyDecision::AcceptAndSave,
+            _ => HostKeyDecision::Reject,
+        };
+        let tx = response_tx_dialog.clone();
+        glib::spawn_future_local(async move {
+            let _ = tx.send(decision).await;
+        });
+    });
+
+    if let Some(root) = anchor.as_ref().root() {
+        if let Ok(window) = root.downcast::<gtk::Window>() {
+            dialog.present(Some(&window));
+            return;
+        }
+    }
+
+    glib::spawn_future_local(async move {
+        let _ = response_tx.send(HostKeyDecision::Reject).await;
+    });
+}
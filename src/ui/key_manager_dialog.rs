@@ -1,15 +1,22 @@
 use gtk4 as gtk;
 use gtk::prelude::*;
+use gtk::glib;
 use libadwaita as adw;
 use adw::prelude::*;
+use zeroize::Zeroizing;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::app::SharedState;
+use crate::error::AppError;
+use crate::keys::certificate::{self, CertificateInfo, CertificateStatus};
 use crate::keys::generate::{generate_keypair, import_keypair};
 use crate::keys::storage::KeyStore;
 use crate::models::connection::KeyAlgorithm;
+use crate::storage::backup;
+use crate::storage::secrets::{self, SecretKind};
+use crate::ui::backup_passphrase_dialog;
 
 pub fn show_key_manager_dialog(parent: &adw::ApplicationWindow, state: &SharedState) {
     let dialog = adw::Dialog::builder()
@@ -197,6 +204,7 @@ pub fn show_key_manager_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
         let keys_listbox_rc = keys_listbox_rc.clone();
         let _keys_group_ref = keys_group.clone();
         let rebuild_fn = rebuild_fn.clone();
+        let parent_ref = parent.clone();
         move || {
             let listbox = keys_listbox_rc.borrow();
             // Remove all rows
@@ -215,14 +223,113 @@ pub fn show_key_manager_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
                 listbox.append(&label);
             } else {
                 for key_meta in &store.keys {
+                    let mut subtitle = glib::markup_escape_text(&format!(
+                        "{} — {}",
+                        key_meta.algorithm, key_meta.public_key_fingerprint
+                    ))
+                    .to_string();
+
+                    let cert_info = match certificate::load_certificate(&key_meta.id) {
+                        Ok(cert) => cert.map(|c| CertificateInfo::from_certificate(&c)),
+                        Err(e) => {
+                            log::warn!("Failed to read certificate of {}: {e}", key_meta.name);
+                            None
+                        }
+                    };
+                    let cert_status = cert_info.as_ref().map(|info| info.status());
+                    if let Some(ref info) = cert_info {
+                        let status = match info.status() {
+                            CertificateStatus::Valid => "valid",
+                            CertificateStatus::ExpiringSoon => "expires soon",
+                            CertificateStatus::Expired => "expired",
+                            CertificateStatus::NotYetValid => "not yet valid",
+                        };
+                        subtitle.push_str(&glib::markup_escape_text(&format!(
+                            "\nCertificate \"{}\" for {} ({status})\n{} · CA {}",
+                            info.key_id,
+                            info.principals_summary(),
+                            info.validity_summary(),
+                            info.ca_fingerprint
+                        )));
+                    }
+
                     let row = adw::ActionRow::builder()
                         .title(&key_meta.name)
-                        .subtitle(&format!(
-                            "{} — {}",
-                            key_meta.algorithm, key_meta.public_key_fingerprint
-                        ))
+                        .subtitle(&subtitle)
                         .build();
 
+                    let warning = match cert_status {
+                        Some(CertificateStatus::ExpiringSoon) => {
+                            Some(("warning", "The certificate of this key expires soon"))
+                        }
+                        Some(CertificateStatus::Expired | CertificateStatus::NotYetValid) => {
+                            Some(("error", "The certificate of this key is not valid now"))
+                        }
+                        _ => None,
+                    };
+                    if let Some((class, tooltip)) = warning {
+                        let icon = gtk::Image::builder()
+                            .icon_name("dialog-warning-symbolic")
+                            .tooltip_text(tooltip)
+                            .css_classes([class])
+                            .build();
+                        row.add_prefix(&icon);
+                    }
+
+                    let cert_btn = gtk::Button::builder()
+                        .icon_name("application-certificate-symbolic")
+                        .tooltip_text(if cert_info.is_some() {
+                            "Replace certificate"
+                        } else {
+                            "Attach OpenSSH certificate"
+                        })
+                        .valign(gtk::Align::Center)
+                        .css_classes(["flat"])
+                        .build();
+
+                    let cert_key_id = key_meta.id;
+                    let state_for_cert = state.clone();
+                    let rebuild_for_cert = rebuild_fn.clone();
+                    let parent_for_cert = parent_ref.clone();
+                    cert_btn.connect_clicked(move |_| {
+                        let file_dialog = gtk::FileDialog::builder()
+                            .title("Select Certificate (*-cert.pub)")
+                            .build();
+                        let state = state_for_cert.clone();
+                        let rebuild = rebuild_for_cert.clone();
+                        let parent = parent_for_cert.clone();
+                        file_dialog.open(
+                            Some(&parent_for_cert),
+                            gtk::gio::Cancellable::NONE,
+                            move |result| {
+                                let Some(path) = result.ok().and_then(|f| f.path()) else {
+                                    return;
+                                };
+                                let attach_result = std::fs::read_to_string(&path)
+                                    .map_err(AppError::from)
+                                    .and_then(|data| {
+                                        let mut store = state.key_store.lock().unwrap();
+                                        store.attach_certificate(&cert_key_id, &data)
+                                    });
+                                match attach_result {
+                                    Ok(()) => {
+                                        if let Some(f) = rebuild.borrow().as_ref() {
+                                            f();
+                                        }
+                                    }
+                                    Err(e) => {
+                                        let alert = adw::AlertDialog::builder()
+                                            .heading("Certificate Not Attached")
+                                            .body(format!("{e}"))
+                                            .build();
+                                        alert.add_response("ok", "OK");
+                                        alert.present(Some(&parent));
+                                    }
+                                }
+                            },
+                        );
+                    });
+
                     let export_btn = gtk::Button::builder()
                         .icon_name("edit-copy-symbolic")
                         .tooltip_text("Copy public key")
@@ -256,11 +363,13 @@ pub fn show_key_manager_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
                                 log::error!("Failed to delete key: {e}");
                             }
                         }
+                        crate::runtime().spawn(secrets::forget(SecretKind::KeyPassphrase, key_id));
                         if let Some(f) = rebuild_ref.borrow().as_ref() {
                             f();
                         }
                     });
 
+                    row.add_suffix(&cert_btn);
                     row.add_suffix(&export_btn);
                     row.add_suffix(&delete_btn);
                     listbox.append(&row);
@@ -408,44 +517,45 @@ pub fn show_key_manager_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
         });
     }
 
-    // Backup button handler
+    // Backup button handler. Key backups hold private keys, so they are
+    // always encrypted
     let state_for_backup = state.clone();
     let parent_for_backup = parent.clone();
     backup_btn.connect_clicked(move |_| {
-        let backup_json = {
-            let store = state_for_backup.key_store.lock().unwrap();
-            store.export_backup()
-        };
-        match backup_json {
-            Ok(json) => {
-                let file_dialog = gtk::FileDialog::builder()
-                    .title("Save Key Backup")
-                    .initial_name("grustyssh-keys-backup.json")
-                    .build();
-                let parent_clone = parent_for_backup.clone();
-                file_dialog.save(
-                    Some(&parent_for_backup),
-                    gtk::gio::Cancellable::NONE,
-                    move |result| {
-                        if let Ok(file) = result {
-                            if let Some(path) = file.path() {
-                                if let Err(e) = std::fs::write(&path, &json) {
-                                    log::error!("Failed to write backup: {e}");
-                                } else {
-                                    let alert = adw::AlertDialog::builder()
-                                        .heading("Backup Saved")
-                                        .body(format!("Keys backed up to {}", path.display()))
-                                        .build();
-                                    alert.add_response("ok", "OK");
-                                    alert.present(Some(&parent_clone));
-                                }
-                            }
+        let state_clone = state_for_backup.clone();
+        let parent_clone = parent_for_backup.clone();
+        backup_passphrase_dialog::prompt_backup_passphrase(
+            &parent_for_backup,
+            "The backup contains your private keys. Choose a passphrase to protect it; it is needed to restore the keys.",
+            false,
+            move |passphrase| {
+                let entries = state_clone.key_store.lock().unwrap().backup_entries();
+                // Deriving the backup key takes a moment, keep it off the UI
+                glib::spawn_future_local(async move {
+                    let backup_json = match entries {
+                        Ok(entries) => crate::runtime()
+                            .spawn_blocking(move || {
+                                KeyStore::export_backup(entries, passphrase.as_deref().map(|p| p.as_str()))
+                            })
+                            .await
+                            .unwrap_or_else(|e| Err(AppError::Other(e.to_string()))),
+                        Err(e) => Err(e),
+                    };
+                    match backup_json {
+                        Ok(json) => save_backup_file(&parent_clone, json),
+                        Err(e) => {
+                            log::error!("Failed to export keys: {e}");
+                            let alert = adw::AlertDialog::builder()
+                                .heading("Backup Failed")
+                                .body(format!("{e}"))
+                                .build();
+                            alert.add_response("ok", "OK");
+                            alert.present(Some(&parent_clone));
                         }
-                    },
-                );
-            }
-            Err(e) => log::error!("Failed to export keys: {e}"),
-        }
+                    }
+                });
+            },
+        );
     });
 
     // Restore button handler
@@ -474,31 +584,25 @@ pub fn show_key_manager_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
                 if let Ok(file) = result {
                     if let Some(path) = file.path() {
                         match std::fs::read_to_string(&path) {
+                            Ok(json) if backup::is_encrypted(&json) => {
+                                let state_clone = state_clone.clone();
+                                let parent_for_restore = parent_clone.clone();
+                                let rebuild = rebuild.clone();
+                                backup_passphrase_dialog::prompt_restore_passphrase(
+                                    &parent_clone,
+                                    move |passphrase| {
+                                        restore_backup(
+                                            &parent_for_restore,
+                                            &state_clone,
+                                            rebuild,
+                                            json,
+                                            Some(passphrase),
+                                        );
+                                    },
+                                );
+                            }
                             Ok(json) => {
-                                let import_result = {
-                                    let mut store = state_clone.key_store.lock().unwrap();
-                                    store.import_backup(&json)
-                                };
-                                match import_result {
-                                    Ok(count) => {
-                                        rebuild();
-                                        let alert = adw::AlertDialog::builder()
-                                            .heading("Restore Complete")
-                                            .body(format!("Imported {count} key(s)."))
-                                            .build();
-                                        alert.add_response("ok", "OK");
-                                        alert.present(Some(&parent_clone));
-                                    }
-                                    Err(e) => {
-                                        log::error!("Failed to import backup: {e}");
-                                        let alert = adw::AlertDialog::builder()
-                                            .heading("Restore Failed")
-                                            .body(format!("{e}"))
-                                            .build();
-                                        alert.add_response("ok", "OK");
-                                        alert.present(Some(&parent_clone));
-                                    }
-                                }
+                                restore_backup(&parent_clone, &state_clone, rebuild.clone(), json, None)
                             }
                             Err(e) => log::error!("Failed to read backup file: {e}"),
                         }
@@ -510,3 +614,72 @@ pub fn show_key_manager_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
 
     dialog.present(Some(parent));
 }
+
+fn save_backup_file(parent: &adw::ApplicationWindow, json: String) {
+    let file_dialog = gtk::FileDialog::builder()
+        .title("Save Key Backup")
+        .initial_name("grustyssh-keys-backup.json")
+        .build();
+    let parent_clone = parent.clone();
+    file_dialog.save(
+        Some(parent),
+        gtk::gio::Cancellable::NONE,
+        move |result| {
+            if let Ok(file) = result {
+                if let Some(path) = file.path() {
+                    if let Err(e) = std::fs::write(&path, &json) {
+                        log::error!("Failed to write backup: {e}");
+                    } else {
+                        let alert = adw::AlertDialog::builder()
+                            .heading("Backup Saved")
+                            .body(format!("Keys backed up to {}", path.display()))
+                            .build();
+                        alert.add_response("ok", "OK");
+                        alert.present(Some(&parent_clone));
+                    }
+                }
+            }
+        },
+    );
+}
+
+fn restore_backup(
+    parent: &adw::ApplicationWindow,
+    state: &SharedState,
+    rebuild: impl Fn() + 'static,
+    json: String,
+    passphrase: Option<Zeroizing<String>>,
+) {
+    let parent = parent.clone();
+    let state = state.clone();
+    // Decrypt off the UI, the store is only locked to write the result
+    glib::spawn_future_local(async move {
+        let import_result = crate::runtime()
+            .spawn_blocking(move || {
+                KeyStore::read_backup(&json, passphrase.as_deref().map(|p| p.as_str()))
+            })
+            .await
+            .unwrap_or_else(|e| Err(AppError::Other(e.to_string())))
+            .and_then(|entries| state.key_store.lock().unwrap().import_backup(entries));
+        match import_result {
+            Ok(count) => {
+                rebuild();
+                let alert = adw::AlertDialog::builder()
+                    .heading("Restore Complete")
+                    .body(format!("Imported {count} key(s)."))
+                    .build();
+                alert.add_response("ok", "OK");
+                alert.present(Some(&parent));
+            }
+            Err(e) => {
+                log::error!("Failed to import backup: {e}");
+                let alert = adw::AlertDialog::builder()
+                    .heading("Restore Failed")
+                    .body(format!("{e}"))
+                    .build();
+                alert.add_response("ok", "OK");
+                alert.present(Some(&parent));
+            }
+        }
+    });
+}
@@ -1,8 +1,17 @@
+pub mod algorithm_rows;
+pub mod auth_prompt_dialog;
+pub mod backup_passphrase_dialog;
 pub mod connection_dialog;
 pub mod connection_list;
+pub mod host_key_dialog;
 pub mod key_manager_dialog;
+pub mod player_tab;
 pub mod preferences_dialog;
+pub mod session_log_rows;
 pub mod sftp_tab;
+pub mod ssh_config_import_dialog;
+pub mod terminal_search;
 pub mod terminal_tab;
 pub mod tunnel_dialog;
+pub mod tunnel_panel;
 pub mod window;
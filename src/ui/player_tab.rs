@@ -0,0 +1,238 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use gtk::glib;
+use libadwaita as adw;
+use vte4::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::app::SharedState;
+use crate::error::AppError;
+use crate::storage::asciicast::{self, CastEventKind, Recording};
+use crate::ui::terminal_tab;
+
+const SPEEDS: [f64; 5] = [0.5, 1.0, 2.0, 4.0, 8.0];
+const FRAME: Duration = Duration::from_millis(16);
+
+/// Playback position in a recording
+struct Player {
+    recording: Recording,
+    /// Seconds into the recording
+    position: f64,
+    /// Index of the first event not yet shown
+    next: usize,
+    playing: bool,
+    speed: f64,
+}
+
+impl Player {
+    /// Show every event up to the current position
+    fn catch_up(&mut self, terminal: &vte4::Terminal) {
+        while let Some(event) = self.recording.events.get(self.next) {
+            if event.time > self.position {
+                break;
+            }
+            match &event.kind {
+                CastEventKind::Output(data) => terminal.feed(data.as_bytes()),
+                CastEventKind::Resize { cols, rows } => {
+                    terminal.set_size(i64::from(*cols), i64::from(*rows));
+                }
+            }
+            self.next += 1;
+        }
+    }
+
+    /// Jump to `target` seconds. Going back replays the recording from the
+    /// start, since terminal output cannot be undone.
+    fn seek(&mut self, terminal: &vte4::Terminal, target: f64) {
+        if target < self.position {
+            terminal.reset(true, true);
+            let header = &self.recording.header;
+            terminal.set_size(i64::from(header.width), i64::from(header.height));
+            self.next = 0;
+        }
+        self.position = target.clamp(0.0, self.recording.duration());
+        self.catch_up(terminal);
+    }
+}
+
+/// Open an asciicast recording in a new tab with playback controls.
+pub fn open_player_tab(
+    tab_view: &adw::TabView,
+    path: &Path,
+    state: &SharedState,
+) -> Result<adw::TabPage, AppError> {
+    let recording = asciicast::load(path)?;
+    let duration = recording.duration();
+    let title = recording.header.title.clone().unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Recording".into())
+    });
+
+    let terminal = vte4::Terminal::new();
+    terminal.set_input_enabled(false);
+    {
+        let settings = state.settings.lock().unwrap();
+        terminal_tab::apply_terminal_settings(&terminal, &settings);
+    }
+    terminal.set_size(
+        i64::from(recording.header.width),
+        i64::from(recording.header.height),
+    );
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&terminal)
+        .vexpand(true)
+        .hexpand(true)
+        .build();
+
+    let play_btn = gtk::Button::builder()
+        .icon_name("media-playback-pause-symbolic")
+        .tooltip_text("Pause (Space)")
+        .build();
+    let position_scale = gtk::Scale::with_range(
+        gtk::Orientation::Horizontal,
+        0.0,
+        duration.max(0.1),
+        0.1,
+    );
+    position_scale.set_hexpand(true);
+    position_scale.set_draw_value(false);
+    let time_label = gtk::Label::builder()
+        .label(format_position(0.0, duration))
+        .css_classes(["numeric"])
+        .build();
+    let speed_dropdown = gtk::DropDown::from_strings(&["0.5×", "1×", "2×", "4×", "8×"]);
+    speed_dropdown.set_selected(1);
+    speed_dropdown.set_tooltip_text(Some("Playback speed"));
+
+    let controls = gtk::ActionBar::new();
+    controls.pack_start(&play_btn);
+    controls.set_center_widget(Some(&position_scale));
+    controls.pack_end(&speed_dropdown);
+    controls.pack_end(&time_label);
+
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    content.append(&scrolled);
+    content.append(&controls);
+
+    let page = tab_view.append(&content);
+    page.set_title(&format!("▶ {title}"));
+    page.set_tooltip(&path.display().to_string());
+
+    let player = Rc::new(RefCell::new(Player {
+        recording,
+        position: 0.0,
+        next: 0,
+        playing: true,
+        speed: 1.0,
+    }));
+    // Set while the timer moves the slider, so that is not taken as a seek
+    let updating_scale = Rc::new(Cell::new(false));
+
+    let player_for_play = player.clone();
+    let terminal_for_play = terminal.clone();
+    play_btn.connect_clicked(move |btn| {
+        let mut player = player_for_play.borrow_mut();
+        if !player.playing && player.position >= player.recording.duration() {
+            player.seek(&terminal_for_play, 0.0);
+        }
+        player.playing = !player.playing;
+        btn.set_icon_name(play_icon(player.playing));
+    });
+
+    // Space toggles playback, the terminal takes no input anyway
+    let key_ctrl = gtk::EventControllerKey::new();
+    let play_btn_for_keys = play_btn.downgrade();
+    key_ctrl.connect_key_pressed(move |_, keyval, _keycode, _modifiers| {
+        if keyval == gtk::gdk::Key::space {
+            if let Some(btn) = play_btn_for_keys.upgrade() {
+                btn.emit_clicked();
+            }
+            return glib::Propagation::Stop;
+        }
+        glib::Propagation::Proceed
+    });
+    terminal.add_controller(key_ctrl);
+
+    let player_for_seek = player.clone();
+    let terminal_for_seek = terminal.clone();
+    let updating_for_seek = updating_scale.clone();
+    let time_label_for_seek = time_label.clone();
+    position_scale.connect_value_changed(move |scale| {
+        if updating_for_seek.get() {
+            return;
+        }
+        let mut player = player_for_seek.borrow_mut();
+        player.seek(&terminal_for_seek, scale.value());
+        time_label_for_seek.set_label(&format_position(player.position, duration));
+    });
+
+    let player_for_speed = player.clone();
+    speed_dropdown.connect_selected_notify(move |dropdown| {
+        if let Some(speed) = SPEEDS.get(dropdown.selected() as usize) {
+            player_for_speed.borrow_mut().speed = *speed;
+        }
+    });
+
+    // Advance playback once per frame until the tab is closed. Only weak
+    // references, or the widgets would keep each other alive.
+    let terminal_weak = terminal.downgrade();
+    let play_btn_weak = play_btn.downgrade();
+    let scale_weak = position_scale.downgrade();
+    let time_label_weak = time_label.downgrade();
+    let mut last_frame = Instant::now();
+    glib::timeout_add_local(FRAME, move || {
+        let (Some(terminal), Some(play_btn), Some(position_scale), Some(time_label)) = (
+            terminal_weak.upgrade(),
+            play_btn_weak.upgrade(),
+            scale_weak.upgrade(),
+            time_label_weak.upgrade(),
+        ) else {
+            return glib::ControlFlow::Break;
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(last_frame).as_secs_f64();
+        last_frame = now;
+
+        let mut player = player.borrow_mut();
+        if !player.playing {
+            return glib::ControlFlow::Continue;
+        }
+        player.position = (player.position + elapsed * player.speed).min(duration);
+        player.catch_up(&terminal);
+        if player.position >= duration {
+            player.playing = false;
+            play_btn.set_icon_name(play_icon(false));
+        }
+
+        updating_scale.set(true);
+        position_scale.set_value(player.position);
+        updating_scale.set(false);
+        time_label.set_label(&format_position(player.position, duration));
+        glib::ControlFlow::Continue
+    });
+
+    Ok(page)
+}
+
+fn play_icon(playing: bool) -> &'static str {
+    if playing {
+        "media-playback-pause-symbolic"
+    } else {
+        "media-playback-start-symbolic"
+    }
+}
+
+/// `1:05 / 12:30`
+fn format_position(position: f64, duration: f64) -> String {
+    let minutes_seconds = |secs: f64| {
+        let secs = secs as u64;
+        format!("{}:{:02}", secs / 60, secs % 60)
+    };
+    format!("{} / {}", minutes_seconds(position), minutes_seconds(duration))
+}
@@ -5,12 +5,16 @@ use adw::prelude::*;
 
 use crate::app::SharedState;
 use crate::config::Settings;
+use crate::ui::{algorithm_rows, session_log_rows};
+
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub fn show_preferences_dialog(parent: &adw::ApplicationWindow, state: &SharedState) {
     let dialog = adw::Dialog::builder()
         .title("Preferences")
         .content_width(450)
-        .content_height(400)
+        .content_height(560)
         .build();
 
     let toolbar_view = adw::ToolbarView::new();
@@ -23,11 +27,10 @@ pub fn show_preferences_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
     header.pack_end(&save_btn);
     toolbar_view.add_top_bar(&header);
 
+    let page = adw::PreferencesPage::new();
+
     let group = adw::PreferencesGroup::builder()
         .title("Terminal Settings")
-        .margin_start(16)
-        .margin_end(16)
-        .margin_top(8)
         .build();
 
     let current_settings = state.settings.lock().unwrap().clone();
@@ -72,8 +75,177 @@ pub fn show_preferences_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
     group.add(&font_size_row);
     group.add(&scrollback_row);
     group.add(&term_type_row);
+    page.add(&group);
+
+    let agent_group = adw::PreferencesGroup::builder()
+        .title("SSH Agent")
+        .description(
+            "Serve the keys of the key manager to ssh and git. Connections pick it or \
+             the system agent in their SSH Agent setting.",
+        )
+        .build();
+
+    let agent_row = adw::SwitchRow::builder()
+        .title("Built-in Agent")
+        .active(current_settings.agent_enabled)
+        .build();
+
+    let lifetime_adj = gtk::Adjustment::new(
+        current_settings.agent_key_lifetime_minutes as f64,
+        0.0,
+        1440.0,
+        1.0,
+        15.0,
+        0.0,
+    );
+    let lifetime_row = adw::SpinRow::builder()
+        .title("Keep Keys Unlocked (minutes)")
+        .subtitle("0 keeps them unlocked until GrustySSH quits")
+        .adjustment(&lifetime_adj)
+        .build();
+
+    let socket_row = adw::ActionRow::builder()
+        .title("SSH_AUTH_SOCK")
+        .subtitle(crate::config::agent_socket_path().display().to_string())
+        .subtitle_selectable(true)
+        .build();
+
+    agent_group.add(&agent_row);
+    agent_group.add(&lifetime_row);
+    agent_group.add(&socket_row);
+    page.add(&agent_group);
+
+    let connection_group = adw::PreferencesGroup::builder()
+        .title("Connection")
+        .description("Detect connections that died without being closed, e.g. behind a NAT or firewall")
+        .build();
+
+    let keepalive_adj = gtk::Adjustment::new(
+        current_settings.keepalive_interval_secs as f64,
+        0.0,
+        3600.0,
+        1.0,
+        10.0,
+        0.0,
+    );
+    let keepalive_row = adw::SpinRow::builder()
+        .title("Keepalive Interval (seconds)")
+        .subtitle("0 sends no keepalives")
+        .adjustment(&keepalive_adj)
+        .build();
+
+    let keepalive_max_adj = gtk::Adjustment::new(
+        current_settings.keepalive_max as f64,
+        1.0,
+        100.0,
+        1.0,
+        5.0,
+        0.0,
+    );
+    let keepalive_max_row = adw::SpinRow::builder()
+        .title("Missed Keepalives")
+        .subtitle("Unanswered keepalives before the connection is closed")
+        .adjustment(&keepalive_max_adj)
+        .build();
+
+    let inactivity_adj = gtk::Adjustment::new(
+        current_settings.inactivity_timeout_secs as f64,
+        0.0,
+        86400.0,
+        1.0,
+        60.0,
+        0.0,
+    );
+    let inactivity_row = adw::SpinRow::builder()
+        .title("Inactivity Timeout (seconds)")
+        .subtitle("Close connections that receive nothing for this long, 0 = never")
+        .adjustment(&inactivity_adj)
+        .build();
 
-    toolbar_view.set_content(Some(&group));
+    let share_row = adw::SwitchRow::builder()
+        .title("Share Connections")
+        .subtitle("Open new tabs of a profile on its existing connection, without logging in again")
+        .active(current_settings.share_connections)
+        .build();
+
+    let idle_adj = gtk::Adjustment::new(
+        current_settings.connection_idle_secs as f64,
+        0.0,
+        86400.0,
+        1.0,
+        60.0,
+        0.0,
+    );
+    let idle_row = adw::SpinRow::builder()
+        .title("Keep Unused Connections (seconds)")
+        .subtitle("How long a shared connection stays open after its last tab is closed")
+        .adjustment(&idle_adj)
+        .sensitive(current_settings.share_connections)
+        .build();
+    let idle_row_for_share = idle_row.clone();
+    share_row.connect_active_notify(move |row| {
+        idle_row_for_share.set_sensitive(row.is_active());
+    });
+
+    connection_group.add(&keepalive_row);
+    connection_group.add(&keepalive_max_row);
+    connection_group.add(&inactivity_row);
+    connection_group.add(&share_row);
+    connection_group.add(&idle_row);
+
+    let algorithm_rows = algorithm_rows::build_algorithm_rows(
+        "Offered to every host, empty lists use the built-in defaults",
+        &current_settings.algorithms,
+    );
+    connection_group.add(&algorithm_rows.expander);
+    page.add(&connection_group);
+
+    let logging_group = adw::PreferencesGroup::builder()
+        .title("Session Logging")
+        .description("Profiles can override these settings")
+        .build();
+
+    let session_log_rows = session_log_rows::build_session_log_rows(&current_settings.session_log);
+    for row in session_log_rows.widgets() {
+        logging_group.add(&row);
+    }
+
+    let log_directory = Rc::new(RefCell::new(current_settings.log_directory.clone()));
+    let log_dir_row = adw::ActionRow::builder()
+        .title("Log Directory")
+        .subtitle(current_settings.log_directory.display().to_string())
+        .subtitle_selectable(true)
+        .build();
+    let log_dir_btn = gtk::Button::builder()
+        .label("Choose…")
+        .valign(gtk::Align::Center)
+        .build();
+    log_dir_row.add_suffix(&log_dir_btn);
+    logging_group.add(&log_dir_row);
+    page.add(&logging_group);
+
+    let parent_for_dir = parent.clone();
+    let log_directory_for_choose = log_directory.clone();
+    log_dir_btn.connect_clicked(move |_| {
+        let file_dialog = gtk::FileDialog::builder()
+            .title("Session Log Directory")
+            .initial_folder(&gtk::gio::File::for_path(&*log_directory_for_choose.borrow()))
+            .build();
+        let log_directory = log_directory_for_choose.clone();
+        let log_dir_row = log_dir_row.clone();
+        file_dialog.select_folder(
+            Some(&parent_for_dir),
+            gtk::gio::Cancellable::NONE,
+            move |result| {
+                if let Some(path) = result.ok().and_then(|folder| folder.path()) {
+                    log_dir_row.set_subtitle(&path.display().to_string());
+                    *log_directory.borrow_mut() = path;
+                }
+            },
+        );
+    });
+
+    toolbar_view.set_content(Some(&page));
     dialog.set_child(Some(&toolbar_view));
 
     // Enter key in entry rows triggers save
@@ -89,19 +261,32 @@ pub fn show_preferences_dialog(parent: &adw::ApplicationWindow, state: &SharedSt
     let state_clone = state.clone();
     let dialog_clone = dialog.clone();
     save_btn.connect_clicked(move |_| {
+        let Some(algorithms) = algorithm_rows.preferences() else {
+            return;
+        };
         let new_settings = Settings {
             font_family: font_family_row.text().to_string(),
             font_size: font_size_row.value() as u32,
             scrollback_lines: scrollback_row.value() as i64,
             default_terminal_type: term_type_row.text().to_string(),
+            agent_enabled: agent_row.is_active(),
+            agent_key_lifetime_minutes: lifetime_row.value() as u32,
+            keepalive_interval_secs: keepalive_row.value() as u32,
+            keepalive_max: keepalive_max_row.value() as u32,
+            inactivity_timeout_secs: inactivity_row.value() as u32,
+            share_connections: share_row.is_active(),
+            connection_idle_secs: idle_row.value() as u32,
+            algorithms,
+            session_log: session_log_rows.config(),
+            log_directory: log_directory.borrow().clone(),
         };
 
         if let Err(e) = new_settings.save() {
             log::error!("Failed to save settings: {e}");
         }
 
-        let mut settings = state_clone.settings.lock().unwrap();
-        *settings = new_settings;
+        *state_clone.settings.lock().unwrap() = new_settings;
+        crate::keys::agent::apply_settings(&state_clone);
         dialog_clone.close();
     });
 
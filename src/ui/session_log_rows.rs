@@ -0,0 +1,100 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use libadwaita as adw;
+use adw::prelude::*;
+
+use crate::models::connection::{LogFormat, SessionLogConfig};
+
+/// Rows editing a `SessionLogConfig`, shared by the connection and
+/// preferences dialogs.
+pub struct SessionLogRows {
+    enabled_row: adw::SwitchRow,
+    format_row: adw::ComboRow,
+    timestamps_row: adw::SwitchRow,
+    input_row: adw::SwitchRow,
+    template_row: adw::EntryRow,
+}
+
+pub fn build_session_log_rows(config: &SessionLogConfig) -> SessionLogRows {
+    let enabled_row = adw::SwitchRow::builder()
+        .title("Log Sessions")
+        .subtitle("Write everything the server sends to a file")
+        .build();
+
+    let format_row = adw::ComboRow::builder()
+        .title("Format")
+        .model(&gtk::StringList::new(&["Text", "Raw"]))
+        .build();
+
+    let timestamps_row = adw::SwitchRow::builder()
+        .title("Timestamps")
+        .subtitle("Start every line of a text log with the time")
+        .build();
+
+    let input_row = adw::SwitchRow::builder()
+        .title("Log Input")
+        .subtitle("Also record what is typed, including passwords at prompts")
+        .build();
+
+    let template_row = adw::EntryRow::builder()
+        .title("File Name ({profile}, {host}, {user}, {date}, {time})")
+        .build();
+
+    // Timestamps only apply to text logs
+    let timestamps_for_format = timestamps_row.clone();
+    format_row.connect_selected_notify(move |row| {
+        timestamps_for_format.set_sensitive(row.selected() == 0);
+    });
+
+    let rows = SessionLogRows {
+        enabled_row,
+        format_row,
+        timestamps_row,
+        input_row,
+        template_row,
+    };
+    rows.set(config);
+    rows
+}
+
+impl SessionLogRows {
+    pub fn widgets(&self) -> [gtk::Widget; 5] {
+        [
+            self.enabled_row.clone().upcast(),
+            self.format_row.clone().upcast(),
+            self.timestamps_row.clone().upcast(),
+            self.input_row.clone().upcast(),
+            self.template_row.clone().upcast(),
+        ]
+    }
+
+    pub fn set(&self, config: &SessionLogConfig) {
+        self.enabled_row.set_active(config.enabled);
+        self.format_row.set_selected(match config.format {
+            LogFormat::Text => 0,
+            LogFormat::Raw => 1,
+        });
+        self.timestamps_row.set_active(config.timestamps);
+        self.timestamps_row.set_sensitive(config.format == LogFormat::Text);
+        self.input_row.set_active(config.log_input);
+        self.template_row.set_text(&config.file_template);
+    }
+
+    pub fn config(&self) -> SessionLogConfig {
+        let template = self.template_row.text().trim().to_string();
+        SessionLogConfig {
+            enabled: self.enabled_row.is_active(),
+            format: match self.format_row.selected() {
+                1 => LogFormat::Raw,
+                _ => LogFormat::Text,
+            },
+            timestamps: self.timestamps_row.is_active(),
+            log_input: self.input_row.is_active(),
+            file_template: if template.is_empty() {
+                SessionLogConfig::default().file_template
+            } else {
+                template
+            },
+        }
+    }
+}
@@ -3,14 +3,14 @@ use gtk::prelude::*;
 use gtk::glib;
 use libadwaita as adw;
 use adw::prelude::*;
-use zeroize::Zeroizing;
 
 use std::cell::{Cell, RefCell};
 use std::path::PathBuf;
 use std::rc::Rc;
 
-use crate::models::connection::ConnectionProfile;
+use crate::app::{ConnectionChain, SshCommand};
 use crate::ssh::sftp::{
+    self,
     SftpCommand,
     SftpConflictDecision,
     SftpConflictDirection,
@@ -18,13 +18,16 @@ use crate::ssh::sftp::{
     SftpEntry,
     SftpEvent,
 };
+use crate::ui::auth_prompt_dialog;
+use crate::ui::host_key_dialog;
 
-/// Create a new SFTP file browser tab connected to the given profile.
+/// Create a new SFTP file browser tab connected to the destination of the given chain.
+/// With `terminal`, the command sender of a terminal tab, the browser is
+/// opened on that terminal's connection.
 pub fn create_sftp_tab(
     tab_view: &adw::TabView,
-    profile: &ConnectionProfile,
-    password: Option<Zeroizing<String>>,
-    key_passphrase: Option<Zeroizing<String>>,
+    chain: ConnectionChain,
+    terminal: Option<async_channel::Sender<SshCommand>>,
 ) -> adw::TabPage {
     // Main vertical box
     let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
@@ -117,18 +120,17 @@ pub fn create_sftp_tab(
     main_box.append(&transfer_bar);
 
     let page = tab_view.append(&main_box);
-    page.set_title(&format!("SFTP - {}", profile.name));
+    page.set_title(&format!("SFTP - {}", chain.profile().name));
     page.set_icon(Some(&gtk::gio::ThemedIcon::new("folder-symbolic")));
 
     // Set up SFTP channels
     let (event_tx, event_rx) = async_channel::bounded::<SftpEvent>(256);
 
-    let cmd_tx = crate::ssh::sftp::spawn_sftp_session(
-        profile.clone(),
-        password,
-        key_passphrase,
-        event_tx,
-    );
+    let (cmd_tx, cmd_rx) = async_channel::bounded::<SftpCommand>(64);
+    match terminal {
+        Some(terminal) => open_on_terminal_connection(terminal, chain, event_tx, cmd_rx),
+        None => sftp::spawn_sftp_session(chain, event_tx, cmd_rx),
+    }
 
     let cmd_tx_rc = Rc::new(cmd_tx);
     let remote_connected = Rc::new(Cell::new(false));
@@ -519,6 +521,14 @@ pub fn create_sftp_tab(
                         response_tx,
                     );
                 }
+                SftpEvent::HostKeyVerify(prompt) => {
+                    status_label_c.set_label("Verifying host key...");
+                    host_key_dialog::prompt_host_key(&conflict_anchor, prompt);
+                }
+                SftpEvent::AuthPrompt(prompt) => {
+                    status_label_c.set_label("Authenticating...");
+                    auth_prompt_dialog::prompt_keyboard_interactive(&conflict_anchor, prompt);
+                }
                 SftpEvent::Error(msg) => {
                     status_label_c.set_label(&format!("Error: {msg}"));
                 }
@@ -1270,7 +1280,26 @@ fn delete_selected_local_entries(
     }
 }
 
-fn format_size(bytes: u64) -> String {
+/// Ask a terminal's session to open the SFTP channel on its connection.
+/// Once the terminal is closed, connect like a tab of its own.
+fn open_on_terminal_connection(
+    terminal: async_channel::Sender<SshCommand>,
+    chain: ConnectionChain,
+    event_tx: async_channel::Sender<SftpEvent>,
+    cmd_rx: async_channel::Receiver<SftpCommand>,
+) {
+    glib::spawn_future_local(async move {
+        let open = SshCommand::OpenSftp {
+            event_tx: event_tx.clone(),
+            cmd_rx: cmd_rx.clone(),
+        };
+        if terminal.send(open).await.is_err() {
+            sftp::spawn_sftp_session(chain, event_tx, cmd_rx);
+        }
+    });
+}
+
+pub fn format_size(bytes: u64) -> String {
     if bytes < 1024 {
         format!("{bytes} B")
     } else if bytes < 1024 * 1024 {
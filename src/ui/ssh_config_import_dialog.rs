@@ -0,0 +1,172 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use libadwaita as adw;
+use adw::prelude::*;
+
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::app::SharedState;
+use crate::storage::ssh_config::{self, SshConfigHost};
+
+/// Parse an ssh_config file and let the user pick which hosts to import.
+/// `on_imported` runs after profiles were added.
+pub fn show_ssh_config_import_dialog(
+    parent: &adw::ApplicationWindow,
+    state: &SharedState,
+    path: &Path,
+    on_imported: impl Fn() + 'static,
+) {
+    let hosts = match ssh_config::load(path) {
+        Ok(hosts) if !hosts.is_empty() => hosts,
+        Ok(_) => {
+            show_message(parent, "Nothing to Import", &format!("{} defines no hosts.", path.display()));
+            return;
+        }
+        Err(e) => {
+            log::error!("Failed to parse ssh_config: {e}");
+            show_message(parent, "Import Failed", &e.to_string());
+            return;
+        }
+    };
+
+    let existing_names: Vec<String> = {
+        let store = state.profile_store.lock().unwrap();
+        store.profiles.iter().map(|p| p.name.clone()).collect()
+    };
+
+    let dialog = adw::Dialog::builder()
+        .title("Import SSH Config")
+        .content_width(520)
+        .content_height(560)
+        .build();
+
+    let toolbar_view = adw::ToolbarView::new();
+    let header = adw::HeaderBar::new();
+
+    let import_btn = gtk::Button::builder()
+        .label("Import")
+        .css_classes(["suggested-action"])
+        .build();
+    header.pack_end(&import_btn);
+    toolbar_view.add_top_bar(&header);
+
+    let content_box = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    content_box.set_margin_start(16);
+    content_box.set_margin_end(16);
+    content_box.set_margin_top(8);
+    content_box.set_margin_bottom(16);
+
+    let group = adw::PreferencesGroup::builder()
+        .title("Hosts")
+        .description(path.display().to_string())
+        .build();
+
+    let mut checks: Vec<(String, gtk::CheckButton)> = Vec::new();
+    for host in &hosts {
+        let exists = existing_names.contains(&host.alias);
+        let check = gtk::CheckButton::builder()
+            .active(!exists)
+            .sensitive(!exists)
+            .valign(gtk::Align::Center)
+            .build();
+
+        let subtitle = if exists {
+            "A connection with this name already exists".to_string()
+        } else {
+            host_summary(host)
+        };
+        let row = adw::ActionRow::builder()
+            .title(&host.alias)
+            .subtitle(&subtitle)
+            .activatable_widget(&check)
+            .build();
+        row.add_prefix(&check);
+        group.add(&row);
+
+        checks.push((host.alias.clone(), check));
+    }
+    content_box.append(&group);
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&content_box)
+        .vexpand(true)
+        .build();
+    toolbar_view.set_content(Some(&scrolled));
+    dialog.set_child(Some(&toolbar_view));
+
+    let checks = Rc::new(checks);
+    let hosts = Rc::new(hosts);
+    let state = state.clone();
+    let parent_for_import = parent.clone();
+    let dialog_for_import = dialog.clone();
+    import_btn.connect_clicked(move |_| {
+        let selected: Vec<String> = checks
+            .iter()
+            .filter(|(_, check)| check.is_active() && check.is_sensitive())
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        if selected.is_empty() {
+            dialog_for_import.close();
+            return;
+        }
+
+        let result = {
+            let mut profile_store = state.profile_store.lock().unwrap();
+            let mut key_store = state.key_store.lock().unwrap();
+            profile_store.import_ssh_config(&hosts, &selected, &mut key_store)
+        };
+        dialog_for_import.close();
+        match result {
+            Ok(count) => {
+                on_imported();
+                show_message(
+                    &parent_for_import,
+                    "Import Complete",
+                    &format!("Imported {count} connection(s)."),
+                );
+            }
+            Err(e) => {
+                log::error!("Failed to import ssh_config: {e}");
+                show_message(&parent_for_import, "Import Failed", &e.to_string());
+            }
+        }
+    });
+
+    dialog.present(Some(parent));
+}
+
+/// One line describing where a host points, e.g. `deploy@10.0.0.5:2222 · 2 forward(s) · via bastion`.
+fn host_summary(host: &SshConfigHost) -> String {
+    let user = host
+        .user
+        .clone()
+        .unwrap_or_else(ssh_config::local_username);
+    let mut parts = vec![format!("{user}@{}:{}", host.hostname, host.port)];
+
+    if !host.forwards.is_empty() {
+        parts.push(format!("{} forward(s)", host.forwards.len()));
+    }
+    if !host.identity_files.is_empty() {
+        let names: Vec<String> = host
+            .identity_files
+            .iter()
+            .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .collect();
+        parts.push(names.join(", "));
+    }
+    if !host.proxy_jump.is_empty() {
+        let hops: Vec<String> = host.proxy_jump.iter().map(|j| j.to_string()).collect();
+        parts.push(format!("via {}", hops.join(", ")));
+    }
+    parts.join(" · ")
+}
+
+fn show_message(parent: &adw::ApplicationWindow, heading: &str, body: &str) {
+    let alert = adw::AlertDialog::builder()
+        .heading(heading)
+        .body(body)
+        .build();
+    alert.add_response("ok", "OK");
+    alert.present(Some(parent));
+}
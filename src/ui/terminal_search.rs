@@ -0,0 +1,165 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use gtk::glib;
+use vte4::prelude::*;
+
+use std::rc::Rc;
+
+// PCRE2 compile flags, VTE takes them as plain integers. Search regexes
+// must be multiline so `^` and `$` match at every terminal line.
+const PCRE2_CASELESS: u32 = 0x0000_0008;
+const PCRE2_MULTILINE: u32 = 0x0000_0400;
+
+/// Search bar for the scrollback of a terminal, hidden until
+/// `search_mode` is turned on. The current match is selected in the
+/// terminal; searching wraps around at either end.
+pub fn build_search_bar(terminal: &vte4::Terminal) -> gtk::SearchBar {
+    let entry = gtk::SearchEntry::builder()
+        .placeholder_text("Search scrollback")
+        .hexpand(true)
+        .build();
+
+    let case_btn = gtk::ToggleButton::builder()
+        .label("Aa")
+        .tooltip_text("Match Case")
+        .build();
+    let regex_btn = gtk::ToggleButton::builder()
+        .label(".*")
+        .tooltip_text("Regular Expression")
+        .build();
+    let previous_btn = gtk::Button::builder()
+        .icon_name("go-up-symbolic")
+        .tooltip_text("Previous Match (Enter)")
+        .build();
+    let next_btn = gtk::Button::builder()
+        .icon_name("go-down-symbolic")
+        .tooltip_text("Next Match (Shift+Enter)")
+        .build();
+
+    let options = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    options.add_css_class("linked");
+    options.append(&case_btn);
+    options.append(&regex_btn);
+    let navigation = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    navigation.add_css_class("linked");
+    navigation.append(&previous_btn);
+    navigation.append(&next_btn);
+
+    let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    row.append(&entry);
+    row.append(&options);
+    row.append(&navigation);
+
+    let search_bar = gtk::SearchBar::builder()
+        .child(&row)
+        .show_close_button(true)
+        .build();
+    search_bar.connect_entry(&entry);
+    terminal.search_set_wrap_around(true);
+
+    // Compile the pattern and jump to the newest match, searching up from
+    // the bottom of the scrollback
+    let update: Rc<dyn Fn()> = {
+        let terminal = terminal.clone();
+        let entry = entry.clone();
+        let case_btn = case_btn.clone();
+        let regex_btn = regex_btn.clone();
+        Rc::new(move || {
+            entry.remove_css_class("error");
+            entry.set_tooltip_text(None);
+            let text = entry.text();
+            if text.is_empty() {
+                terminal.search_set_regex(None, 0);
+                terminal.unselect_all();
+                return;
+            }
+
+            let pattern = if regex_btn.is_active() {
+                text.to_string()
+            } else {
+                glib::Regex::escape_string(&text).to_string()
+            };
+            let mut flags = PCRE2_MULTILINE;
+            if !case_btn.is_active() {
+                flags |= PCRE2_CASELESS;
+            }
+            match vte4::Regex::for_search(&pattern, flags) {
+                Ok(regex) => {
+                    terminal.search_set_regex(Some(&regex), 0);
+                    terminal.unselect_all();
+                    if !terminal.search_find_previous() {
+                        entry.add_css_class("error");
+                    }
+                }
+                Err(e) => {
+                    terminal.search_set_regex(None, 0);
+                    entry.add_css_class("error");
+                    entry.set_tooltip_text(Some(e.message()));
+                }
+            }
+        })
+    };
+
+    let update_for_entry = update.clone();
+    entry.connect_search_changed(move |_| update_for_entry());
+    let update_for_case = update.clone();
+    case_btn.connect_toggled(move |_| update_for_case());
+    regex_btn.connect_toggled(move |_| update());
+
+    // Enter goes on to older matches, like scrolling up through the output
+    let find_previous = {
+        let terminal = terminal.clone();
+        let entry = entry.clone();
+        move || {
+            if terminal.search_get_regex().is_some() && !terminal.search_find_previous() {
+                entry.add_css_class("error");
+            }
+        }
+    };
+    let find_next = {
+        let terminal = terminal.clone();
+        let entry = entry.clone();
+        move || {
+            if terminal.search_get_regex().is_some() && !terminal.search_find_next() {
+                entry.add_css_class("error");
+            }
+        }
+    };
+
+    let previous_for_entry = find_previous.clone();
+    entry.connect_activate(move |_| previous_for_entry());
+    let previous_for_signal = find_previous.clone();
+    entry.connect_previous_match(move |_| previous_for_signal());
+    previous_btn.connect_clicked(move |_| find_previous());
+    let next_for_signal = find_next.clone();
+    entry.connect_next_match(move |_| next_for_signal());
+    next_btn.connect_clicked(move |_| find_next());
+
+    // Shift+Enter searches the other way
+    let key_ctrl = gtk::EventControllerKey::new();
+    let next_btn_for_keys = next_btn.downgrade();
+    key_ctrl.connect_key_pressed(move |_, keyval, _keycode, modifiers| {
+        let enter = matches!(keyval, gtk::gdk::Key::Return | gtk::gdk::Key::KP_Enter);
+        if enter && modifiers.contains(gtk::gdk::ModifierType::SHIFT_MASK) {
+            if let Some(btn) = next_btn_for_keys.upgrade() {
+                btn.emit_clicked();
+            }
+            return glib::Propagation::Stop;
+        }
+        glib::Propagation::Proceed
+    });
+    entry.add_controller(key_ctrl);
+
+    // Closing the bar hands the keyboard back to the terminal
+    let terminal_for_close = terminal.downgrade();
+    search_bar.connect_search_mode_enabled_notify(move |bar| {
+        if !bar.is_search_mode() {
+            if let Some(terminal) = terminal_for_close.upgrade() {
+                terminal.unselect_all();
+                terminal.grab_focus();
+            }
+        }
+    });
+
+    search_bar
+}
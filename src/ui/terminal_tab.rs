@@ -3,25 +3,87 @@ use gtk::prelude::*;
 use gtk::glib;
 use libadwaita as adw;
 use vte4::prelude::*;
-use zeroize::Zeroizing;
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::time::Duration;
 
-use crate::app::{SharedState, SshCommand, SshEvent};
-use crate::config::Settings;
+use crate::app::{ConnectionChain, DisconnectReason, SharedState, SshCommand, SshEvent};
+use crate::config::{self, Settings};
+use crate::error::AppError;
 use crate::models::connection::ConnectionProfile;
+use crate::ssh::options::SessionOptions;
 use crate::ssh::session;
+use crate::ssh::sftp;
+use crate::storage::asciicast::CastRecorder;
+use crate::storage::session_log;
+use crate::ui::auth_prompt_dialog;
+use crate::ui::host_key_dialog;
+use crate::ui::sftp_tab;
+use crate::ui::terminal_search;
+use crate::ui::tunnel_panel;
 
-/// Create a new terminal tab connected to the given profile.
+/// Longest wait between automatic reconnect attempts
+const MAX_RECONNECT_DELAY_SECS: u32 = 60;
+
+/// Create a new terminal tab connected to the destination of the given chain.
 /// Returns the tab page widget.
 pub fn create_terminal_tab(
     tab_view: &adw::TabView,
-    profile: &ConnectionProfile,
-    password: Option<Zeroizing<String>>,
-    key_passphrase: Option<Zeroizing<String>>,
+    chain: ConnectionChain,
     state: &SharedState,
 ) -> adw::TabPage {
+    build_terminal_tab(tab_view, chain, state, None)
+}
+
+/// Open another shell on the connection of a terminal tab, in a new tab
+/// next to it.
+pub fn duplicate_tab(
+    tab_view: &adw::TabView,
+    page: &adw::TabPage,
+    state: &SharedState,
+) -> Option<adw::TabPage> {
+    // A disconnected tab has no connection to share, the duplicate makes its own
+    let (chain, cmd_tx) = tab_session(page)?;
+    let duplicate = build_terminal_tab(tab_view, chain, state, cmd_tx);
+    tab_view.reorder_page(&duplicate, tab_view.page_position(page) + 1);
+    tab_view.set_selected_page(&duplicate);
+    Some(duplicate)
+}
+
+/// Whether `page` is a terminal tab, which can be duplicated
+pub fn is_terminal_tab(page: &adw::TabPage) -> bool {
+    tab_session(page).is_some()
+}
+
+/// The chain stored on a terminal tab, and its command sender while it is
+/// connected
+fn tab_session(
+    page: &adw::TabPage,
+) -> Option<(ConnectionChain, Option<async_channel::Sender<SshCommand>>)> {
+    // SAFETY: We only store and retrieve our own typed data under known keys
+    unsafe {
+        let chain = ConnectionChain::clone(page.data::<Rc<ConnectionChain>>("chain")?.as_ref());
+        let connected = page
+            .data::<Rc<Cell<bool>>>("connected")
+            .is_some_and(|connected| connected.as_ref().get());
+        let cmd_tx = page
+            .data::<Rc<async_channel::Sender<SshCommand>>>("cmd_tx")
+            .filter(|_| connected)
+            .map(|cmd_tx| async_channel::Sender::clone(cmd_tx.as_ref()));
+        Some((chain, cmd_tx))
+    }
+}
+
+/// Build a terminal tab. With `origin`, the command sender of another
+/// terminal tab, the first shell is opened on that tab's connection.
+fn build_terminal_tab(
+    tab_view: &adw::TabView,
+    chain: ConnectionChain,
+    state: &SharedState,
+    origin: Option<async_channel::Sender<SshCommand>>,
+) -> adw::TabPage {
+    let profile = chain.profile().clone();
     let terminal = vte4::Terminal::new();
 
     // Explicitly set erase bindings so VTE doesn't try to read from a
@@ -49,9 +111,43 @@ pub fn create_terminal_tab(
     });
     terminal.add_controller(gesture_click);
 
-    // Ctrl+Shift+C = copy, Ctrl+Shift+V = paste
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&terminal)
+        .vexpand(true)
+        .hexpand(true)
+        .build();
+
+    // Shown while the connection is down, with the reconnect countdown
+    let banner = adw::Banner::builder().revealed(false).build();
+    let search_bar = terminal_search::build_search_bar(&terminal);
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    content.append(&banner);
+    content.append(&search_bar);
+    content.append(&scrolled);
+
+    // Tunnel side panel, hidden until toggled
+    let split_view = adw::OverlaySplitView::builder()
+        .content(&content)
+        .sidebar_position(gtk::PackType::End)
+        .show_sidebar(false)
+        .collapsed(false)
+        .build();
+
+    let recorder = Rc::new(TabRecorder {
+        terminal: terminal.downgrade(),
+        page: RefCell::new(glib::WeakRef::new()),
+        profile: profile.clone(),
+        term_type: SessionOptions::resolve(&chain.settings, &profile).terminal_type,
+        recorder: RefCell::new(None),
+    });
+
+    // Ctrl+Shift+C = copy, Ctrl+Shift+V = paste, Ctrl+Shift+T = tunnels panel,
+    // Ctrl+Shift+R = start or stop recording, Ctrl+Shift+F = search
+    let recorder_for_keys = recorder.clone();
     let key_ctrl = gtk::EventControllerKey::new();
     let term_for_keys = terminal.clone();
+    let split_for_keys = split_view.clone();
+    let search_bar_for_keys = search_bar.clone();
     key_ctrl.connect_key_pressed(move |_, keyval, _keycode, modifiers| {
         let ctrl_shift = gtk::gdk::ModifierType::CONTROL_MASK
             | gtk::gdk::ModifierType::SHIFT_MASK;
@@ -65,6 +161,18 @@ pub fn create_terminal_tab(
                     term_for_keys.paste_clipboard();
                     return glib::Propagation::Stop;
                 }
+                gtk::gdk::Key::T => {
+                    split_for_keys.set_show_sidebar(!split_for_keys.shows_sidebar());
+                    return glib::Propagation::Stop;
+                }
+                gtk::gdk::Key::R => {
+                    recorder_for_keys.toggle();
+                    return glib::Propagation::Stop;
+                }
+                gtk::gdk::Key::F => {
+                    search_bar_for_keys.set_search_mode(true);
+                    return glib::Propagation::Stop;
+                }
                 _ => {}
             }
         }
@@ -72,24 +180,26 @@ pub fn create_terminal_tab(
     });
     terminal.add_controller(key_ctrl);
 
-    let scrolled = gtk::ScrolledWindow::builder()
-        .child(&terminal)
-        .vexpand(true)
-        .hexpand(true)
-        .build();
-
-    let page = tab_view.append(&scrolled);
+    let page = tab_view.append(&split_view);
     page.set_title(&profile.name);
+    recorder.page.replace(page.downgrade());
 
-    // Set up async channels
+    // Set up async channels. The command channel outlives individual
+    // sessions, so the widgets stay wired to it across reconnects.
     let (event_tx, event_rx) = async_channel::bounded::<SshEvent>(256);
-
-    // Spawn the SSH session and get the command sender
-    let cmd_tx = session::spawn_session(profile.clone(), password, key_passphrase, event_tx);
+    let (cmd_tx, cmd_rx) = async_channel::bounded::<SshCommand>(64);
 
     // Store cmd_tx in an Rc for sharing across closures
     let cmd_tx_rc = Rc::new(cmd_tx);
 
+    let (banner_tx, banner_rx) = async_channel::unbounded::<()>();
+    banner.connect_button_clicked(move |_| {
+        let _ = banner_tx.try_send(());
+    });
+
+    let tunnel_panel = tunnel_panel::build_tunnel_panel(&profile.tunnels, cmd_tx_rc.clone());
+    split_view.set_sidebar(Some(&tunnel_panel.container));
+
     // Wire terminal input -> SSH command
     let cmd_tx_input = cmd_tx_rc.clone();
     terminal.connect_commit(move |_term, text, _size| {
@@ -106,17 +216,21 @@ pub fn create_terminal_tab(
     // a resize command is sent only when values actually change.
     let last_cols = Rc::new(Cell::new(terminal.column_count()));
     let last_rows = Rc::new(Cell::new(terminal.row_count()));
+    let last_cols_for_resize = last_cols.clone();
+    let last_rows_for_resize = last_rows.clone();
     let cmd_tx_resize = cmd_tx_rc.clone();
     let term_for_resize = terminal.clone();
+    let recorder_for_resize = recorder.clone();
     terminal.add_tick_callback(move |_widget, _clock| {
         let cols = term_for_resize.column_count();
         let rows = term_for_resize.row_count();
-        if cols != last_cols.get() || rows != last_rows.get() {
-            last_cols.set(cols);
-            last_rows.set(rows);
+        if cols != last_cols_for_resize.get() || rows != last_rows_for_resize.get() {
+            last_cols_for_resize.set(cols);
+            last_rows_for_resize.set(rows);
             let tx = (*cmd_tx_resize).clone();
             let cols = cols as u32;
             let rows = rows as u32;
+            recorder_for_resize.resize(cols, rows);
             glib::spawn_future_local(async move {
                 let _ = tx.send(SshCommand::Resize { cols, rows }).await;
             });
@@ -124,51 +238,154 @@ pub fn create_terminal_tab(
         glib::ControlFlow::Continue
     });
 
-    // Poll SSH events and feed data to terminal
+    // Run the session, and with auto-reconnect start a new one whenever the
+    // connection is lost. Poll SSH events and feed data to terminal.
     let terminal_clone = terminal.clone();
+    let banner_weak = banner.downgrade();
+    let auto_reconnect = profile.auto_reconnect;
+    let chain_for_page = Rc::new(chain.clone());
+    // Whether a session is running that duplicates can join
+    let connected = Rc::new(Cell::new(false));
+    let connected_for_page = connected.clone();
+    // The tab it was duplicated from already runs the profile's tunnels
+    let start_tunnels = origin.is_none();
+    let mut origin = origin;
+    let recorder_for_page = recorder.clone();
     glib::spawn_future_local(async move {
-        while let Ok(event) = event_rx.recv().await {
-            match event {
-                SshEvent::Connected => {
-                    log::info!("SSH session connected");
-                    terminal_clone.grab_focus();
-                }
-                SshEvent::Data(data) => {
-                    terminal_clone.feed(&data);
-                }
-                SshEvent::Disconnected(reason) => {
-                    if let Some(reason) = reason {
-                        let msg = format!("\r\n[Disconnected: {}]\r\n", reason);
+        let mut attempt: u32 = 0;
+        loop {
+            let attached = match origin.take() {
+                Some(origin) => origin
+                    .send(SshCommand::OpenShell {
+                        event_tx: event_tx.clone(),
+                        cmd_rx: cmd_rx.clone(),
+                    })
+                    .await
+                    .is_ok(),
+                None => false,
+            };
+            if !attached {
+                session::spawn_session(
+                    chain.clone(),
+                    event_tx.clone(),
+                    cmd_rx.clone(),
+                    start_tunnels,
+                );
+            }
+
+            let reason = loop {
+                let Ok(event) = event_rx.recv().await else {
+                    return;
+                };
+                match event {
+                    SshEvent::Connected => {
+                        log::info!("SSH session connected");
+                        connected.set(true);
+                        attempt = 0;
+                        if let Some(banner) = banner_weak.upgrade() {
+                            banner.set_revealed(false);
+                        }
+                        // A new PTY starts at 80x24, make the tick callback resend our size
+                        last_cols.set(-1);
+                        terminal_clone.grab_focus();
+                    }
+                    SshEvent::AlgorithmsNegotiated(negotiated) => {
+                        let msg = format!("\r\n[Algorithms: {}]\r\n", negotiated);
                         terminal_clone.feed(msg.as_bytes());
-                    } else {
-                        terminal_clone.feed(b"\r\n[Disconnected]\r\n");
                     }
-                    break;
-                }
-                SshEvent::Error(msg) => {
-                    let err_msg = format!("\r\n[Error: {}]\r\n", msg);
-                    terminal_clone.feed(err_msg.as_bytes());
+                    SshEvent::AgentInUse(agent) => {
+                        let msg = format!("\r\n[SSH agent: {}]\r\n", agent);
+                        terminal_clone.feed(msg.as_bytes());
+                    }
+                    SshEvent::LogStarted(path) => {
+                        let msg = format!("\r\n[Logging to {}]\r\n", path.display());
+                        terminal_clone.feed(msg.as_bytes());
+                    }
+                    SshEvent::Data(data) => {
+                        terminal_clone.feed(&data);
+                        recorder.output(&data);
+                    }
+                    SshEvent::Disconnected(reason) => {
+                        connected.set(false);
+                        break reason;
+                    }
+                    SshEvent::Error(msg) => {
+                        let err_msg = format!("\r\n[Error: {}]\r\n", msg);
+                        terminal_clone.feed(err_msg.as_bytes());
+                    }
+                    SshEvent::HostKeyVerify(prompt) => {
+                        let msg = format!(
+                            "\r\n[Unknown host key ({}): {}]\r\n",
+                            prompt.key_type, prompt.fingerprint
+                        );
+                        terminal_clone.feed(msg.as_bytes());
+                        host_key_dialog::prompt_host_key(&terminal_clone, prompt);
+                    }
+                    SshEvent::AuthPrompt(prompt) => {
+                        auth_prompt_dialog::prompt_keyboard_interactive(&terminal_clone, prompt);
+                    }
+                    SshEvent::TunnelEstablished(id) => {
+                        tunnel_panel.set_running(&id);
+                        let name = tunnel_panel.tunnel_name(&id).unwrap_or_else(|| id.to_string());
+                        let msg = format!("\r\n[Tunnel {} established]\r\n", name);
+                        terminal_clone.feed(msg.as_bytes());
+                    }
+                    SshEvent::TunnelFailed(id, err) => {
+                        tunnel_panel.set_failed(&id, &err);
+                        let name = tunnel_panel.tunnel_name(&id).unwrap_or_else(|| id.to_string());
+                        let msg = format!("\r\n[Tunnel {} failed: {}]\r\n", name, err);
+                        terminal_clone.feed(msg.as_bytes());
+                    }
+                    SshEvent::TunnelStopped(id) => {
+                        tunnel_panel.set_stopped(&id);
+                        let name = tunnel_panel.tunnel_name(&id).unwrap_or_else(|| id.to_string());
+                        let msg = format!("\r\n[Tunnel {} stopped]\r\n", name);
+                        terminal_clone.feed(msg.as_bytes());
+                    }
+                    SshEvent::TunnelStats(snapshots) => {
+                        tunnel_panel.update_stats(&snapshots);
+                    }
                 }
-                SshEvent::HostKeyVerify {
-                    key_type,
-                    fingerprint,
-                } => {
-                    let msg = format!(
-                        "\r\n[Host key ({key_type}): {fingerprint}]\r\n\
-                         [Accepting host key automatically (TOFU)]\r\n"
-                    );
+            };
+
+            tunnel_panel.set_all_stopped();
+            match reason {
+                DisconnectReason::Lost(ref msg)
+                | DisconnectReason::TimedOut(ref msg)
+                | DisconnectReason::Failed(ref msg) => {
+                    let msg = format!("\r\n[Disconnected: {}]\r\n", msg);
                     terminal_clone.feed(msg.as_bytes());
                 }
-                SshEvent::TunnelEstablished(id) => {
-                    let msg = format!("\r\n[Tunnel {} established]\r\n", id);
-                    terminal_clone.feed(msg.as_bytes());
+                DisconnectReason::User | DisconnectReason::RemoteClosed => {
+                    terminal_clone.feed(b"\r\n[Disconnected]\r\n");
                 }
-                SshEvent::TunnelFailed(id, err) => {
-                    let msg = format!("\r\n[Tunnel {} failed: {}]\r\n", id, err);
-                    terminal_clone.feed(msg.as_bytes());
+            }
+            if !reason.is_recoverable() {
+                break;
+            }
+
+            let delay = auto_reconnect.then(|| reconnect_delay(attempt));
+            attempt = attempt.saturating_add(1);
+            if !wait_for_reconnect(&banner_weak, &banner_rx, delay).await {
+                break;
+            }
+
+            // A queued Disconnect means the user gave up on this tab
+            if discard_pending_commands(&cmd_rx, &chain) {
+                if let Some(banner) = banner_weak.upgrade() {
+                    banner.set_revealed(false);
                 }
+                break;
+            }
+
+            if let Some(banner) = banner_weak.upgrade() {
+                banner.set_title("Reconnecting…");
+                banner.set_button_label(None);
             }
+            terminal_clone.feed(b"\r\n[Reconnecting...]\r\n");
         }
+        connected.set(false);
+        discard_pending_commands(&cmd_rx, &chain);
     });
 
     // Send initial terminal size
@@ -179,6 +396,17 @@ pub fn create_terminal_tab(
         let _ = cmd_tx_init.send(SshCommand::Resize { cols, rows }).await;
     });
 
+    // SAFETY: We only store and retrieve our own typed data under a known key
+    unsafe {
+        page.set_data::<Rc<TabRecorder>>("recorder", recorder_for_page);
+    }
+
+    // SAFETY: We only store and retrieve our own typed data under known keys
+    unsafe {
+        page.set_data::<Rc<ConnectionChain>>("chain", chain_for_page);
+        page.set_data::<Rc<Cell<bool>>>("connected", connected_for_page);
+    }
+
     // Store cmd_tx Rc in the page's GObject data for later disconnect
     let cmd_tx_for_page = cmd_tx_rc.clone();
     // SAFETY: We only store and retrieve our own typed data under a known key
@@ -189,7 +417,55 @@ pub fn create_terminal_tab(
     page
 }
 
-fn apply_terminal_settings(terminal: &vte4::Terminal, settings: &Settings) {
+/// Seconds before reconnect attempt `attempt` (counted from 0): 1, 2, 4, … up to a minute.
+fn reconnect_delay(attempt: u32) -> u32 {
+    (1u32 << attempt.min(6)).min(MAX_RECONNECT_DELAY_SECS)
+}
+
+/// Count down `delay` seconds on the banner, or without a delay wait for the
+/// user to ask for a reconnect. Cancelling the countdown turns it into the
+/// latter. Returns `false` when the tab went away instead.
+async fn wait_for_reconnect(
+    banner: &glib::WeakRef<adw::Banner>,
+    clicks: &async_channel::Receiver<()>,
+    delay: Option<u32>,
+) -> bool {
+    if let Some(delay) = delay {
+        let mut cancelled = false;
+        for remaining in (1..=delay).rev() {
+            let Some(strong) = banner.upgrade() else {
+                return false;
+            };
+            strong.set_title(&format!("Connection lost. Reconnecting in {remaining} s…"));
+            strong.set_button_label(Some("Cancel"));
+            strong.set_revealed(true);
+            drop(strong);
+
+            match glib::future_with_timeout(Duration::from_secs(1), clicks.recv()).await {
+                Err(_) => {}
+                Ok(Ok(())) => {
+                    cancelled = true;
+                    break;
+                }
+                Ok(Err(_)) => return false,
+            }
+        }
+        if !cancelled {
+            return true;
+        }
+    }
+
+    let Some(strong) = banner.upgrade() else {
+        return false;
+    };
+    strong.set_title("Connection lost");
+    strong.set_button_label(Some("Reconnect"));
+    strong.set_revealed(true);
+    drop(strong);
+    clicks.recv().await.is_ok()
+}
+
+pub fn apply_terminal_settings(terminal: &vte4::Terminal, settings: &Settings) {
     let font_desc = gtk::pango::FontDescription::from_string(&format!(
         "{} {}",
         settings.font_family, settings.font_size
@@ -198,6 +474,143 @@ fn apply_terminal_settings(terminal: &vte4::Terminal, settings: &Settings) {
     terminal.set_scrollback_lines(settings.scrollback_lines);
 }
 
+/// Start or stop recording a terminal tab to an asciicast file.
+pub fn toggle_recording(page: &adw::TabPage) {
+    // SAFETY: We only store and retrieve our own typed data under a known key
+    unsafe {
+        if let Some(recorder) = page.data::<Rc<TabRecorder>>("recorder") {
+            recorder.as_ref().toggle();
+        }
+    }
+}
+
+/// Asciicast recording of a terminal tab. Output and size changes are
+/// recorded while one is running.
+struct TabRecorder {
+    terminal: glib::WeakRef<vte4::Terminal>,
+    page: RefCell<glib::WeakRef<adw::TabPage>>,
+    profile: ConnectionProfile,
+    term_type: String,
+    recorder: RefCell<Option<CastRecorder>>,
+}
+
+impl TabRecorder {
+    fn toggle(&self) {
+        let Some(terminal) = self.terminal.upgrade() else {
+            return;
+        };
+        let page = self.page.borrow().upgrade();
+
+        if let Some(mut recorder) = self.recorder.take() {
+            let msg = match recorder.flush() {
+                Ok(()) => format!("\r\n[Recording saved to {}]\r\n", recorder.path().display()),
+                Err(e) => format!("\r\n[Recording failed: {e}]\r\n"),
+            };
+            terminal.feed(msg.as_bytes());
+            if let Some(page) = page {
+                page.set_indicator_icon(gtk::gio::Icon::NONE);
+            }
+            return;
+        }
+
+        let file_name = format!(
+            "{}.cast",
+            session_log::file_name("{profile}-{date}-{time}", &self.profile)
+        );
+        let path = config::recordings_dir().join(file_name);
+        match CastRecorder::create(
+            &path,
+            terminal.column_count() as u32,
+            terminal.row_count() as u32,
+            &self.profile.name,
+            &self.term_type,
+        ) {
+            Ok(recorder) => {
+                let msg = format!("\r\n[Recording to {}]\r\n", path.display());
+                terminal.feed(msg.as_bytes());
+                if let Some(page) = page {
+                    page.set_indicator_icon(Some(&gtk::gio::ThemedIcon::new("media-record-symbolic")));
+                    page.set_indicator_tooltip("Recording");
+                }
+                self.recorder.replace(Some(recorder));
+            }
+            Err(e) => {
+                let msg = format!("\r\n[Cannot start recording: {e}]\r\n");
+                terminal.feed(msg.as_bytes());
+            }
+        }
+    }
+
+    fn output(&self, data: &[u8]) {
+        self.write(|recorder| recorder.output(data));
+    }
+
+    fn resize(&self, cols: u32, rows: u32) {
+        self.write(|recorder| recorder.resize(cols, rows));
+    }
+
+    /// Stop recording when writing fails, e.g. on a full disk
+    fn write(&self, write: impl FnOnce(&mut CastRecorder) -> Result<(), AppError>) {
+        let mut slot = self.recorder.borrow_mut();
+        let Some(recorder) = slot.as_mut() else {
+            return;
+        };
+        if let Err(e) = write(recorder) {
+            log::error!("Recording to {} failed: {e}", recorder.path().display());
+            *slot = None;
+            drop(slot);
+            if let Some(terminal) = self.terminal.upgrade() {
+                let msg = format!("\r\n[Recording stopped: {e}]\r\n");
+                terminal.feed(msg.as_bytes());
+            }
+            if let Some(page) = self.page.borrow().upgrade() {
+                page.set_indicator_icon(gtk::gio::Icon::NONE);
+            }
+        }
+    }
+}
+
+/// Show or hide the tunnel side panel of a terminal tab.
+pub fn toggle_tunnel_panel(page: &adw::TabPage) {
+    if let Ok(split_view) = page.child().downcast::<adw::OverlaySplitView>() {
+        split_view.set_show_sidebar(!split_view.shows_sidebar());
+    }
+}
+
+/// Open an SFTP browser next to a terminal tab, on the terminal's connection
+/// while it is connected, so there is nothing to log in to.
+pub fn open_sftp_for_tab(tab_view: &adw::TabView, page: &adw::TabPage) {
+    if let Some((chain, cmd_tx)) = tab_session(page) {
+        let sftp_page = sftp_tab::create_sftp_tab(tab_view, chain, cmd_tx);
+        tab_view.reorder_page(&sftp_page, tab_view.page_position(page) + 1);
+        tab_view.set_selected_page(&sftp_page);
+    }
+}
+
+/// Drop commands queued while the tab was offline: keystrokes must not reach
+/// the next shell. Tabs that asked for a channel on this tab's connection
+/// get a connection of their own instead. Returns whether a Disconnect was
+/// queued.
+fn discard_pending_commands(
+    cmd_rx: &async_channel::Receiver<SshCommand>,
+    chain: &ConnectionChain,
+) -> bool {
+    let mut disconnect_requested = false;
+    while let Ok(cmd) = cmd_rx.try_recv() {
+        match cmd {
+            SshCommand::OpenShell { event_tx, cmd_rx } => {
+                session::spawn_session(chain.clone(), event_tx, cmd_rx, false);
+            }
+            SshCommand::OpenSftp { event_tx, cmd_rx } => {
+                sftp::spawn_sftp_session(chain.clone(), event_tx, cmd_rx);
+            }
+            SshCommand::Disconnect => disconnect_requested = true,
+            _ => {}
+        }
+    }
+    disconnect_requested
+}
+
 /// Disconnect the SSH session for a tab page.
 pub fn disconnect_tab(page: &adw::TabPage) {
     // Retrieve the stored cmd_tx and send Disconnect
@@ -3,7 +3,7 @@ use gtk::prelude::*;
 use libadwaita as adw;
 use adw::prelude::*;
 
-use crate::models::tunnel::TunnelConfig;
+use crate::models::tunnel::{TunnelConfig, TunnelType};
 
 /// Show a dialog to add/edit a tunnel configuration.
 pub fn show_tunnel_dialog(
@@ -19,7 +19,7 @@ pub fn show_tunnel_dialog(
             "Add Tunnel"
         })
         .content_width(400)
-        .content_height(350)
+        .content_height(420)
         .build();
 
     let toolbar_view = adw::ToolbarView::new();
@@ -33,13 +33,24 @@ pub fn show_tunnel_dialog(
     toolbar_view.add_top_bar(&header);
 
     let group = adw::PreferencesGroup::builder()
-        .title("Local Port Forward")
+        .title("Port Forward")
         .margin_start(16)
         .margin_end(16)
         .margin_top(8)
         .build();
 
     let name_row = adw::EntryRow::builder().title("Tunnel Name").build();
+
+    let type_row = adw::ComboRow::builder()
+        .title("Type")
+        .build();
+    let type_list = gtk::StringList::new(&[
+        "Local Forward (-L)",
+        "Remote Forward (-R)",
+        "Dynamic SOCKS Proxy (-D)",
+    ]);
+    type_row.set_model(Some(&type_list));
+
     let local_host_row = adw::EntryRow::builder().title("Local Host").build();
     local_host_row.set_text("127.0.0.1");
 
@@ -63,7 +74,29 @@ pub fn show_tunnel_dialog(
         .active(true)
         .build();
 
+    // Describe which side listens and which side connects for the selected type.
+    // A SOCKS proxy picks its destination per connection, so the remote rows are unused.
+    let group_for_type = group.clone();
+    let remote_host_for_type = remote_host_row.clone();
+    let remote_port_for_type = remote_port_row.clone();
+    let update_type_description = move |selected: u32| {
+        let description = match selected {
+            1 => "The server listens on the remote host and port and forwards connections to the local host and port.",
+            2 => "Runs a SOCKS4/SOCKS5 proxy on the local host and port. Each connection is tunnelled to the destination the client asks for.",
+            _ => "Listens on the local host and port and connects to the remote host and port from the server.",
+        };
+        group_for_type.set_description(Some(description));
+        remote_host_for_type.set_sensitive(selected != 2);
+        remote_port_for_type.set_sensitive(selected != 2);
+    };
+    update_type_description(type_row.selected());
+    let update_fn = update_type_description.clone();
+    type_row.connect_selected_notify(move |row| {
+        update_fn(row.selected());
+    });
+
     group.add(&name_row);
+    group.add(&type_row);
     group.add(&local_host_row);
     group.add(&local_port_row);
     group.add(&remote_host_row);
@@ -73,6 +106,11 @@ pub fn show_tunnel_dialog(
     // Populate existing
     let tunnel_id = if let Some(ref tc) = existing {
         name_row.set_text(&tc.name);
+        type_row.set_selected(match tc.tunnel_type {
+            TunnelType::LocalForward => 0,
+            TunnelType::RemoteForward => 1,
+            TunnelType::DynamicForward => 2,
+        });
         local_host_row.set_text(&tc.local_host);
         local_port_row.set_value(tc.local_port as f64);
         remote_host_row.set_text(&tc.remote_host);
@@ -107,10 +145,16 @@ pub fn show_tunnel_dialog(
             return;
         }
 
+        let tunnel_type = match type_row.selected() {
+            1 => TunnelType::RemoteForward,
+            2 => TunnelType::DynamicForward,
+            _ => TunnelType::LocalForward,
+        };
+
         let tc = TunnelConfig {
             id: tunnel_id,
             name,
-            tunnel_type: crate::models::tunnel::TunnelType::LocalForward,
+            tunnel_type,
             local_host: local_host_row.text().to_string(),
             local_port: local_port_row.value() as u16,
             remote_host: remote_host_row.text().to_string(),
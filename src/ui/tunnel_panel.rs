@@ -0,0 +1,270 @@
+use gtk4 as gtk;
+use gtk::prelude::*;
+use gtk::glib;
+use libadwaita as adw;
+use adw::prelude::*;
+use uuid::Uuid;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::app::SshCommand;
+use crate::models::tunnel::TunnelConfig;
+use crate::ssh::tunnel::TunnelStatsSnapshot;
+use crate::ui::sftp_tab::format_size;
+use crate::ui::tunnel_dialog;
+
+#[derive(Debug, Clone, PartialEq)]
+enum TunnelState {
+    Stopped,
+    Starting,
+    Running(TunnelStatsSnapshot),
+    Stopping,
+    Failed(String),
+}
+
+struct TunnelRow {
+    config: TunnelConfig,
+    row: adw::ActionRow,
+    toggle_btn: gtk::Button,
+    state: TunnelState,
+}
+
+/// Side panel of a terminal tab for starting, stopping and watching the
+/// tunnels of a live session.
+#[derive(Clone)]
+pub struct TunnelPanel {
+    pub container: gtk::Box,
+    listbox: gtk::ListBox,
+    rows: Rc<RefCell<HashMap<Uuid, TunnelRow>>>,
+    cmd_tx: Rc<async_channel::Sender<SshCommand>>,
+}
+
+pub fn build_tunnel_panel(
+    tunnels: &[TunnelConfig],
+    cmd_tx: Rc<async_channel::Sender<SshCommand>>,
+) -> TunnelPanel {
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    container.add_css_class("tunnel-panel");
+    container.set_width_request(300);
+    container.set_margin_start(8);
+    container.set_margin_end(8);
+    container.set_margin_top(8);
+    container.set_margin_bottom(8);
+
+    let header = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let title_label = gtk::Label::builder()
+        .label("Tunnels")
+        .css_classes(["title-4"])
+        .hexpand(true)
+        .halign(gtk::Align::Start)
+        .build();
+    let add_btn = gtk::Button::builder()
+        .icon_name("list-add-symbolic")
+        .tooltip_text("Add a tunnel to this session")
+        .css_classes(["flat"])
+        .build();
+    header.append(&title_label);
+    header.append(&add_btn);
+    container.append(&header);
+
+    let hint_label = gtk::Label::builder()
+        .label("Tunnels added here only last for this session.")
+        .css_classes(["dim-label", "caption"])
+        .halign(gtk::Align::Start)
+        .wrap(true)
+        .build();
+    container.append(&hint_label);
+
+    let listbox = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .valign(gtk::Align::Start)
+        .build();
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&listbox)
+        .vexpand(true)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .build();
+    container.append(&scrolled);
+
+    let panel = TunnelPanel {
+        container,
+        listbox,
+        rows: Rc::new(RefCell::new(HashMap::new())),
+        cmd_tx,
+    };
+
+    for tc in tunnels {
+        let state = if tc.enabled {
+            TunnelState::Starting
+        } else {
+            TunnelState::Stopped
+        };
+        panel.add_row(tc.clone(), state);
+    }
+
+    let panel_for_add = panel.clone();
+    add_btn.connect_clicked(move |btn| {
+        let Some(window) = btn.root().and_downcast::<adw::ApplicationWindow>() else {
+            return;
+        };
+        let panel = panel_for_add.clone();
+        tunnel_dialog::show_tunnel_dialog(&window, None, move |tc: TunnelConfig| {
+            if tc.enabled {
+                panel.add_row(tc.clone(), TunnelState::Starting);
+                panel.send(SshCommand::StartTunnel(tc));
+            } else {
+                panel.add_row(tc, TunnelState::Stopped);
+            }
+        });
+    });
+
+    panel
+}
+
+impl TunnelPanel {
+    fn add_row(&self, config: TunnelConfig, state: TunnelState) {
+        let row = adw::ActionRow::builder()
+            .title(&config.name)
+            .subtitle_lines(2)
+            .build();
+
+        let toggle_btn = gtk::Button::builder()
+            .valign(gtk::Align::Center)
+            .css_classes(["flat"])
+            .build();
+        row.add_suffix(&toggle_btn);
+
+        let id = config.id;
+        let panel = self.clone();
+        toggle_btn.connect_clicked(move |_| {
+            panel.toggle(&id);
+        });
+
+        // Replace an existing row for the same tunnel
+        if let Some(old) = self.rows.borrow_mut().remove(&id) {
+            self.listbox.remove(&old.row);
+        }
+
+        self.listbox.append(&row);
+        let tunnel_row = TunnelRow {
+            config,
+            row,
+            toggle_btn,
+            state,
+        };
+        refresh_row(&tunnel_row);
+        self.rows.borrow_mut().insert(id, tunnel_row);
+    }
+
+    fn toggle(&self, id: &Uuid) {
+        let command = {
+            let mut rows = self.rows.borrow_mut();
+            let Some(tunnel_row) = rows.get_mut(id) else {
+                return;
+            };
+            let command = match tunnel_row.state {
+                TunnelState::Running(_) | TunnelState::Starting => {
+                    tunnel_row.state = TunnelState::Stopping;
+                    SshCommand::StopTunnel(*id)
+                }
+                TunnelState::Stopped | TunnelState::Failed(_) => {
+                    tunnel_row.state = TunnelState::Starting;
+                    SshCommand::StartTunnel(tunnel_row.config.clone())
+                }
+                TunnelState::Stopping => return,
+            };
+            refresh_row(tunnel_row);
+            command
+        };
+        self.send(command);
+    }
+
+    fn send(&self, command: SshCommand) {
+        let tx = (*self.cmd_tx).clone();
+        glib::spawn_future_local(async move {
+            let _ = tx.send(command).await;
+        });
+    }
+
+    fn set_state(&self, id: &Uuid, state: TunnelState) {
+        if let Some(tunnel_row) = self.rows.borrow_mut().get_mut(id) {
+            tunnel_row.state = state;
+            refresh_row(tunnel_row);
+        }
+    }
+
+    pub fn tunnel_name(&self, id: &Uuid) -> Option<String> {
+        self.rows.borrow().get(id).map(|r| r.config.name.clone())
+    }
+
+    pub fn set_running(&self, id: &Uuid) {
+        self.set_state(id, TunnelState::Running(TunnelStatsSnapshot::default()));
+    }
+
+    pub fn set_stopped(&self, id: &Uuid) {
+        self.set_state(id, TunnelState::Stopped);
+    }
+
+    pub fn set_failed(&self, id: &Uuid, error: &str) {
+        self.set_state(id, TunnelState::Failed(error.to_string()));
+    }
+
+    /// Mark every tunnel as stopped, e.g. after the session disconnected.
+    pub fn set_all_stopped(&self) {
+        for tunnel_row in self.rows.borrow_mut().values_mut() {
+            tunnel_row.state = TunnelState::Stopped;
+            refresh_row(tunnel_row);
+        }
+    }
+
+    pub fn update_stats(&self, snapshots: &[(Uuid, TunnelStatsSnapshot)]) {
+        let mut rows = self.rows.borrow_mut();
+        for (id, snapshot) in snapshots {
+            if let Some(tunnel_row) = rows.get_mut(id) {
+                if matches!(tunnel_row.state, TunnelState::Running(_)) {
+                    tunnel_row.state = TunnelState::Running(*snapshot);
+                    refresh_row(tunnel_row);
+                }
+            }
+        }
+    }
+}
+
+fn refresh_row(tunnel_row: &TunnelRow) {
+    let status = match &tunnel_row.state {
+        TunnelState::Stopped => "Stopped".to_string(),
+        TunnelState::Starting => "Starting...".to_string(),
+        TunnelState::Stopping => "Stopping...".to_string(),
+        TunnelState::Failed(error) => format!("Failed: {error}"),
+        TunnelState::Running(stats) => format!(
+            "Active · {} open, {} total · ↑ {} ↓ {}",
+            stats.active_connections,
+            stats.total_connections,
+            format_size(stats.bytes_sent),
+            format_size(stats.bytes_received),
+        ),
+    };
+    tunnel_row.row.set_subtitle(&format!(
+        "{}\n{}",
+        glib::markup_escape_text(&tunnel_row.config.summary()),
+        glib::markup_escape_text(&status)
+    ));
+
+    let (icon, tooltip) = match tunnel_row.state {
+        TunnelState::Running(_) | TunnelState::Starting | TunnelState::Stopping => {
+            ("media-playback-stop-symbolic", "Stop tunnel")
+        }
+        TunnelState::Stopped | TunnelState::Failed(_) => {
+            ("media-playback-start-symbolic", "Start tunnel")
+        }
+    };
+    tunnel_row.toggle_btn.set_icon_name(icon);
+    tunnel_row.toggle_btn.set_tooltip_text(Some(tooltip));
+    tunnel_row
+        .toggle_btn
+        .set_sensitive(tunnel_row.state != TunnelState::Stopping);
+}
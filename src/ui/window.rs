@@ -4,9 +4,14 @@ use gtk::glib;
 use libadwaita as adw;
 use adw::prelude::*;
 
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use crate::app::SharedState;
+use crate::ui::auth_prompt_dialog;
 use crate::ui::connection_list;
 use crate::ui::key_manager_dialog;
+use crate::ui::player_tab;
 use crate::ui::preferences_dialog;
 use crate::ui::terminal_tab;
 
@@ -43,6 +48,7 @@ pub fn build_window(app: &adw::Application, state: SharedState) -> adw::Applicat
 
     let menu = gtk::gio::Menu::new();
     menu.append(Some("SSH Key Manager"), Some("app.key-manager"));
+    menu.append(Some("Play Recording…"), Some("app.play-recording"));
     menu.append(Some("Preferences"), Some("app.preferences"));
     menu.append(Some("About"), Some("app.about"));
 
@@ -50,6 +56,24 @@ pub fn build_window(app: &adw::Application, state: SharedState) -> adw::Applicat
     menu_btn.set_popover(Some(&popover));
     header_bar.pack_end(&menu_btn);
 
+    let tunnels_btn = gtk::Button::builder()
+        .icon_name("network-transmit-receive-symbolic")
+        .tooltip_text("Tunnels (Ctrl+Shift+T)")
+        .build();
+    header_bar.pack_end(&tunnels_btn);
+
+    let sftp_btn = gtk::Button::builder()
+        .icon_name("folder-remote-symbolic")
+        .tooltip_text("Open SFTP for This Terminal")
+        .build();
+    header_bar.pack_end(&sftp_btn);
+
+    let record_btn = gtk::Button::builder()
+        .icon_name("media-record-symbolic")
+        .tooltip_text("Start or Stop Recording (Ctrl+Shift+R)")
+        .build();
+    header_bar.pack_end(&record_btn);
+
     content_box.append(&header_bar);
     content_box.append(&tab_bar);
     content_box.append(&tab_view);
@@ -107,6 +131,80 @@ pub fn build_window(app: &adw::Application, state: SharedState) -> adw::Applicat
         }
     });
 
+    // Toggle the tunnel panel of the selected terminal tab
+    let tab_view_tunnels = tab_view.clone();
+    tunnels_btn.connect_clicked(move |_| {
+        if let Some(page) = tab_view_tunnels.selected_page() {
+            terminal_tab::toggle_tunnel_panel(&page);
+        }
+    });
+
+    // Record the selected terminal tab
+    let tab_view_record = tab_view.clone();
+    record_btn.connect_clicked(move |_| {
+        if let Some(page) = tab_view_record.selected_page() {
+            terminal_tab::toggle_recording(&page);
+        }
+    });
+
+    // Browse the files of the selected terminal's host
+    let tab_view_sftp = tab_view.clone();
+    sftp_btn.connect_clicked(move |_| {
+        if let Some(page) = tab_view_sftp.selected_page() {
+            terminal_tab::open_sftp_for_tab(&tab_view_sftp, &page);
+        }
+    });
+
+    // Tab context menu. Its actions apply to the tab it was opened on,
+    // which need not be the selected one.
+    let tab_menu = gtk::gio::Menu::new();
+    tab_menu.append(Some("Duplicate Tab"), Some("win.duplicate-tab"));
+    tab_menu.append(Some("Open SFTP Here"), Some("win.open-sftp-here"));
+    tab_view.set_menu_model(Some(&tab_menu));
+
+    let menu_page: Rc<RefCell<Option<adw::TabPage>>> = Rc::default();
+    let duplicate_action = gtk::gio::SimpleAction::new("duplicate-tab", None);
+    let sftp_here_action = gtk::gio::SimpleAction::new("open-sftp-here", None);
+
+    let menu_page_for_setup = menu_page.clone();
+    let duplicate_for_setup = duplicate_action.clone();
+    let sftp_here_for_setup = sftp_here_action.clone();
+    tab_view.connect_setup_menu(move |_, page| {
+        // Only terminal tabs have a connection to share
+        if let Some(page) = page {
+            let is_terminal = terminal_tab::is_terminal_tab(page);
+            duplicate_for_setup.set_enabled(is_terminal);
+            sftp_here_for_setup.set_enabled(is_terminal);
+        }
+        menu_page_for_setup.replace(page.cloned());
+    });
+
+    let menu_page_for_duplicate = menu_page.clone();
+    let tab_view_duplicate = tab_view.clone();
+    let state_for_duplicate = state.clone();
+    duplicate_action.connect_activate(move |_, _| {
+        let page = menu_page_for_duplicate
+            .borrow()
+            .clone()
+            .or_else(|| tab_view_duplicate.selected_page());
+        if let Some(page) = page {
+            terminal_tab::duplicate_tab(&tab_view_duplicate, &page, &state_for_duplicate);
+        }
+    });
+    window.add_action(&duplicate_action);
+
+    let tab_view_sftp_here = tab_view.clone();
+    sftp_here_action.connect_activate(move |_, _| {
+        let page = menu_page
+            .borrow()
+            .clone()
+            .or_else(|| tab_view_sftp_here.selected_page());
+        if let Some(page) = page {
+            terminal_tab::open_sftp_for_tab(&tab_view_sftp_here, &page);
+        }
+    });
+    window.add_action(&sftp_here_action);
+
     // Tab close handler: disconnect SSH session
     tab_view.connect_close_page(|tab_view, page| {
         terminal_tab::disconnect_tab(page);
@@ -142,6 +240,49 @@ pub fn build_window(app: &adw::Application, state: SharedState) -> adw::Applicat
     });
     app.add_action(&preferences_action);
 
+    let window_for_play = window.clone();
+    let tab_view_for_play = tab_view.clone();
+    let state_for_play = state.clone();
+    let play_action = gtk::gio::SimpleAction::new("play-recording", None);
+    play_action.connect_activate(move |_, _| {
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some("Terminal recordings"));
+        filter.add_pattern("*.cast");
+        let filters = gtk::gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&filter);
+
+        let file_dialog = gtk::FileDialog::builder()
+            .title("Play Recording")
+            .filters(&filters)
+            .initial_folder(&gtk::gio::File::for_path(crate::config::recordings_dir()))
+            .build();
+        let window = window_for_play.clone();
+        let tab_view = tab_view_for_play.clone();
+        let state = state_for_play.clone();
+        file_dialog.open(
+            Some(&window_for_play),
+            gtk::gio::Cancellable::NONE,
+            move |result| {
+                let Some(path) = result.ok().and_then(|file| file.path()) else {
+                    return;
+                };
+                match player_tab::open_player_tab(&tab_view, &path, &state) {
+                    Ok(page) => tab_view.set_selected_page(&page),
+                    Err(e) => {
+                        log::error!("Failed to open recording {}: {e}", path.display());
+                        let alert = adw::AlertDialog::builder()
+                            .heading("Cannot Play Recording")
+                            .body(e.to_string())
+                            .build();
+                        alert.add_response("ok", "OK");
+                        alert.present(Some(&window));
+                    }
+                }
+            },
+        );
+    });
+    app.add_action(&play_action);
+
     let about_action = gtk::gio::SimpleAction::new("about", None);
     let window_for_about = window.clone();
     about_action.connect_activate(move |_, _| {
@@ -156,6 +297,16 @@ pub fn build_window(app: &adw::Application, state: SharedState) -> adw::Applicat
     });
     app.add_action(&about_action);
 
+    // Passphrase prompts from the built-in SSH agent
+    let window_for_agent = window.clone();
+    let key_unlock_rx = state.key_unlock_rx.clone();
+    glib::spawn_future_local(async move {
+        while let Ok(prompt) = key_unlock_rx.recv().await {
+            auth_prompt_dialog::prompt_key_unlock(&window_for_agent, prompt);
+        }
+    });
+    crate::keys::agent::apply_settings(&state);
+
     window
 }
 